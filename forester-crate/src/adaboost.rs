@@ -0,0 +1,378 @@
+//! AdaBoost for classification.
+//!
+//! Implements the multi-class SAMME algorithm (Zhu et al., 2009): shallow trees are fit to
+//! reweighted training data, where the weights of misclassified samples are increased after each
+//! round. The trees are combined in a weighted vote.
+
+use std::error;
+use std::f64;
+use std::fmt;
+
+use num_traits::Bounded;
+use rand::{thread_rng, Rng, SeedableRng};
+use rand::prng::ChaChaRng;
+
+use categorical::{Categorical, CatCount, Category, GenericCatCounter};
+use criterion::GiniCriterion;
+use data::{feature_bounds_of, first_inner, SampleDescription, TrainingData, WrappedSample};
+use dtree::{DeterministicTree, DeterministicTreeBuilder};
+use split::SplitFinder;
+
+/// A training sample together with its current boosting weight.
+///
+/// The boosted trees are fit to data sets of these. Their leaves predict the class id with the
+/// largest total weight.
+#[derive(Debug, Clone)]
+pub struct Weighted<S> {
+    sample: S,
+    weight: f64,
+}
+
+impl<S> SampleDescription for Weighted<S>
+    where S: SampleDescription,
+          S::Target: Categorical,
+{
+    type ThetaSplit = S::ThetaSplit;
+    type ThetaLeaf = usize;
+    type Feature = S::Feature;
+    type Target = S::Target;
+    type Prediction = usize;
+
+    fn target(&self) -> S::Target {
+        self.sample.target()
+    }
+
+    fn sample_as_split_feature(&self, theta: &Self::ThetaSplit) -> Self::Feature {
+        self.sample.sample_as_split_feature(theta)
+    }
+
+    fn sample_as_category(&self, theta: &Self::ThetaSplit) -> Option<Category> {
+        self.sample.sample_as_category(theta)
+    }
+
+    fn sample_predict(&self, w: &usize) -> usize {
+        *w
+    }
+
+    fn weight(&self) -> f64 {
+        self.weight
+    }
+}
+
+impl<S: Clone> WrappedSample for Weighted<S> {
+    type Inner = S;
+
+    fn inner(&self) -> &S {
+        &self.sample
+    }
+}
+
+/// Split features are generated by the wrapped samples' data set.
+impl<S> TrainingData<Weighted<S>> for [Weighted<S>]
+    where S: SampleDescription + Clone,
+          S::Feature: Bounded + Clone,
+          S::Target: Categorical,
+          [S]: TrainingData<S>,
+{
+    type Criterion = GiniCriterion;
+
+    fn n_samples(&self) -> usize {
+        self.len()
+    }
+
+    fn gen_split_feature<R: Rng>(&self, rng: &mut R) -> S::ThetaSplit {
+        first_inner(self).gen_split_feature(rng)
+    }
+
+    fn all_split_features(&self) -> Option<Box<Iterator<Item=S::ThetaSplit>>> {
+        first_inner(self).all_split_features()
+    }
+
+    fn train_leaf_predictor(&self) -> usize {
+        let mut counts = GenericCatCounter::new();
+        for s in self {
+            counts.add_weighted(s.target(), s.weight);
+        }
+        counts.most_frequent()
+    }
+
+    fn feature_bounds(&self, theta: &S::ThetaSplit) -> (S::Feature, S::Feature) {
+        feature_bounds_of(self, theta)
+    }
+}
+
+/// Errors that can occur while fitting a `WeightedVoteForest`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The weighted error rate of the first tree was no better than random guessing, so the
+    /// ensemble would be empty
+    NoBetterThanChance(f64),
+    /// The training data has fewer than two classes (the number found is given), so the tree
+    /// weights are undefined
+    TooFewClasses(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::NoBetterThanChance(error) =>
+                write!(f, "first tree is no better than chance (error rate {})", error),
+            Error::TooFewClasses(n_classes) =>
+                write!(f, "boosting needs at least two classes, found {}", n_classes),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// An ensemble of classification trees that vote with per-tree weights.
+pub struct WeightedVoteForest<S>
+    where S: SampleDescription,
+          S::Target: Categorical,
+{
+    n_classes: usize,
+    estimators: Vec<DeterministicTree<Weighted<S>>>,
+    estimator_weights: Vec<f64>,
+}
+
+impl<S> WeightedVoteForest<S>
+    where S: SampleDescription,
+          S::Target: Categorical,
+{
+    /// Number of trees in the ensemble
+    pub fn n_estimators(&self) -> usize {
+        self.estimators.len()
+    }
+
+    /// Voting weight of each tree
+    pub fn estimator_weights(&self) -> &[f64] {
+        &self.estimator_weights
+    }
+
+    /// Sum of the weights of the trees voting for each class
+    pub fn decision_function<TestingSample>(&self, sample: &TestingSample) -> Vec<f64>
+        where TestingSample: SampleDescription<ThetaSplit=S::ThetaSplit, Feature=S::Feature> + ?Sized,
+    {
+        let mut votes = vec![0.0; self.n_classes];
+        for (tree, w) in self.estimators.iter().zip(&self.estimator_weights) {
+            votes[*tree.predict_leaf(sample)] += w;
+        }
+        votes
+    }
+
+    /// Predict the class with the largest weighted vote
+    pub fn predict<TestingSample>(&self, sample: &TestingSample) -> S::Target
+        where TestingSample: SampleDescription<ThetaSplit=S::ThetaSplit, Feature=S::Feature> + ?Sized,
+    {
+        let mut counts = GenericCatCounter::new();
+        for (c, w) in self.decision_function(sample).into_iter().enumerate() {
+            counts.add_weighted(c, w);
+        }
+        counts.most_frequent()
+    }
+}
+
+/// Fit a `WeightedVoteForest` with the SAMME algorithm.
+pub struct AdaBoostBuilder<SF, S>
+    where SF: SplitFinder,
+          S: SampleDescription,
+          S::Target: Categorical,
+{
+    n_estimators: usize,
+    learning_rate: f64,
+    seed: Option<u64>,
+    tree_builder: DeterministicTreeBuilder<SF, Weighted<S>>,
+}
+
+impl<SF, S> AdaBoostBuilder<SF, S>
+    where SF: SplitFinder,
+          S: SampleDescription + Clone,
+          S::Feature: Bounded + Clone,
+          S::Target: Categorical,
+          [S]: TrainingData<S>,
+{
+    pub fn new(n_estimators: usize, tree_builder: DeterministicTreeBuilder<SF, Weighted<S>>) -> Self {
+        AdaBoostBuilder {
+            n_estimators,
+            learning_rate: 1.0,
+            seed: None,
+            tree_builder,
+        }
+    }
+
+    /// Shrink the voting weight of each tree by `learning_rate`.
+    pub fn with_learning_rate(mut self, learning_rate: f64) -> Self {
+        self.learning_rate = learning_rate;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn fit(&self, data: &[S]) -> Result<WeightedVoteForest<S>, Error> {
+        match self.seed {
+            None => self.fit_with_rng(data, &mut thread_rng()),
+            Some(seed) => self.fit_with_rng(data, &mut ChaChaRng::seed_from_u64(seed)),
+        }
+    }
+
+    /// Fit the ensemble, drawing all randomness from `rng`. The builder's seed is ignored.
+    ///
+    /// Boosting stops early if a tree fits the weighted data perfectly, or if a tree is no better
+    /// than random guessing (in which case it is discarded). Fails if that happens to the first
+    /// tree, or if the data has fewer than two classes.
+    pub fn fit_with_rng<R: Rng>(&self, data: &[S], rng: &mut R) -> Result<WeightedVoteForest<S>, Error> {
+        let n = data.len();
+        let n_classes = data.iter()
+            .map(|s| {
+                let c = s.target();
+                c.n_categories().unwrap_or_else(|| c.as_usize() + 1)
+            })
+            .max()
+            .unwrap_or(0);
+
+        if n_classes < 2 {
+            return Err(Error::TooFewClasses(n_classes))
+        }
+
+        let mut weights = vec![1.0 / n as f64; n];
+        let mut estimators = Vec::with_capacity(self.n_estimators);
+        let mut estimator_weights = Vec::with_capacity(self.n_estimators);
+
+        for _ in 0..self.n_estimators {
+            let mut weighted: Vec<_> = data.iter().zip(&weights)
+                .map(|(s, &weight)| Weighted { sample: s.clone(), weight })
+                .collect();
+
+            let tree = self.tree_builder.fit_with_rng(&mut weighted[..], rng);
+
+            let miss: Vec<bool> = data.iter()
+                .map(|s| *tree.predict_leaf(s) != s.target().as_usize())
+                .collect();
+
+            let error = weights.iter().zip(&miss)
+                .filter(|&(_, &m)| m)
+                .map(|(w, _)| w)
+                .sum::<f64>();
+
+            if error <= 0.0 {
+                estimators.push(tree);
+                estimator_weights.push(1.0);
+                break
+            }
+
+            if error >= 1.0 - 1.0 / n_classes as f64 {
+                if estimators.is_empty() {
+                    return Err(Error::NoBetterThanChance(error))
+                }
+                break
+            }
+
+            let alpha = self.learning_rate * (((1.0 - error) / error).ln() + (n_classes as f64 - 1.0).ln());
+
+            for (w, &m) in weights.iter_mut().zip(&miss) {
+                if m {
+                    *w *= alpha.exp();
+                }
+            }
+            let total: f64 = weights.iter().sum();
+            for w in weights.iter_mut() {
+                *w /= total;
+            }
+
+            estimators.push(tree);
+            estimator_weights.push(alpha);
+        }
+
+        Ok(WeightedVoteForest {
+            n_classes,
+            estimators,
+            estimator_weights,
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use split::BestSplit;
+    use testdata::ClassSample;
+
+    #[test]
+    fn weighted_gini() {
+        use criterion::SplitCriterion;
+
+        let x = [[0.0]];
+        let mut crit = <GiniCriterion as SplitCriterion<u8>>::new();
+        crit.add_sample(&Weighted { sample: ClassSample::new(&x[0], 0u8), weight: 3.0 });
+        crit.add_sample(&Weighted { sample: ClassSample::new(&x[0], 1u8), weight: 1.0 });
+        assert_eq!(SplitCriterion::<u8>::get(&crit), 0.375);
+        assert_eq!(SplitCriterion::<u8>::get_weighted(&crit), 1.5);
+    }
+
+    #[test]
+    fn adaboost() {
+        // a single stump can't separate three classes in four intervals
+        let x: Vec<_> = (0..40).map(|i| [i as f64]).collect();
+        let data: Vec<_> = x.iter()
+            .map(|xi| ClassSample::new(xi, match xi[0] as usize {
+                0..=9 => 0u8,
+                20..=29 => 2,
+                _ => 1,
+            }))
+            .collect();
+
+        let stump = DeterministicTreeBuilder::new(2, BestSplit::new()).with_max_depth(1).fit(&mut data.clone()[..]);
+        let stump_correct = data.iter().filter(|s| stump.predict(*s) == s.y).count();
+        assert!(stump_correct <= 30);
+
+        let model = AdaBoostBuilder::new(
+            50,
+            DeterministicTreeBuilder::new(2, BestSplit::new()).with_max_depth(1)
+        ).fit(&data).unwrap();
+
+        assert!(model.n_estimators() > 1);
+        assert!(model.estimator_weights().iter().all(|&w| w > 0.0));
+        for sample in &data {
+            assert_eq!(model.predict(sample), sample.y);
+            assert_eq!(model.decision_function(sample).len(), 3);
+        }
+
+        // a tree that fits the data perfectly ends boosting
+        let model = AdaBoostBuilder::new(
+            50,
+            DeterministicTreeBuilder::new(2, BestSplit::new())
+        ).fit(&data).unwrap();
+        assert_eq!(model.n_estimators(), 1);
+    }
+
+    #[test]
+    fn no_better_than_chance() {
+        // identical features can't be split, so the first tree guesses
+        let x = [[0.0]];
+        let data: Vec<_> = (0..10).map(|i| ClassSample::new(&x[0], (i % 2) as u8)).collect();
+
+        let result = AdaBoostBuilder::new(
+            10,
+            DeterministicTreeBuilder::new(2, BestSplit::new())
+        ).fit(&data);
+        match result {
+            Err(Error::NoBetterThanChance(error)) => assert!((error - 0.5).abs() < 1e-12),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(model) => panic!("unexpected ensemble of {} trees", model.n_estimators()),
+        }
+    }
+
+    #[test]
+    fn too_few_classes() {
+        let builder = AdaBoostBuilder::new(10, DeterministicTreeBuilder::new(2, BestSplit::new()));
+
+        let x = [[0.0], [1.0]];
+        let data: Vec<_> = x.iter().map(|xi| ClassSample::new(xi, 0u8)).collect();
+        assert_eq!(builder.fit(&data).err(), Some(Error::TooFewClasses(1)));
+        assert_eq!(builder.fit(&[]).err(), Some(Error::TooFewClasses(0)));
+    }
+}
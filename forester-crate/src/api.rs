@@ -4,7 +4,7 @@ use std::marker::PhantomData;
 
 use num_traits::Bounded;
 
-use rand::thread_rng;
+use rand::Rng;
 use rand::distributions::range::SampleRange;
 
 use vec2d::Vec2D;
@@ -13,30 +13,72 @@ use vec2d::Vec2D;
 pub mod extra_trees_regressor {
     use super::*;
     use std::f64;
-    use rand::Rng;
     use criterion::VarianceCriterion;
-    use data::{SampleDescription, TrainingData};
+    use data::{feature_bounds_of, SampleDescription, TrainingData};
     use dforest::{DeterministicForest, DeterministicForestBuilder};
     use dtree::DeterministicTreeBuilder;
-    use iter_mean::IterMean;
+    use iter_mean::WeightedMean;
+    use order_stats::WeightedOrderStatistics;
+    use quantile::QuantileForest;
     use split::BestRandomSplit;
     use split_between::SplitBetween;
 
+    /// Statistic of the training targets that a leaf predicts
+    pub trait LeafStatistic: Clone {
+        /// Compute the statistic from `(weight, target)` pairs
+        fn from_targets<I: Iterator<Item=(f64, f64)>>(targets: I) -> f64;
+    }
+
+    /// Leaves predict the weighted mean of their training targets
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    pub struct Mean;
+
+    impl LeafStatistic for Mean {
+        fn from_targets<I: Iterator<Item=(f64, f64)>>(targets: I) -> f64 {
+            f64::weighted_mean(targets)
+        }
+    }
+
+    /// Leaves predict the weighted median of their training targets, which is robust to outliers
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    pub struct Median;
+
+    impl LeafStatistic for Median {
+        fn from_targets<I: Iterator<Item=(f64, f64)>>(targets: I) -> f64 {
+            let mut stats = WeightedOrderStatistics::new();
+            for (w, y) in targets {
+                stats.add(y, w);
+            }
+            stats.median().unwrap_or(f64::NAN)
+        }
+    }
+
+    /// Sample for training and prediction; `L` selects the statistic the leaves predict.
     #[derive(Debug, Clone)]
-    pub struct Sample<'a, X: 'a, Y> {
+    pub struct Sample<'a, X: 'a, Y, L = Mean> {
         x: &'a[X],
         y: Y,
+        weight: f64,
+        _leaf: PhantomData<L>,
     }
 
     impl<'a, X: 'a, Y> Sample<'a, X, Y> {
         pub fn new(x: &'a[X], y: Y) -> Self {
-            Sample { x, y }
+            Sample { x, y, weight: 1.0, _leaf: PhantomData }
         }
     }
 
-    impl<'a, X, Y> SampleDescription for Sample<'a, X, Y>
+    impl<'a, X: 'a, Y, L> Sample<'a, X, Y, L> {
+        pub fn with_weight(mut self, weight: f64) -> Self {
+            self.weight = weight;
+            self
+        }
+    }
+
+    impl<'a, X, Y, L> SampleDescription for Sample<'a, X, Y, L>
         where X: Clone + PartialOrd + SampleRange + SplitBetween,
-              Y: Clone
+              Y: Clone,
+              L: Clone
     {
         type ThetaSplit = usize;
         type ThetaLeaf = f64;
@@ -55,49 +97,53 @@ pub mod extra_trees_regressor {
         fn sample_predict(&self, w: &Self::ThetaLeaf) -> Self::Prediction {
             *w
         }
+
+        fn weight(&self) -> f64 {
+            self.weight
+        }
     }
 
-    impl<'a, X> TrainingData<Sample<'a, X, f64>> for [Sample<'a, X, f64>]
-        where X: Clone + PartialOrd + SampleRange + Bounded + SplitBetween
+    impl<'a, X, L> TrainingData<Sample<'a, X, f64, L>> for [Sample<'a, X, f64, L>]
+        where X: Clone + PartialOrd + SampleRange + Bounded + SplitBetween,
+              L: LeafStatistic
     {
         type Criterion = VarianceCriterion;
         fn n_samples(&self) -> usize {
             self.len()
         }
 
-        fn gen_split_feature(&self) -> usize {
+        fn gen_split_feature<R: Rng>(&self, rng: &mut R) -> usize {
             let n = self[0].x.len();
-            thread_rng().gen_range(0, n)
+            rng.gen_range(0, n)
         }
 
         fn train_leaf_predictor(&self) -> f64 {
-            f64::mean(self.iter().map(|sample| &sample.y))
+            L::from_targets(self.iter().map(|sample| (sample.weight, sample.y)))
         }
 
         fn feature_bounds(&self, theta: &usize) -> (X, X) {
-            self.iter()
-                .map(|sample| sample.sample_as_split_feature(theta))
-                .fold((X::max_value(), X::min_value()),
-                      |(min, max), x| {
-                          (if x < min {x.clone()} else {min},
-                           if x > max {x} else {max})
-                      })
+            feature_bounds_of(self, theta)
         }
     }
 
-    pub struct ExtraTreesRegressor {
+    pub struct ExtraTreesRegressor<L = Mean> {
         n_estimators: usize,
         n_splits: usize,
         min_samples_split: usize,
         max_depth: Option<usize>,
         bootstrap: Option<usize>,
+        oob_score: bool,
+        seed: Option<u64>,
+        _leaf: PhantomData<L>,
     }
 
     impl ExtraTreesRegressor {
         pub fn new() -> Self {
             Self::default()
         }
+    }
 
+    impl<L: LeafStatistic> ExtraTreesRegressor<L> {
         pub fn with_n_estimators(mut self, n: usize) -> Self {
             self.n_estimators = n;
             self
@@ -118,34 +164,100 @@ pub mod extra_trees_regressor {
             self
         }
 
-        pub fn with_bootstrap(mut self, d: usize) -> Self {
-            self.max_depth = Some(d);
+        pub fn with_bootstrap(mut self, n: usize) -> Self {
+            self.bootstrap = Some(n);
             self
         }
 
-        pub fn fit<'a, 'b, T>(&'a self, x: &'b Vec2D<T>, y: &'b Vec<f64>) -> DeterministicForest<Sample<'b, T, f64>>
+        /// Compute out-of-bag predictions; requires `with_bootstrap`.
+        pub fn with_oob_score(mut self) -> Self {
+            self.oob_score = true;
+            self
+        }
+
+        /// Make leaves predict another statistic of their training targets, such as the `Median`.
+        pub fn with_leaf_statistic<M: LeafStatistic>(self) -> ExtraTreesRegressor<M> {
+            ExtraTreesRegressor {
+                n_estimators: self.n_estimators,
+                n_splits: self.n_splits,
+                min_samples_split: self.min_samples_split,
+                max_depth: self.max_depth,
+                bootstrap: self.bootstrap,
+                oob_score: self.oob_score,
+                seed: self.seed,
+                _leaf: PhantomData,
+            }
+        }
+
+        pub fn with_seed(mut self, seed: u64) -> Self {
+            self.seed = Some(seed);
+            self
+        }
+
+        pub fn fit<'a, 'b, T>(&'a self, x: &'b Vec2D<T>, y: &'b Vec<f64>) -> DeterministicForest<Sample<'b, T, f64, L>>
             where T: Clone + cmp::PartialOrd + SampleRange + Bounded + SplitBetween,
         {
-            let mut data: Vec<Sample<T, f64>> = x.iter()
-                .zip(y.iter())
-                .map(|(xi, yi)| Sample{x: xi, y: *yi})
-                .collect();
+            self.fit_weighted(x, y, &vec![1.0; y.len()])
+        }
+
+        /// Fit the forest with a weight for each sample.
+        pub fn fit_weighted<'b, T>(&self, x: &'b Vec2D<T>, y: &'b Vec<f64>, sample_weight: &[f64])
+            -> DeterministicForest<Sample<'b, T, f64, L>>
+            where T: Clone + cmp::PartialOrd + SampleRange + Bounded + SplitBetween,
+        {
+            assert_eq!(y.len(), sample_weight.len());
+            let mut data = Self::training_data(x, y, sample_weight);
 
-            DeterministicForestBuilder::new(
+            let mut builder = DeterministicForestBuilder::new(
                 self.n_estimators,
                 DeterministicTreeBuilder {
                     _p: PhantomData,
                     min_samples_split: self.min_samples_split,
                     min_samples_leaf: 1,
+                    min_samples_weighted: false,
                     split_finder: BestRandomSplit::new(self.n_splits),
                     max_depth: self.max_depth,
                     bootstrap: self.bootstrap,
+                    seed: None,
                 }
-            ).fit(&mut data[..])
+            );
+
+            if let Some(seed) = self.seed {
+                builder = builder.with_seed(seed);
+            }
+            if self.oob_score {
+                builder = builder.with_oob_score();
+            }
+            builder.fit(&mut data[..])
+        }
+
+        /// Fit a quantile regression forest, whose leaves keep the training targets.
+        pub fn fit_quantile<'b, T>(&self, x: &'b Vec2D<T>, y: &'b Vec<f64>) -> QuantileForest<Sample<'b, T, f64, L>>
+            where T: Clone + cmp::PartialOrd + SampleRange + Bounded + SplitBetween,
+        {
+            self.fit_quantile_weighted(x, y, &vec![1.0; y.len()])
+        }
+
+        /// Fit a quantile regression forest with a weight for each sample.
+        pub fn fit_quantile_weighted<'b, T>(&self, x: &'b Vec2D<T>, y: &'b Vec<f64>, sample_weight: &[f64])
+            -> QuantileForest<Sample<'b, T, f64, L>>
+            where T: Clone + cmp::PartialOrd + SampleRange + Bounded + SplitBetween,
+        {
+            let forest = self.fit_weighted(x, y, sample_weight);
+            let data = Self::training_data(x, y, sample_weight);
+            QuantileForest::new(forest, &data[..])
+        }
+
+        fn training_data<'b, T>(x: &'b Vec2D<T>, y: &'b Vec<f64>, sample_weight: &[f64]) -> Vec<Sample<'b, T, f64, L>> {
+            x.iter()
+                .zip(y.iter())
+                .zip(sample_weight)
+                .map(|((xi, yi), wi)| Sample { x: xi, y: *yi, weight: *wi, _leaf: PhantomData })
+                .collect()
         }
     }
 
-    impl Default for ExtraTreesRegressor {
+    impl<L> Default for ExtraTreesRegressor<L> {
         fn default() -> Self {
             Self {
                 n_estimators: 10,
@@ -153,6 +265,9 @@ pub mod extra_trees_regressor {
                 min_samples_split: 2,
                 max_depth: None,
                 bootstrap: None,
+                oob_score: false,
+                seed: None,
+                _leaf: PhantomData,
             }
         }
     }
@@ -161,18 +276,20 @@ pub mod extra_trees_regressor {
 
 pub mod extra_trees_classifier {
     use super::*;
+    use std::collections::HashMap;
     use std::f64;
-    use rand::Rng;
     use categorical::{Categorical, CatCount};
     use criterion::GiniCriterion;
-    use data::{SampleDescription, TrainingData};
+    use data::{feature_bounds_of, SampleDescription, TrainingData};
     use dforest::{DeterministicForest, DeterministicForestBuilder};
     use dtree::DeterministicTreeBuilder;
     use iter_mean::IterMean;
+    use persist::{self, Codec};
     use split::BestRandomSplit;
     use split_between::SplitBetween;
 
     #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
     pub struct Classes(pub u8);
 
     impl Categorical for Classes {
@@ -191,25 +308,36 @@ pub mod extra_trees_classifier {
         }
     }
 
+    /// Weighted number of samples per class
     #[derive(Debug, Clone)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
     pub struct ClassCounts {
-        counts: Vec<usize>,
-        total: usize,
+        counts: Vec<f64>,
+        total: f64,
     }
 
     impl ClassCounts {
         fn new() -> Self {
             ClassCounts {
                 counts: Vec::new(),
-                total: 0,
+                total: 0.0,
             }
         }
 
         pub fn probs<F: FnMut(f64)>(&self, mut f: F) {
-            let n = self.total as f64;
             for c in self.counts.iter() {
-                f(*c as f64 / n);
+                f(*c / self.total);
+            }
+        }
+
+        /// Add an observation of class `c` with weight `w`.
+        pub fn add_weighted(&mut self, c: Classes, w: f64) {
+            let i = c.as_usize();
+            if i >= self.counts.len() {
+                self.counts.resize(i + 1, 0.0);
             }
+            self.counts[i] += w;
+            self.total += w;
         }
     }
 
@@ -219,30 +347,25 @@ pub mod extra_trees_classifier {
         }
 
         fn add_n(&mut self, c: Classes, n: usize) {
-            let i = c.as_usize();
-            if i >= self.counts.len() {
-                self.counts.resize(i + 1, 0);
-            }
-            self.counts[i] += n;
-            self.total += n;
+            self.add_weighted(c, n as f64)
         }
 
         fn probability(&self, c: Classes) -> f64 {
             let i = c.as_usize();
             if i < self.counts.len() {
-                self.counts[i] as f64 / self.total as f64
+                self.counts[i] / self.total
             } else {
                 0.0
             }
         }
 
         fn most_frequent(&self) -> Classes {
-            let mut n = 0;
+            let mut n = 0.0;
             let mut c = 0;
-            for i in 0..self.counts.len() {
+            for (i, &ni) in self.counts.iter().enumerate() {
                 // TODO: handle ties?
-                if self.counts[i] > n {
-                    n = self.counts[i];
+                if ni > n {
+                    n = ni;
                     c = i;
                 }
             }
@@ -250,6 +373,29 @@ pub mod extra_trees_classifier {
         }
     }
 
+    impl Codec for ClassCounts {
+        fn encode(&self, buf: &mut Vec<u8>) {
+            self.counts.encode(buf);
+        }
+
+        fn decode(input: &mut &[u8]) -> Result<Self, persist::Error> {
+            let counts: Vec<f64> = Codec::decode(input)?;
+            let total = counts.iter().sum();
+            Ok(ClassCounts { counts, total })
+        }
+
+        fn decode_version(input: &mut &[u8], version: u32) -> Result<Self, persist::Error> {
+            if version >= 3 {
+                return Self::decode(input)
+            }
+            // older formats stored integer counts
+            let counts: Vec<usize> = Codec::decode(input)?;
+            let counts: Vec<f64> = counts.into_iter().map(|n| n as f64).collect();
+            let total = counts.iter().sum();
+            Ok(ClassCounts { counts, total })
+        }
+    }
+
     impl<'a> Sum<&'a Classes> for ClassCounts {
         fn sum<I: Iterator<Item=&'a Classes>>(iter: I) -> Self {
             let mut counts = ClassCounts::new();
@@ -265,7 +411,7 @@ pub mod extra_trees_classifier {
             let mut total_counts = ClassCounts::new();
             for c in iter {
                 for (i, n) in c.counts.iter().enumerate() {
-                    total_counts.add_n(Classes(i as u8), *n);
+                    total_counts.add_weighted(Classes(i as u8), *n);
                 }
             }
             total_counts
@@ -278,13 +424,19 @@ pub mod extra_trees_classifier {
     {
         x: &'a[X],
         y: Y,
+        weight: f64,
     }
 
     impl<'a, X: 'a, Y> Sample<'a, X, Y>
         where X: Clone + PartialOrd + SampleRange,
     {
         pub fn new(x: &'a[X], y: Y) -> Self {
-            Sample { x, y }
+            Sample { x, y, weight: 1.0 }
+        }
+
+        pub fn with_weight(mut self, weight: f64) -> Self {
+            self.weight = weight;
+            self
         }
     }
 
@@ -309,6 +461,10 @@ pub mod extra_trees_classifier {
         fn sample_predict(&self, w: &Self::ThetaLeaf) -> Self::Prediction {
             w.clone()
         }
+
+        fn weight(&self) -> f64 {
+            self.weight
+        }
     }
 
     impl<'a, X> TrainingData<Sample<'a, X, Classes>> for [Sample<'a, X, Classes>]
@@ -320,23 +476,59 @@ pub mod extra_trees_classifier {
             self.len()
         }
 
-        fn gen_split_feature(&self) -> usize {
+        fn gen_split_feature<R: Rng>(&self, rng: &mut R) -> usize {
             let n = self[0].x.len();
-            thread_rng().gen_range(0, n)
+            rng.gen_range(0, n)
         }
 
         fn train_leaf_predictor(&self) -> ClassCounts {
-            self.iter().map(|sample| &sample.y).sum()
+            let mut counts = ClassCounts::new();
+            for sample in self {
+                counts.add_weighted(sample.y, sample.weight);
+            }
+            counts
         }
 
         fn feature_bounds(&self, theta: &usize) -> (X, X) {
-            self.iter()
-                .map(|sample| sample.sample_as_split_feature(theta))
-                .fold((X::max_value(), X::min_value()),
-                      |(min, max), x| {
-                          (if x < min {x.clone()} else {min},
-                           if x > max {x} else {max})
-                      })
+            feature_bounds_of(self, theta)
+        }
+    }
+
+    /// Weighting of the classes in training.
+    #[derive(Debug, Clone)]
+    pub enum ClassWeight {
+        /// Weight classes inversely proportional to their frequency, so that every class has the
+        /// same total weight.
+        Balanced,
+
+        /// Explicit weight per class label; classes not in the map have weight 1.0.
+        Explicit(HashMap<u8, f64>),
+    }
+
+    impl ClassWeight {
+        /// Weight of each class label in `y`, indexed by label
+        fn class_weights(&self, y: &[u8]) -> Vec<f64> {
+            let mut weights = vec![1.0; 256];
+            match *self {
+                ClassWeight::Balanced => {
+                    let mut counts = vec![0usize; 256];
+                    for &c in y {
+                        counts[c as usize] += 1;
+                    }
+                    let n_classes = counts.iter().filter(|&&n| n > 0).count();
+                    for (w, &n) in weights.iter_mut().zip(&counts) {
+                        if n > 0 {
+                            *w = y.len() as f64 / (n_classes * n) as f64;
+                        }
+                    }
+                }
+                ClassWeight::Explicit(ref map) => {
+                    for (&c, &w) in map {
+                        weights[c as usize] = w;
+                    }
+                }
+            }
+            weights
         }
     }
 
@@ -346,6 +538,9 @@ pub mod extra_trees_classifier {
         min_samples_split: usize,
         max_depth: Option<usize>,
         bootstrap: Option<usize>,
+        oob_score: bool,
+        seed: Option<u64>,
+        class_weight: Option<ClassWeight>,
     }
 
     impl ExtraTreesClassifier {
@@ -373,30 +568,75 @@ pub mod extra_trees_classifier {
             self
         }
 
-        pub fn with_bootstrap(mut self, d: usize) -> Self {
-            self.max_depth = Some(d);
+        pub fn with_bootstrap(mut self, n: usize) -> Self {
+            self.bootstrap = Some(n);
+            self
+        }
+
+        /// Compute out-of-bag predictions; requires `with_bootstrap`.
+        pub fn with_oob_score(mut self) -> Self {
+            self.oob_score = true;
+            self
+        }
+
+        pub fn with_seed(mut self, seed: u64) -> Self {
+            self.seed = Some(seed);
+            self
+        }
+
+        /// Weight the classes in split evaluation and leaf estimates, for example to counter
+        /// class imbalance.
+        ///
+        /// Class weights are multiplied with the sample weights.
+        pub fn with_class_weight(mut self, class_weight: ClassWeight) -> Self {
+            self.class_weight = Some(class_weight);
             self
         }
 
         pub fn fit<'a, 'b, T>(&'a self, x: &'b Vec2D<T>, y: &'b Vec<u8>) -> DeterministicForest<Sample<'b, T, Classes>>
             where T: Clone + cmp::PartialOrd + SampleRange + Bounded + SplitBetween,
         {
+            self.fit_weighted(x, y, &vec![1.0; y.len()])
+        }
+
+        /// Fit the forest with a weight for each sample.
+        pub fn fit_weighted<'b, T>(&self, x: &'b Vec2D<T>, y: &'b Vec<u8>, sample_weight: &[f64])
+            -> DeterministicForest<Sample<'b, T, Classes>>
+            where T: Clone + cmp::PartialOrd + SampleRange + Bounded + SplitBetween,
+        {
+            assert_eq!(y.len(), sample_weight.len());
+            let class_weights = match self.class_weight {
+                Some(ref cw) => cw.class_weights(y),
+                None => vec![1.0; 256],
+            };
+
             let mut data: Vec<Sample<T, Classes>> = x.iter()
                 .zip(y.iter())
-                .map(|(xi, yi)| Sample{x: xi, y: Classes(*yi)})
+                .zip(sample_weight)
+                .map(|((xi, yi), wi)| Sample{x: xi, y: Classes(*yi), weight: wi * class_weights[*yi as usize]})
                 .collect();
 
-            DeterministicForestBuilder::new(
+            let mut builder = DeterministicForestBuilder::new(
                 self.n_estimators,
                 DeterministicTreeBuilder {
                     _p: PhantomData,
                     min_samples_split: self.min_samples_split,
                     min_samples_leaf: 1,
+                    min_samples_weighted: false,
                     split_finder: BestRandomSplit::new(self.n_splits),
                     max_depth: self.max_depth,
                     bootstrap: self.bootstrap,
+                    seed: None,
                 }
-            ).fit(&mut data[..])
+            );
+
+            if let Some(seed) = self.seed {
+                builder = builder.with_seed(seed);
+            }
+            if self.oob_score {
+                builder = builder.with_oob_score();
+            }
+            builder.fit(&mut data[..])
         }
     }
 
@@ -408,6 +648,321 @@ pub mod extra_trees_classifier {
                 min_samples_split: 2,
                 max_depth: None,
                 bootstrap: None,
+                oob_score: false,
+                seed: None,
+                class_weight: None,
+            }
+        }
+    }
+}
+
+
+pub mod density_forest {
+    use super::*;
+    use std::f64;
+    use criterion::LogDetCriterion;
+    use data::{feature_bounds_of, SampleDescription, TrainingData};
+    use dforest::{DeterministicForest, DeterministicForestBuilder};
+    use dtree::{DeterministicTreeBuilder, Node};
+    use persist::{self, Codec};
+    use split::BestRandomSplit;
+
+    /// Lower bound on the variance of leaf Gaussians
+    const MIN_VARIANCE: f64 = 1e-6;
+
+    /// A point in feature space.
+    ///
+    /// Density estimation is unsupervised. The target of a sample is simply its coordinates, which
+    /// is what `LogDetCriterion` expects.
+    #[derive(Debug, Clone)]
+    pub struct Sample<'a> {
+        x: &'a[f64],
+    }
+
+    impl<'a> Sample<'a> {
+        pub fn new(x: &'a[f64]) -> Self {
+            Sample { x }
+        }
+    }
+
+    /// Gaussian leaf model with diagonal covariance, normalized over the leaf's cell.
+    #[derive(Debug, Clone)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct GaussianLeaf {
+        n: usize,
+        mean: Vec<f64>,
+        var: Vec<f64>,
+        lower: Vec<f64>,
+        upper: Vec<f64>,
+        scale: f64,
+    }
+
+    impl GaussianLeaf {
+        /// Density at `x`; zero outside the leaf's cell.
+        pub fn density(&self, x: &[f64]) -> f64 {
+            let mut log_p = 0.0;
+            let cell = self.lower.iter().zip(self.upper.iter());
+            let gauss = self.mean.iter().zip(self.var.iter());
+            for ((xi, (lo, up)), (mu, var)) in x.iter().zip(cell).zip(gauss) {
+                if xi < lo || xi > up {
+                    return 0.0
+                }
+                log_p -= (xi - mu) * (xi - mu) / (2.0 * var) + 0.5 * (2.0 * f64::consts::PI * var).ln();
+            }
+            self.scale * log_p.exp()
+        }
+
+        /// Restrict the Gaussian to a cell, and scale it to integrate to the leaf's share of the
+        /// `n_total` training samples.
+        fn set_cell(&mut self, lower: Vec<f64>, upper: Vec<f64>, n_total: f64) {
+            let mut z = 1.0;
+            for ((lo, up), (mu, var)) in lower.iter().zip(&upper).zip(self.mean.iter().zip(&self.var)) {
+                let sd = var.sqrt();
+                z *= normal_probability((lo - mu) / sd, (up - mu) / sd);
+            }
+            self.scale = self.n as f64 / n_total / z.max(f64::MIN_POSITIVE);
+            self.lower = lower;
+            self.upper = upper;
+        }
+    }
+
+    impl Codec for GaussianLeaf {
+        fn encode(&self, buf: &mut Vec<u8>) {
+            self.n.encode(buf);
+            self.mean.encode(buf);
+            self.var.encode(buf);
+            self.lower.encode(buf);
+            self.upper.encode(buf);
+            self.scale.encode(buf);
+        }
+
+        fn decode(input: &mut &[u8]) -> Result<Self, persist::Error> {
+            Ok(GaussianLeaf {
+                n: Codec::decode(input)?,
+                mean: Codec::decode(input)?,
+                var: Codec::decode(input)?,
+                lower: Codec::decode(input)?,
+                upper: Codec::decode(input)?,
+                scale: Codec::decode(input)?,
+            })
+        }
+    }
+
+    /// Probability that a standard normal variable falls between `a` and `b`.
+    fn normal_probability(a: f64, b: f64) -> f64 {
+        // evaluate in the lower tail, where erfc is accurate
+        if a > 0.0 {
+            0.5 * (erfc(a / f64::consts::SQRT_2) - erfc(b / f64::consts::SQRT_2))
+        } else {
+            0.5 * (erfc(-b / f64::consts::SQRT_2) - erfc(-a / f64::consts::SQRT_2))
+        }
+    }
+
+    /// Complementary error function with a fractional error below 1.2e-7 (Numerical Recipes).
+    fn erfc(x: f64) -> f64 {
+        let z = x.abs();
+        let t = 1.0 / (1.0 + 0.5 * z);
+        let ans = t * (-z * z - 1.265_512_23 + t * (1.000_023_68 + t * (0.374_091_96
+            + t * (0.096_784_18 + t * (-0.186_288_06 + t * (0.278_868_07 + t * (-1.135_203_98
+            + t * (1.488_515_87 + t * (-0.822_152_23 + t * 0.170_872_77))))))))).exp();
+        if x >= 0.0 { ans } else { 2.0 - ans }
+    }
+
+    impl<'a> SampleDescription for Sample<'a> {
+        type ThetaSplit = usize;
+        type ThetaLeaf = GaussianLeaf;
+        type Feature = f64;
+        type Target = &'a[f64];
+        type Prediction = f64;
+
+        fn target(&self) -> Self::Target {
+            self.x
+        }
+
+        fn sample_as_split_feature(&self, theta: &Self::ThetaSplit) -> Self::Feature {
+            self.x[*theta]
+        }
+
+        fn sample_predict(&self, w: &Self::ThetaLeaf) -> Self::Prediction {
+            w.density(self.x)
+        }
+    }
+
+    impl<'a> TrainingData<Sample<'a>> for [Sample<'a>] {
+        type Criterion = LogDetCriterion;
+
+        fn n_samples(&self) -> usize {
+            self.len()
+        }
+
+        fn gen_split_feature<R: Rng>(&self, rng: &mut R) -> usize {
+            let n = self[0].x.len();
+            rng.gen_range(0, n)
+        }
+
+        fn all_split_features(&self) -> Option<Box<Iterator<Item=usize>>> {
+            Some(Box::new(0..self[0].x.len()))
+        }
+
+        fn train_leaf_predictor(&self) -> GaussianLeaf {
+            let n = self.len() as f64;
+            let d = self[0].x.len();
+            let mut mean = vec![0.0; d];
+            let mut var = vec![0.0; d];
+            for sample in self {
+                for (m, xi) in mean.iter_mut().zip(sample.x) {
+                    *m += xi / n;
+                }
+            }
+            for sample in self {
+                for ((v, m), xi) in var.iter_mut().zip(&mean).zip(sample.x) {
+                    *v += (xi - m) * (xi - m) / n;
+                }
+            }
+            for v in var.iter_mut() {
+                *v = v.max(MIN_VARIANCE);
+            }
+
+            // the cell and normalization are filled in after the tree has been fit
+            GaussianLeaf {
+                n: self.len(),
+                mean,
+                var,
+                lower: vec![],
+                upper: vec![],
+                scale: 0.0,
+            }
+        }
+
+        fn feature_bounds(&self, theta: &usize) -> (f64, f64) {
+            feature_bounds_of(self, theta)
+        }
+    }
+
+    /// Assign each leaf its cell within the bounds and normalize the leaf densities.
+    fn normalize_leaves(nodes: &mut [Node<Sample>], lower: &[f64], upper: &[f64]) {
+        let n_total: usize = nodes.iter()
+            .map(|node| match *node {
+                Node::Leaf(ref leaf) => leaf.n,
+                _ => 0,
+            })
+            .sum();
+        normalize_node(nodes, 0, lower.to_vec(), upper.to_vec(), n_total as f64);
+    }
+
+    fn normalize_node(nodes: &mut [Node<Sample>], n: usize, lower: Vec<f64>, upper: Vec<f64>, n_total: f64) {
+        let (theta, threshold, left, right) = match nodes[n] {
+            Node::Split { theta, threshold, left, right, .. } => (theta, threshold, left, right),
+            Node::Leaf(ref mut leaf) => return leaf.set_cell(lower, upper, n_total),
+            Node::Invalid => return,
+            Node::SubsetSplit { .. } => unreachable!("density samples have no categorical features"),
+        };
+
+        let mut left_upper = upper.clone();
+        left_upper[theta] = threshold.min(upper[theta]);
+        let mut right_lower = lower.clone();
+        right_lower[theta] = threshold.max(lower[theta]);
+
+        normalize_node(nodes, left, lower, left_upper, n_total);
+        normalize_node(nodes, right, right_lower, upper, n_total);
+    }
+
+    /// Density estimation forest.
+    ///
+    /// Splits minimize the log-determinant of the covariance of the samples in each child. Every
+    /// leaf models its samples with a Gaussian that is truncated to the leaf's cell, so each tree's
+    /// density integrates to one over the bounded feature space.
+    pub struct DensityForest {
+        n_estimators: usize,
+        n_splits: usize,
+        min_samples_leaf: usize,
+        max_depth: Option<usize>,
+        bounds: Option<(Vec<f64>, Vec<f64>)>,
+        seed: Option<u64>,
+    }
+
+    impl DensityForest {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn with_n_estimators(mut self, n: usize) -> Self {
+            self.n_estimators = n;
+            self
+        }
+
+        pub fn with_n_splits(mut self, n: usize) -> Self {
+            self.n_splits = n;
+            self
+        }
+
+        pub fn with_min_samples_leaf(mut self, n: usize) -> Self {
+            self.min_samples_leaf = n;
+            self
+        }
+
+        pub fn with_max_depth(mut self, d: usize) -> Self {
+            self.max_depth = Some(d);
+            self
+        }
+
+        /// Set the bounds of the feature space. Defaults to the bounding box of the training data.
+        pub fn with_bounds(mut self, lower: Vec<f64>, upper: Vec<f64>) -> Self {
+            assert_eq!(lower.len(), upper.len());
+            self.bounds = Some((lower, upper));
+            self
+        }
+
+        pub fn with_seed(mut self, seed: u64) -> Self {
+            self.seed = Some(seed);
+            self
+        }
+
+        pub fn fit<'b>(&self, x: &'b Vec2D<f64>) -> DeterministicForest<Sample<'b>> {
+            let mut data: Vec<Sample> = x.iter().map(Sample::new).collect();
+
+            let (lower, upper) = match self.bounds {
+                Some((ref lower, ref upper)) => (lower.clone(), upper.clone()),
+                None => (0..x.n_cols())
+                    .map(|c| data.feature_bounds(&c))
+                    .unzip(),
+            };
+
+            let mut builder = DeterministicForestBuilder::new(
+                self.n_estimators,
+                DeterministicTreeBuilder {
+                    _p: PhantomData,
+                    min_samples_split: 2 * self.min_samples_leaf,
+                    min_samples_leaf: self.min_samples_leaf,
+                    min_samples_weighted: false,
+                    split_finder: BestRandomSplit::new(self.n_splits),
+                    max_depth: self.max_depth,
+                    bootstrap: None,
+                    seed: None,
+                }
+            );
+
+            if let Some(seed) = self.seed {
+                builder = builder.with_seed(seed);
+            }
+
+            let mut forest = builder.fit(&mut data[..]);
+            for tree in &mut forest.estimators {
+                normalize_leaves(&mut tree.nodes, &lower, &upper);
+            }
+            forest
+        }
+    }
+
+    impl Default for DensityForest {
+        fn default() -> Self {
+            Self {
+                n_estimators: 10,
+                n_splits: 10,
+                min_samples_leaf: 5,
+                max_depth: None,
+                bounds: None,
+                seed: None,
             }
         }
     }
@@ -472,4 +1027,223 @@ mod tests {
         assert_eq!(model.predict(&Sample::new(&[2], ())).most_frequent(), Classes(1));
         assert_eq!(model.predict(&Sample::new(&[8], ())).most_frequent(), Classes(2));
     }
+
+    #[test]
+    fn bootstrap_keeps_max_depth() {
+        use super::extra_trees_classifier::ExtraTreesClassifier;
+        use super::extra_trees_regressor::ExtraTreesRegressor;
+        use vec2d::Vec2D;
+
+        // `with_bootstrap` used to set the maximum depth instead of enabling bootstrapping
+        let x = Vec2D::from_vec((0..32).collect(), 1);
+        let y: Vec<_> = (0..32).map(|i| (i % 2) as u8).collect();
+        let y_float: Vec<_> = y.iter().map(|&yi| yi as f64).collect();
+
+        let regressor = ExtraTreesRegressor::new()
+            .with_n_estimators(5)
+            .with_max_depth(1)
+            .with_bootstrap(20)
+            .with_seed(0)
+            .fit(&x, &y_float);
+        let classifier = ExtraTreesClassifier::new()
+            .with_n_estimators(5)
+            .with_max_depth(1)
+            .with_bootstrap(20)
+            .with_seed(0)
+            .fit(&x, &y);
+
+        for t in 0..5 {
+            assert_eq!(regressor.in_bag_counts(t).unwrap().iter().sum::<usize>(), 20);
+            assert_eq!(classifier.in_bag_counts(t).unwrap().iter().sum::<usize>(), 20);
+            assert_eq!(regressor.estimators[t].nodes.len(), 3);
+            assert_eq!(classifier.estimators[t].nodes.len(), 3);
+        }
+    }
+
+    #[test]
+    fn sample_weight() {
+        use super::extra_trees_classifier::{self, Classes, ExtraTreesClassifier};
+        use super::extra_trees_regressor::{ExtraTreesRegressor, Sample};
+        use categorical::CatCount;
+        use dtree::DeterministicTreeBuilder;
+        use split::BestRandomSplit;
+        use vec2d::Vec2D;
+
+        let x = Vec2D::from_slice(&[1.0, 2.0, 3.0], 1);
+
+        let y = vec![0.0, 10.0, 10.0];
+        let model = ExtraTreesRegressor::new().with_max_depth(0).fit_weighted(&x, &y, &[6.0, 1.0, 1.0]);
+        assert_eq!(model.predict(&Sample::new(&[2.0], ())), 2.5);
+
+        let y = vec![1, 2, 2];
+        let model = ExtraTreesClassifier::new().with_max_depth(0).fit_weighted(&x, &y, &[6.0, 1.0, 1.0]);
+        let p = model.predict(&extra_trees_classifier::Sample::new(&[2.0], ()));
+        assert_eq!(p.most_frequent(), Classes(1));
+        assert_eq!(p.probability(Classes(2)), 0.25);
+
+        // a total sample weight below `min_samples_split`
+        let mut data: Vec<_> = x.iter().zip(&y)
+            .map(|(xi, &yi)| Sample::new(xi, yi as f64).with_weight(0.5))
+            .collect();
+
+        let builder = DeterministicTreeBuilder::new(2, BestRandomSplit::new(5));
+        assert!(builder.fit(&mut data[..]).nodes.len() > 1);
+
+        let builder = DeterministicTreeBuilder::new(2, BestRandomSplit::new(5)).with_min_samples_weighted();
+        assert_eq!(builder.fit(&mut data[..]).nodes.len(), 1);
+    }
+
+    #[test]
+    fn median_leaf() {
+        use super::extra_trees_regressor::{ExtraTreesRegressor, Median, Sample};
+        use vec2d::Vec2D;
+
+        let x = Vec2D::from_slice(&[1.0, 2.0, 3.0, 4.0, 5.0], 1);
+        let y = vec![1.0, 2.0, 3.0, 4.0, 1000.0];
+
+        let model = ExtraTreesRegressor::new().with_max_depth(0).fit(&x, &y);
+        assert_eq!(model.predict(&Sample::new(&[2.0], ())), 202.0);
+
+        let model = ExtraTreesRegressor::new()
+            .with_max_depth(0)
+            .with_leaf_statistic::<Median>()
+            .fit(&x, &y);
+        assert_eq!(model.predict(&Sample::new(&[2.0], ())), 3.0);
+
+        let model = ExtraTreesRegressor::new()
+            .with_max_depth(0)
+            .with_leaf_statistic::<Median>()
+            .fit_weighted(&x, &y, &[1.0, 5.0, 1.0, 1.0, 1.0]);
+        assert_eq!(model.predict(&Sample::new(&[2.0], ())), 2.0);
+    }
+
+    #[test]
+    fn quantile_forest() {
+        use super::extra_trees_regressor::{ExtraTreesRegressor, Sample};
+        use vec2d::Vec2D;
+
+        // the target's spread grows with x
+        let n = 200;
+        let x: Vec<f64> = (0..n).map(|i| (i / 20) as f64).collect();
+        let y: Vec<f64> = (0..n).map(|i| x[i] * ((i % 20) as f64 - 9.5)).collect();
+        let x = Vec2D::from_slice(&x, 1);
+
+        let model = ExtraTreesRegressor::new()
+            .with_n_estimators(20)
+            .with_min_samples_split(40)
+            .with_seed(3)
+            .fit_quantile(&x, &y);
+
+        let narrow = model.predict_distribution(&Sample::new(&[1.0], ()));
+        let wide = model.predict_distribution(&Sample::new(&[9.0], ()));
+        assert!(narrow.quantile(0.9) - narrow.quantile(0.1) < wide.quantile(0.9) - wide.quantile(0.1));
+        assert!(narrow.quantile(0.1) <= narrow.quantile(0.5));
+        assert!(narrow.quantile(0.5) <= narrow.quantile(0.9));
+        assert_eq!(narrow.cdf(f64::INFINITY), 1.0);
+        assert_eq!(wide.cdf(wide.quantile(0.0) - 1.0), 0.0);
+
+        // the mean of the distribution pools the same leaves as the forest's prediction
+        let forest_mean = model.forest().predict(&Sample::new(&[9.0], ()));
+        assert!((wide.mean() - forest_mean).abs() < 1e-9);
+        assert_eq!(model.predict_quantile(&Sample::new(&[9.0], ()), 0.5), wide.quantile(0.5));
+    }
+
+    #[test]
+    fn class_weight() {
+        use std::collections::HashMap;
+        use super::extra_trees_classifier::{ClassWeight, Classes, ExtraTreesClassifier, Sample};
+        use categorical::CatCount;
+        use vec2d::Vec2D;
+
+        let x = Vec2D::from_slice(&[1.0, 2.0, 3.0, 4.0], 1);
+        let y = vec![0, 0, 0, 1];
+
+        let model = ExtraTreesClassifier::new()
+            .with_max_depth(0)
+            .with_class_weight(ClassWeight::Balanced)
+            .fit(&x, &y);
+        let p = model.predict(&Sample::new(&[1.0], ()));
+        assert_eq!(p.probability(Classes(0)), 0.5);
+        assert_eq!(p.probability(Classes(1)), 0.5);
+
+        let mut weights = HashMap::new();
+        weights.insert(1, 6.0);
+        let model = ExtraTreesClassifier::new()
+            .with_max_depth(0)
+            .with_class_weight(ClassWeight::Explicit(weights))
+            .fit(&x, &y);
+        let p = model.predict(&Sample::new(&[1.0], ()));
+        assert_eq!(p.most_frequent(), Classes(1));
+        assert_eq!(p.probability(Classes(1)), 2.0 / 3.0);
+    }
+
+    #[test]
+    fn seed() {
+        use super::extra_trees_classifier::ExtraTreesClassifier;
+        use super::extra_trees_regressor::ExtraTreesRegressor;
+        use vec2d::Vec2D;
+
+        let x = Vec2D::from_slice(&[1.0, 2.0, 3.0, 7.0, 8.0, 9.0], 1);
+        let y = vec![5.0, 5.0, 5.0, 2.0, 2.0, 2.0];
+
+        let a = ExtraTreesRegressor::new().with_seed(7).fit(&x, &y);
+        let b = ExtraTreesRegressor::new().with_seed(7).fit(&x, &y);
+        assert_eq!(format!("{:?}", a), format!("{:?}", b));
+
+        let y = vec![1, 1, 1, 2, 2, 2];
+
+        let a = ExtraTreesClassifier::new().with_seed(7).fit(&x, &y);
+        let b = ExtraTreesClassifier::new().with_seed(7).fit(&x, &y);
+        assert_eq!(format!("{:?}", a), format!("{:?}", b));
+    }
+
+    #[test]
+    fn oob_score() {
+        use super::extra_trees_regressor::ExtraTreesRegressor;
+        use vec2d::Vec2D;
+
+        let x = Vec2D::from_vec((0..30).map(|i| i as f64).collect(), 1);
+        let y: Vec<_> = (0..30).map(|i| if i < 15 { 5.0 } else { 2.0 }).collect();
+
+        let model = ExtraTreesRegressor::new()
+            .with_n_estimators(20)
+            .with_bootstrap(30)
+            .with_oob_score()
+            .with_seed(1)
+            .fit(&x, &y);
+
+        assert!(model.in_bag_counts(0).is_some());
+        let mse = model.oob_score(|p, t| (p - t) * (p - t)).unwrap();
+        assert!(mse < 1.0);
+    }
+
+    #[test]
+    fn density_forest() {
+        use super::density_forest::{DensityForest, Sample};
+        use vec2d::Vec2D;
+
+        // two clusters around 1 and 4, in [0, 5]
+        let x: Vec<f64> = (0..100)
+            .map(|i| if i % 2 == 0 { 1.0 } else { 4.0 } + ((i * 7) % 11) as f64 / 20.0 - 0.25)
+            .collect();
+        let x = Vec2D::from_vec(x, 1);
+
+        let model = DensityForest::new()
+            .with_n_estimators(5)
+            .with_bounds(vec![0.0], vec![5.0])
+            .with_seed(0)
+            .fit(&x);
+
+        let density = |xi: f64| model.predict(&Sample::new(&[xi]));
+
+        let n = 100_000;
+        let dx = 5.0 / n as f64;
+        let integral: f64 = (0..n).map(|i| density((i as f64 + 0.5) * dx) * dx).sum();
+        assert!((integral - 1.0).abs() < 1e-3);
+
+        assert!(density(1.0) > density(2.5));
+        assert!(density(4.0) > density(2.5));
+        assert_eq!(density(-1.0), 0.0);
+        assert_eq!(density(6.0), 0.0);
+    }
 }
@@ -140,6 +140,19 @@ pub fn resample<T: Clone, R: Rng>(x: &[T], n: usize, rng: &mut R) -> Vec<T> {
         .collect()
 }
 
+/// Like `resample`, but also return how often each element of `x` was drawn.
+pub fn resample_with_counts<T: Clone, R: Rng>(x: &[T], n: usize, rng: &mut R) -> (Vec<T>, Vec<usize>) {
+    let mut counts = vec![0; x.len()];
+    let y = rng.sample_iter(&Uniform::new(0, x.len()))
+        .take(n)
+        .map(|i| {
+            counts[i] += 1;
+            x[i].clone()
+        })
+        .collect();
+    (y, counts)
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -233,4 +246,15 @@ mod tests {
         // https://www.itl.nist.gov/div898/handbook/eda/section3/eda3674.htm
         assert!(chi_square < 9.21);
     }
+
+    #[test]
+    fn bootstrap_counts() {
+        let x: Vec<_> = (0..5).collect();
+        let (y, counts) = resample_with_counts(&x, 20, &mut thread_rng());
+
+        assert_eq!(counts.iter().sum::<usize>(), 20);
+        for (i, &c) in counts.iter().enumerate() {
+            assert_eq!(y.iter().filter(|&&yi| yi == i).count(), c);
+        }
+    }
 }
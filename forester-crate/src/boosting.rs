@@ -0,0 +1,612 @@
+//! Gradient boosting.
+//!
+//! A gradient boosted ensemble is a sum of shallow regression trees. Each tree is fit to the
+//! negative gradient of a differentiable loss (the pseudo-residuals) of the ensemble built so far,
+//! and its leaf values are then set to minimize the loss in each leaf.
+
+use std::f64;
+
+use num_traits::Bounded;
+use rand::{thread_rng, Rng, SeedableRng};
+use rand::prng::ChaChaRng;
+use rand::seq::sample_indices;
+
+use categorical::Category;
+use continuous::Continuous;
+use criterion::VarianceCriterion;
+use data::{feature_bounds_of, first_inner, SampleDescription, TrainingData, WrappedSample};
+use dtree::{DeterministicTree, DeterministicTreeBuilder, Node};
+use iter_mean::WeightedMean;
+use split::SplitFinder;
+
+/// Differentiable loss function for gradient boosting.
+///
+/// The slices passed to `init` and `leaf_value` hold one entry per sample, and `w` holds the
+/// samples' weights.
+pub trait Loss {
+    /// Constant initial prediction that minimizes the weighted loss over all targets `y`
+    fn init(&self, y: &[f64], w: &[f64]) -> f64;
+
+    /// Loss of the raw prediction `f` for target `y`
+    fn loss(&self, y: f64, f: f64) -> f64;
+
+    /// Negative gradient of the loss with respect to `f`
+    fn negative_gradient(&self, y: f64, f: f64) -> f64;
+
+    /// Value to add to the raw predictions `f` of the samples in a leaf with targets `y`
+    ///
+    /// The default implementation is the weighted mean negative gradient.
+    fn leaf_value(&self, y: &[f64], f: &[f64], w: &[f64]) -> f64 {
+        f64::weighted_mean(w.iter().cloned().zip(y.iter().zip(f).map(|(&y, &f)| self.negative_gradient(y, f))))
+    }
+
+    /// Convert a raw prediction into the final prediction
+    fn transform(&self, f: f64) -> f64 {
+        f
+    }
+}
+
+/// Squared error loss for regression.
+#[derive(Debug, Copy, Clone)]
+pub struct SquaredError;
+
+impl Loss for SquaredError {
+    fn init(&self, y: &[f64], w: &[f64]) -> f64 {
+        f64::weighted_mean(w.iter().cloned().zip(y.iter().cloned()))
+    }
+
+    fn loss(&self, y: f64, f: f64) -> f64 {
+        0.5 * (y - f) * (y - f)
+    }
+
+    fn negative_gradient(&self, y: f64, f: f64) -> f64 {
+        y - f
+    }
+}
+
+/// Absolute error loss for robust regression.
+#[derive(Debug, Copy, Clone)]
+pub struct AbsoluteError;
+
+impl Loss for AbsoluteError {
+    fn init(&self, y: &[f64], w: &[f64]) -> f64 {
+        weighted_median(y.iter().cloned().zip(w.iter().cloned()).collect())
+    }
+
+    fn loss(&self, y: f64, f: f64) -> f64 {
+        (y - f).abs()
+    }
+
+    fn negative_gradient(&self, y: f64, f: f64) -> f64 {
+        if y > f { 1.0 } else if y < f { -1.0 } else { 0.0 }
+    }
+
+    fn leaf_value(&self, y: &[f64], f: &[f64], w: &[f64]) -> f64 {
+        weighted_median(y.iter().zip(f).map(|(y, f)| y - f).zip(w.iter().cloned()).collect())
+    }
+}
+
+/// Huber loss: quadratic for residuals up to `delta`, linear beyond.
+#[derive(Debug, Copy, Clone)]
+pub struct Huber {
+    pub delta: f64,
+}
+
+impl Loss for Huber {
+    fn init(&self, y: &[f64], w: &[f64]) -> f64 {
+        weighted_median(y.iter().cloned().zip(w.iter().cloned()).collect())
+    }
+
+    fn loss(&self, y: f64, f: f64) -> f64 {
+        let r = (y - f).abs();
+        if r <= self.delta {
+            0.5 * r * r
+        } else {
+            self.delta * (r - 0.5 * self.delta)
+        }
+    }
+
+    fn negative_gradient(&self, y: f64, f: f64) -> f64 {
+        (y - f).clamp(-self.delta, self.delta)
+    }
+
+    fn leaf_value(&self, y: &[f64], f: &[f64], w: &[f64]) -> f64 {
+        // one step from the median towards the mean of the clipped residuals (Friedman, 2001)
+        let r: Vec<(f64, f64)> = y.iter().zip(f).map(|(y, f)| y - f).zip(w.iter().cloned()).collect();
+        let m = weighted_median(r.clone());
+        m + f64::weighted_mean(r.iter().map(|&(ri, wi)| (wi, (ri - m).clamp(-self.delta, self.delta))))
+    }
+}
+
+/// Binary log-loss for classification with targets 0 and 1.
+///
+/// Raw predictions are log-odds; the final prediction is the probability of class 1.
+#[derive(Debug, Copy, Clone)]
+pub struct LogLoss;
+
+impl Loss for LogLoss {
+    fn init(&self, y: &[f64], w: &[f64]) -> f64 {
+        let p = f64::weighted_mean(w.iter().cloned().zip(y.iter().cloned())).clamp(1e-15, 1.0 - 1e-15);
+        (p / (1.0 - p)).ln()
+    }
+
+    fn loss(&self, y: f64, f: f64) -> f64 {
+        // log(1 + exp(f)) - y * f, evaluated without overflow
+        f.max(0.0) + (-f.abs()).exp().ln_1p() - y * f
+    }
+
+    fn negative_gradient(&self, y: f64, f: f64) -> f64 {
+        y - sigmoid(f)
+    }
+
+    fn leaf_value(&self, y: &[f64], f: &[f64], w: &[f64]) -> f64 {
+        // single Newton step
+        let (num, den) = y.iter().zip(f).zip(w).fold((0.0, 0.0), |(num, den), ((&y, &f), &w)| {
+            let p = sigmoid(f);
+            (num + w * (y - p), den + w * p * (1.0 - p))
+        });
+        if den < 1e-150 { 0.0 } else { num / den }
+    }
+
+    fn transform(&self, f: f64) -> f64 {
+        sigmoid(f)
+    }
+}
+
+fn sigmoid(f: f64) -> f64 {
+    1.0 / (1.0 + (-f).exp())
+}
+
+/// Median of `(value, weight)` pairs. If the lower half of the weights ends exactly between two
+/// values, their average is returned, so unit weights give the ordinary median.
+fn weighted_median(mut x: Vec<(f64, f64)>) -> f64 {
+    x.sort_unstable_by(|a, b| a.0.partial_cmp(&b.0).expect("Could not compare values (NaN?)"));
+    let half = x.iter().map(|&(_, w)| w).sum::<f64>() / 2.0;
+    let mut cumulative = 0.0;
+    for (i, &(value, w)) in x.iter().enumerate() {
+        cumulative += w;
+        if cumulative > half {
+            return value
+        }
+        if cumulative == half && i + 1 < x.len() {
+            return (value + x[i + 1].0) / 2.0
+        }
+    }
+    x.last().map_or(f64::NAN, |&(value, _)| value)
+}
+
+/// A training sample together with its current pseudo-residual.
+///
+/// The boosted trees are fit to data sets of these.
+#[derive(Debug, Clone)]
+pub struct Residual<S> {
+    sample: S,
+    y: f64,
+    f: f64,
+    residual: f64,
+}
+
+impl<S> SampleDescription for Residual<S>
+    where S: SampleDescription
+{
+    type ThetaSplit = S::ThetaSplit;
+    type ThetaLeaf = f64;
+    type Feature = S::Feature;
+    type Target = f64;
+    type Prediction = f64;
+
+    fn target(&self) -> f64 {
+        self.residual
+    }
+
+    fn sample_as_split_feature(&self, theta: &Self::ThetaSplit) -> Self::Feature {
+        self.sample.sample_as_split_feature(theta)
+    }
+
+    fn sample_as_category(&self, theta: &Self::ThetaSplit) -> Option<Category> {
+        self.sample.sample_as_category(theta)
+    }
+
+    fn sample_predict(&self, w: &f64) -> f64 {
+        *w
+    }
+
+    fn weight(&self) -> f64 {
+        self.sample.weight()
+    }
+}
+
+impl<S: Clone> WrappedSample for Residual<S> {
+    type Inner = S;
+
+    fn inner(&self) -> &S {
+        &self.sample
+    }
+}
+
+/// Split features are generated by the wrapped samples' data set.
+impl<S> TrainingData<Residual<S>> for [Residual<S>]
+    where S: SampleDescription + Clone,
+          S::Feature: Bounded + Clone,
+          [S]: TrainingData<S>,
+{
+    type Criterion = VarianceCriterion;
+
+    fn n_samples(&self) -> usize {
+        self.len()
+    }
+
+    fn gen_split_feature<R: Rng>(&self, rng: &mut R) -> S::ThetaSplit {
+        first_inner(self).gen_split_feature(rng)
+    }
+
+    fn all_split_features(&self) -> Option<Box<Iterator<Item=S::ThetaSplit>>> {
+        first_inner(self).all_split_features()
+    }
+
+    fn train_leaf_predictor(&self) -> f64 {
+        f64::weighted_mean(self.iter().map(|r| (r.weight(), r.residual)))
+    }
+
+    fn feature_bounds(&self, theta: &S::ThetaSplit) -> (S::Feature, S::Feature) {
+        feature_bounds_of(self, theta)
+    }
+}
+
+/// An additive ensemble of regression trees.
+pub struct GradientBoostedTrees<S, L>
+    where S: SampleDescription
+{
+    init: f64,
+    learning_rate: f64,
+    estimators: Vec<DeterministicTree<Residual<S>>>,
+    loss: L,
+}
+
+impl<S, L> GradientBoostedTrees<S, L>
+    where S: SampleDescription,
+          L: Loss,
+{
+    /// Number of trees in the ensemble
+    pub fn n_estimators(&self) -> usize {
+        self.estimators.len()
+    }
+
+    /// Raw prediction, i.e. the sum of the initial prediction and all tree contributions
+    pub fn decision_function<TestingSample>(&self, sample: &TestingSample) -> f64
+        where TestingSample: SampleDescription<ThetaSplit=S::ThetaSplit, Feature=S::Feature> + ?Sized,
+    {
+        self.estimators
+            .iter()
+            .fold(self.init, |f, tree| f + self.learning_rate * tree.predict_leaf(sample))
+    }
+
+    pub fn predict<TestingSample>(&self, sample: &TestingSample) -> f64
+        where TestingSample: SampleDescription<ThetaSplit=S::ThetaSplit, Feature=S::Feature> + ?Sized,
+    {
+        self.loss.transform(self.decision_function(sample))
+    }
+}
+
+/// Fit `GradientBoostedTrees` to `TrainingData`.
+pub struct GradientBoostingBuilder<SF, S, L>
+    where SF: SplitFinder,
+          S: SampleDescription,
+{
+    n_estimators: usize,
+    learning_rate: f64,
+    subsample: f64,
+    n_iter_no_change: Option<usize>,
+    validation_fraction: f64,
+    seed: Option<u64>,
+    loss: L,
+    tree_builder: DeterministicTreeBuilder<SF, Residual<S>>,
+}
+
+impl<SF, S, L> GradientBoostingBuilder<SF, S, L>
+    where SF: SplitFinder,
+          S: SampleDescription + Clone,
+          S::Feature: Bounded + Clone,
+          S::Target: Continuous,
+          [S]: TrainingData<S>,
+          L: Loss + Clone,
+{
+    pub fn new(n_estimators: usize, loss: L, tree_builder: DeterministicTreeBuilder<SF, Residual<S>>) -> Self {
+        GradientBoostingBuilder {
+            n_estimators,
+            learning_rate: 0.1,
+            subsample: 1.0,
+            n_iter_no_change: None,
+            validation_fraction: 0.1,
+            seed: None,
+            loss,
+            tree_builder,
+        }
+    }
+
+    /// Shrink the contribution of each tree by `learning_rate`.
+    pub fn with_learning_rate(mut self, learning_rate: f64) -> Self {
+        self.learning_rate = learning_rate;
+        self
+    }
+
+    /// Fit each tree on a random fraction of the training data, drawn without replacement.
+    pub fn with_subsample(mut self, fraction: f64) -> Self {
+        assert!(fraction > 0.0 && fraction <= 1.0);
+        self.subsample = fraction;
+        self
+    }
+
+    /// Stop fitting when the validation loss did not improve for `n_rounds` rounds.
+    ///
+    /// `fit` holds out a random fraction of the training data for validation (see
+    /// `with_validation_fraction`); `fit_with_validation` uses the given validation set instead.
+    /// Trees fit after the best round are discarded.
+    pub fn with_early_stopping(mut self, n_rounds: usize) -> Self {
+        self.n_iter_no_change = Some(n_rounds);
+        self
+    }
+
+    /// Fraction of the training data that `fit` holds out for early stopping (default 0.1).
+    ///
+    /// Has no effect unless early stopping is enabled.
+    pub fn with_validation_fraction(mut self, fraction: f64) -> Self {
+        assert!(fraction > 0.0 && fraction < 1.0);
+        self.validation_fraction = fraction;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn fit(&self, data: &[S]) -> GradientBoostedTrees<S, L> {
+        match self.seed {
+            None => self.fit_holding_out(data, &mut thread_rng()),
+            Some(seed) => self.fit_holding_out(data, &mut ChaChaRng::seed_from_u64(seed)),
+        }
+    }
+
+    /// Fit the ensemble, monitoring the loss on a separate `validation` set for early stopping.
+    pub fn fit_with_validation(&self, data: &[S], validation: &[S]) -> GradientBoostedTrees<S, L> {
+        match self.seed {
+            None => self.fit_with_rng(data, validation, &mut thread_rng()),
+            Some(seed) => self.fit_with_rng(data, validation, &mut ChaChaRng::seed_from_u64(seed)),
+        }
+    }
+
+    /// Fit the ensemble, drawing all randomness from `rng`. The builder's seed is ignored.
+    pub fn fit_with_rng<R: Rng>(&self, data: &[S], validation: &[S], rng: &mut R) -> GradientBoostedTrees<S, L> {
+        let n = data.len();
+        let n_sub = ((self.subsample * n as f64).round() as usize).max(1);
+
+        let y: Vec<f64> = data.iter().map(|s| s.target().as_float()).collect();
+        let w: Vec<f64> = data.iter().map(|s| s.weight()).collect();
+        let y_valid: Vec<f64> = validation.iter().map(|s| s.target().as_float()).collect();
+
+        let init = self.loss.init(&y, &w);
+        let mut f = vec![init; n];
+        let mut f_valid = vec![init; validation.len()];
+
+        let mut estimators = Vec::with_capacity(self.n_estimators);
+        let mut best = (f64::INFINITY, 0);
+
+        for round in 0..self.n_estimators {
+            let indices = if n_sub < n {
+                sample_indices(rng, n, n_sub)
+            } else {
+                (0..n).collect()
+            };
+
+            let mut residuals: Vec<_> = indices.into_iter()
+                .map(|i| Residual {
+                    sample: data[i].clone(),
+                    y: y[i],
+                    f: f[i],
+                    residual: self.loss.negative_gradient(y[i], f[i]),
+                })
+                .collect();
+
+            let mut tree = self.tree_builder.fit_with_rng(&mut residuals[..], rng);
+            self.update_leaves(&mut tree, &residuals);
+
+            for (fi, sample) in f.iter_mut().zip(data) {
+                *fi += self.learning_rate * tree.predict_leaf(sample);
+            }
+            for (fi, sample) in f_valid.iter_mut().zip(validation) {
+                *fi += self.learning_rate * tree.predict_leaf(sample);
+            }
+            estimators.push(tree);
+
+            if let (Some(patience), false) = (self.n_iter_no_change, validation.is_empty()) {
+                let loss = f64::weighted_mean(validation.iter().zip(y_valid.iter().zip(&f_valid))
+                    .map(|(s, (&y, &f))| (s.weight(), self.loss.loss(y, f))));
+                if loss < best.0 {
+                    best = (loss, round + 1);
+                } else if round + 1 - best.1 >= patience {
+                    estimators.truncate(best.1);
+                    break
+                }
+            }
+        }
+
+        GradientBoostedTrees {
+            init,
+            learning_rate: self.learning_rate,
+            estimators,
+            loss: self.loss.clone(),
+        }
+    }
+
+    /// Fit the ensemble, holding out a random validation set if early stopping is enabled.
+    fn fit_holding_out<R: Rng>(&self, data: &[S], rng: &mut R) -> GradientBoostedTrees<S, L> {
+        let n = data.len();
+        let n_valid = (self.validation_fraction * n as f64).round() as usize;
+        if self.n_iter_no_change.is_none() || n_valid == 0 || n_valid >= n {
+            return self.fit_with_rng(data, &[], rng)
+        }
+
+        let mut held_out = vec![false; n];
+        for i in sample_indices(rng, n, n_valid) {
+            held_out[i] = true;
+        }
+        let mut train = Vec::with_capacity(n - n_valid);
+        let mut valid = Vec::with_capacity(n_valid);
+        for (sample, h) in data.iter().zip(held_out) {
+            if h { valid.push(sample.clone()) } else { train.push(sample.clone()) }
+        }
+
+        self.fit_with_rng(&train, &valid, rng)
+    }
+
+    /// Replace the leaf values of `tree` with the loss-specific optimum.
+    fn update_leaves(&self, tree: &mut DeterministicTree<Residual<S>>, residuals: &[Residual<S>]) {
+        let mut leaves: Vec<(Vec<f64>, Vec<f64>, Vec<f64>)> = vec![(vec![], vec![], vec![]); tree.nodes.len()];
+        for r in residuals {
+            let leaf = &mut leaves[tree.leaf_index(r)];
+            leaf.0.push(r.y);
+            leaf.1.push(r.f);
+            leaf.2.push(r.weight());
+        }
+
+        for (node, (y, f, w)) in tree.nodes.iter_mut().zip(leaves) {
+            if let Node::Leaf(ref mut value) = *node {
+                if !y.is_empty() {
+                    *value = self.loss.leaf_value(&y, &f, &w);
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use iter_mean::IterMean;
+    use split::BestSplit;
+    use testdata::Sample;
+
+    #[test]
+    fn losses() {
+        assert_eq!(SquaredError.init(&[1.0, 2.0, 6.0], &[1.0; 3]), 3.0);
+        assert_eq!(SquaredError.init(&[1.0, 2.0, 6.0], &[2.0, 1.0, 1.0]), 2.5);
+        assert_eq!(AbsoluteError.init(&[1.0, 2.0, 6.0], &[1.0; 3]), 2.0);
+        assert_eq!(AbsoluteError.init(&[1.0, 2.0, 6.0], &[1.0, 1.0, 3.0]), 6.0);
+        assert_eq!(AbsoluteError.leaf_value(&[1.0, 2.0, 6.0, 7.0], &[0.0; 4], &[1.0; 4]), 4.0);
+        assert_eq!(Huber { delta: 1.0 }.negative_gradient(5.0, 1.0), 1.0);
+        assert_eq!(Huber { delta: 1.0 }.loss(1.5, 1.0), 0.125);
+        assert_eq!(LogLoss.init(&[0.0, 1.0], &[1.0; 2]), 0.0);
+        assert_eq!(LogLoss.transform(0.0), 0.5);
+        assert!((LogLoss.loss(1.0, 0.0) - 2f64.ln()).abs() < 1e-12);
+        assert!((LogLoss.loss(0.0, 1000.0) - 1000.0).abs() < 1e-9);
+    }
+
+    fn data<'a>(x: &'a [[f64; 1]]) -> Vec<Sample<'a, f64, f64>> {
+        x.iter().map(|xi| Sample::new(xi, (xi[0] * 0.5).sin() * 3.0)).collect()
+    }
+
+    #[test]
+    fn regression() {
+        let x: Vec<_> = (0..50).map(|i| [i as f64 / 2.0]).collect();
+        let data = data(&x);
+
+        let model = GradientBoostingBuilder::new(
+            100,
+            SquaredError,
+            DeterministicTreeBuilder::new(2, BestSplit::new()).with_max_depth(2)
+        ).fit(&data);
+
+        assert_eq!(model.n_estimators(), 100);
+        let mse = f64::mean(data.iter().map(|s| (model.predict(s) - s.y).powi(2)));
+        assert!(mse < 0.01);
+
+        let model = GradientBoostingBuilder::new(
+            100,
+            Huber { delta: 0.5 },
+            DeterministicTreeBuilder::new(2, BestSplit::new()).with_max_depth(2)
+        ).with_subsample(0.5).with_seed(1).fit(&data);
+
+        let mae = f64::mean(data.iter().map(|s| (model.predict(s) - s.y).abs()));
+        assert!(mae < 0.2);
+    }
+
+    #[test]
+    fn early_stopping() {
+        let x: Vec<_> = (0..50).map(|i| [i as f64 / 2.0]).collect();
+        let train = data(&x);
+        let x_valid: Vec<_> = (0..50).map(|i| [i as f64 / 2.0 + 0.25]).collect();
+        let valid = data(&x_valid);
+
+        let model = GradientBoostingBuilder::new(
+            1000,
+            AbsoluteError,
+            DeterministicTreeBuilder::new(2, BestSplit::new()).with_max_depth(2)
+        ).with_learning_rate(0.5).with_early_stopping(5).fit_with_validation(&train, &valid);
+
+        assert!(model.n_estimators() < 1000);
+
+        // without a validation set, `fit` holds out part of the training data
+        let model = GradientBoostingBuilder::new(
+            1000,
+            AbsoluteError,
+            DeterministicTreeBuilder::new(2, BestSplit::new()).with_max_depth(2)
+        ).with_learning_rate(0.5).with_early_stopping(5).with_validation_fraction(0.2).with_seed(3).fit(&train);
+
+        assert!(model.n_estimators() < 1000);
+    }
+
+    #[test]
+    fn classification() {
+        let x: Vec<_> = (0..40).map(|i| [i as f64]).collect();
+        let data: Vec<_> = x.iter()
+            .map(|xi| Sample::new(xi, if xi[0] >= 10.0 && xi[0] < 30.0 { 1.0 } else { 0.0 }))
+            .collect();
+
+        let model = GradientBoostingBuilder::new(
+            50,
+            LogLoss,
+            DeterministicTreeBuilder::new(2, BestSplit::new()).with_max_depth(2)
+        ).with_learning_rate(0.5).fit(&data);
+
+        for sample in &data {
+            let p = model.predict(sample);
+            assert!(p > 0.0 && p < 1.0);
+            assert_eq!(p > 0.5, sample.y == 1.0);
+        }
+    }
+
+    #[test]
+    fn residual_data() {
+        let x = [[3.0, 0.0], [1.0, 5.0], [2.0, 4.0]];
+        let data: Vec<_> = x.iter()
+            .map(|xi| Residual { sample: Sample::new(&xi[..], 0.0), y: 0.0, f: 0.0, residual: 0.0 })
+            .collect();
+
+        assert_eq!(data.feature_bounds(&0), (1.0, 3.0));
+        assert_eq!(data.feature_bounds(&1), (0.0, 5.0));
+        assert_eq!(data.all_split_features().unwrap().collect::<Vec<_>>(), vec![0, 1]);
+    }
+
+    struct Weighted(f64, f64);
+
+    impl SampleDescription for Weighted {
+        type ThetaSplit = ();
+        type ThetaLeaf = ();
+        type Feature = f64;
+        type Target = f64;
+        type Prediction = ();
+
+        fn target(&self) -> f64 { self.0 }
+        fn sample_as_split_feature(&self, _: &()) -> f64 { self.0 }
+        fn sample_predict(&self, _: &()) {}
+        fn weight(&self) -> f64 { self.1 }
+    }
+
+    #[test]
+    fn residual_weight() {
+        let r = Residual { sample: Weighted(1.0, 3.0), y: 1.0, f: 0.0, residual: 1.0 };
+        assert_eq!(r.weight(), 3.0);
+    }
+}
@@ -68,25 +68,87 @@ impl Categorical for usize {
     }
 }
 
+/// Value of a categorical split feature (see `SampleDescription::sample_as_category`).
+///
+/// Holds the id of a `Categorical` value, or nothing if the value is missing.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Category(Option<usize>);
+
+impl Category {
+    pub fn of<C: Categorical>(c: C) -> Self {
+        Category(Some(c.as_usize()))
+    }
+
+    pub fn missing() -> Self {
+        Category(None)
+    }
+
+    /// Id of the category, or `None` if it is missing
+    pub fn id(&self) -> Option<usize> {
+        self.0
+    }
+
+    pub fn is_missing(&self) -> bool {
+        self.0.is_none()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct GenericCatCounter {
-    counts: Vec<usize>,
-    total: usize,
+    counts: Vec<f64>,
+    total: f64,
 }
 
 impl GenericCatCounter {
     pub fn new() -> Self {
         GenericCatCounter {
             counts: Vec::new(),
-            total: 0,
+            total: 0.0,
         }
     }
 
     pub fn probs<F: FnMut(f64)>(&self, mut f: F) {
-        let n = self.total as f64;
+        if self.total <= 0.0 {
+            return
+        }
         for c in self.counts.iter() {
-            f(*c as f64 / n);
+            f(*c / self.total);
+        }
+    }
+
+    /// Add an observation of given category with weight `w`.
+    pub fn add_weighted<C: Categorical>(&mut self, c: C, w: f64) {
+        let i = c.as_usize();
+        if i >= self.counts.len() {
+            self.counts.resize(i + 1, 0.0);
         }
+        self.counts[i] += w;
+        self.total += w;
+    }
+
+    /// Remove an observation of given category with weight `w`.
+    pub fn remove_weighted<C: Categorical>(&mut self, c: C, w: f64) {
+        let i = c.as_usize();
+        debug_assert!(i < self.counts.len());
+        self.counts[i] -= w;
+        self.total -= w;
+    }
+
+    /// Call `f` with the id and weight of each category.
+    pub fn visit_counts<F: FnMut(usize, f64)>(&self, mut f: F) {
+        for (i, &c) in self.counts.iter().enumerate() {
+            f(i, c);
+        }
+    }
+
+    /// Sum of the weights of the observations of category `c`.
+    pub fn count<C: Categorical>(&self, c: C) -> f64 {
+        self.counts.get(c.as_usize()).cloned().unwrap_or(0.0)
+    }
+
+    /// Sum of the weights of all observations.
+    pub fn total(&self) -> f64 {
+        self.total
     }
 }
 
@@ -96,39 +158,30 @@ impl<C: Categorical> CatCount<C> for GenericCatCounter {
     }
 
     fn add_n(&mut self, c: C, n: usize) {
-        let i = c.as_usize();
-        if i >= self.counts.len() {
-            self.counts.resize(i + 1, 0);
-        }
-        self.counts[i] += n;
-        self.total += n;
+        self.add_weighted(c, n as f64)
     }
 
     fn remove(&mut self, c: C) {
-        let i = c.as_usize();
-        debug_assert!(i < self.counts.len());
-        debug_assert!(self.counts[i] > 0);
-        debug_assert!(self.total > 0);
-        self.counts[i] -= 1;
-        self.total -= 1;
+        debug_assert!(self.counts[c.as_usize()] > 0.0);
+        self.remove_weighted(c, 1.0)
     }
 
     fn probability(&self, c: C) -> f64 {
         let i = c.as_usize();
         if i < self.counts.len() {
-            self.counts[i] as f64 / self.total as f64
+            self.counts[i] / self.total
         } else {
             0.0
         }
     }
 
     fn most_frequent(&self) -> C {
-        let mut n = 0;
+        let mut n = 0.0;
         let mut c = 0;
-        for i in 0..self.counts.len() {
+        for (i, &ni) in self.counts.iter().enumerate() {
             // TODO: handle ties?
-            if self.counts[i] > n {
-                n = self.counts[i];
+            if ni > n {
+                n = ni;
                 c = i;
             }
         }
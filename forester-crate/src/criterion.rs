@@ -1,8 +1,11 @@
 use std::f64;
+use std::marker::PhantomData;
 
-use categorical::{Categorical, CatCount, GenericCatCounter};
+use categorical::{Categorical, GenericCatCounter};
 use continuous::Continuous;
 use data::{SampleDescription, TrainingData};
+use multilabel::LabelSet;
+use order_stats::WeightedOrderStatistics;
 
 /// Criterion for evaluating splits
 ///
@@ -26,9 +29,29 @@ pub trait SplitCriterion<T>
 
     /// Compute final value weighted by number of samples
     ///
-    /// This is equivalent to `.get() * n_samples`.
+    /// This is equivalent to `.get() * total_weight`, where unweighted samples have weight 1.0.
     fn get_weighted(&self) -> f64;
 
+    /// Score of a split into the samples of `left` and `right`; smaller is better.
+    ///
+    /// The default is the sum of both sides' weighted criteria. Criteria that compare the two
+    /// sides with each other (such as a two-sample test) override this, and should keep the
+    /// score no larger than the parent's `get_weighted` for splits worth making. Such scores may
+    /// be negative (the log-rank score subtracts an unbounded test statistic), so scores are only
+    /// ever compared with each other and with the parent's criterion.
+    fn split_score(left: &Self, right: &Self) -> f64 {
+        left.get_weighted() + right.get_weighted()
+    }
+
+    /// Key for ordering the categories of a categorical feature, computed from the samples of
+    /// one category.
+    ///
+    /// Criteria for which the best subset split is found by sorting the categories by this key
+    /// and splitting the sorted sequence (e.g. variance or deviance regression and binary
+    /// classification) return a value. The default `None` makes split finders fall back to a
+    /// heuristic search.
+    fn category_order_key(&self) -> Option<f64> { None }
+
     /// Construct the criterion from a data set
     fn from_dataset<S, D>(data: &D) -> Self
         where S: SampleDescription<Target=T>,
@@ -41,8 +64,10 @@ pub trait SplitCriterion<T>
 }
 
 /// Variance criterion for evaluating splits in regression tasks
+///
+/// Samples are counted according to their `weight()`.
 pub struct VarianceCriterion {
-    n: usize,
+    w: f64,
     mean: f64,
     m2: f64,
 }
@@ -52,7 +77,7 @@ where T: Continuous
 {
     fn new() -> Self {
         VarianceCriterion {
-            n: 0,
+            w: 0.0,
             mean: 0.0,
             m2: 0.0,
         }
@@ -60,39 +85,421 @@ where T: Continuous
 
     fn add_sample<S: SampleDescription<Target=T>>(&mut self, sample: &S) {
         let x = sample.target().as_float();
-        self.n += 1;
+        let wi = sample.weight();
+        self.w += wi;
         let delta = x - self.mean;
-        self.mean += delta / self.n as f64;
-        self.m2 += delta * (x - self.mean);
+        self.mean += wi * delta / self.w;
+        self.m2 += wi * delta * (x - self.mean);
     }
 
     fn remove_sample<S: SampleDescription<Target=T>>(&mut self, sample: &S) {
-        debug_assert!(self.n > 0);
+        debug_assert!(self.w > 0.0);
         let x = sample.target().as_float();
+        let wi = sample.weight();
+        if self.w - wi <= 0.0 {
+            *self = <Self as SplitCriterion<T>>::new();
+            return
+        }
         let delta = x - self.mean;
-        self.mean = (self.n as f64 * self.mean - x) / (self.n - 1) as f64;
-        self.m2 -= delta * (x - self.mean);
-        self.n -= 1;
+        self.mean = (self.w * self.mean - wi * x) / (self.w - wi);
+        self.m2 -= wi * delta * (x - self.mean);
+        self.w -= wi;
     }
 
     fn get(&self) -> f64 {
-        if self.n == 0 {
+        if self.w <= 0.0 {
             0.0
         } else {
-            self.m2 / self.n as f64
+            self.m2 / self.w
         }
     }
 
     fn get_weighted(&self) -> f64 {
         self.m2
     }
+
+    fn category_order_key(&self) -> Option<f64> {
+        Some(self.mean)
+    }
+}
+
+/// Variance criterion for evaluating splits in multi-output regression tasks
+///
+/// Targets are vectors of equal length, and the criterion is the sum of the per-output
+/// variances. (Averaging them instead would only scale the criterion by a constant and not change
+/// which split is best.) Samples are counted according to their `weight()`.
+pub struct MultiOutputVarianceCriterion {
+    w: f64,
+    mean: Vec<f64>,
+    m2: Vec<f64>,
+}
+
+impl<T> SplitCriterion<T> for MultiOutputVarianceCriterion
+    where T: AsRef<[f64]>
+{
+    fn new() -> Self {
+        MultiOutputVarianceCriterion {
+            w: 0.0,
+            mean: Vec::new(),
+            m2: Vec::new(),
+        }
+    }
+
+    fn add_sample<S: SampleDescription<Target=T>>(&mut self, sample: &S) {
+        let target = sample.target();
+        let x = target.as_ref();
+        if self.mean.is_empty() {
+            self.mean = vec![0.0; x.len()];
+            self.m2 = vec![0.0; x.len()];
+        }
+        debug_assert_eq!(self.mean.len(), x.len());
+        let wi = sample.weight();
+        self.w += wi;
+        for ((mean, m2), &xi) in self.mean.iter_mut().zip(self.m2.iter_mut()).zip(x) {
+            let delta = xi - *mean;
+            *mean += wi * delta / self.w;
+            *m2 += wi * delta * (xi - *mean);
+        }
+    }
+
+    fn remove_sample<S: SampleDescription<Target=T>>(&mut self, sample: &S) {
+        debug_assert!(self.w > 0.0);
+        let target = sample.target();
+        let x = target.as_ref();
+        let wi = sample.weight();
+        if self.w - wi <= 0.0 {
+            *self = <Self as SplitCriterion<T>>::new();
+            return
+        }
+        for ((mean, m2), &xi) in self.mean.iter_mut().zip(self.m2.iter_mut()).zip(x) {
+            let delta = xi - *mean;
+            *mean = (self.w * *mean - wi * xi) / (self.w - wi);
+            *m2 -= wi * delta * (xi - *mean);
+        }
+        self.w -= wi;
+    }
+
+    fn get(&self) -> f64 {
+        if self.w <= 0.0 {
+            0.0
+        } else {
+            self.m2.iter().sum::<f64>() / self.w
+        }
+    }
+
+    fn get_weighted(&self) -> f64 {
+        self.m2.iter().sum()
+    }
+}
+
+/// Mean absolute error criterion for evaluating splits in regression tasks
+///
+/// The criterion is the mean absolute deviation of the targets from their weighted median, which
+/// is less sensitive to outliers than the variance. Samples are counted according to their
+/// `weight()`, and adding or removing a sample takes `O(log n)` time.
+pub struct MeanAbsoluteErrorCriterion {
+    stats: WeightedOrderStatistics,
+}
+
+impl MeanAbsoluteErrorCriterion {
+    fn sum_abs_deviation(&self) -> f64 {
+        let m = match self.stats.median() {
+            Some(m) => m,
+            None => return 0.0,
+        };
+        let lower = self.stats.below(m, true);
+        let upper = self.stats.total().minus(&lower);
+        (m * lower.w - lower.wy + upper.wy - m * upper.w).max(0.0)
+    }
+}
+
+impl<T> SplitCriterion<T> for MeanAbsoluteErrorCriterion
+where T: Continuous
+{
+    fn new() -> Self {
+        MeanAbsoluteErrorCriterion {
+            stats: WeightedOrderStatistics::new(),
+        }
+    }
+
+    fn add_sample<S: SampleDescription<Target=T>>(&mut self, sample: &S) {
+        self.stats.add(sample.target().as_float(), sample.weight());
+    }
+
+    fn remove_sample<S: SampleDescription<Target=T>>(&mut self, sample: &S) {
+        self.stats.remove(sample.target().as_float(), sample.weight());
+    }
+
+    fn get(&self) -> f64 {
+        let w = self.stats.total().w;
+        if w <= 0.0 {
+            0.0
+        } else {
+            self.sum_abs_deviation() / w
+        }
+    }
+
+    fn get_weighted(&self) -> f64 {
+        self.sum_abs_deviation()
+    }
+}
+
+/// Threshold of `HuberCriterion` between quadratic and linear penalties
+pub trait HuberDelta {
+    /// Residuals up to this magnitude are penalized quadratically
+    const DELTA: f64;
+}
+
+/// Huber threshold of one, for targets whose typical inlier residuals are of the order of one
+pub struct UnitDelta;
+
+impl HuberDelta for UnitDelta {
+    const DELTA: f64 = 1.0;
+}
+
+/// Huber criterion for evaluating splits in regression tasks
+///
+/// The criterion is the mean Huber loss of the targets' deviations from their weighted median:
+/// deviations smaller than `D::DELTA` contribute quadratically (like the variance), larger ones
+/// linearly (like the absolute error). The median is used as location because the Huber
+/// M-estimator has no closed form. Samples are counted according to their `weight()`, and adding
+/// or removing a sample takes `O(log n)` time.
+pub struct HuberCriterion<D = UnitDelta> {
+    stats: WeightedOrderStatistics,
+    _delta: PhantomData<D>,
+}
+
+impl<D: HuberDelta> HuberCriterion<D> {
+    fn sum_loss(&self) -> f64 {
+        let m = match self.stats.median() {
+            Some(m) => m,
+            None => return 0.0,
+        };
+        let d = D::DELTA;
+        let total = self.stats.total();
+        let lower = self.stats.below(m - d, false);
+        let upper = total.minus(&self.stats.below(m + d, true));
+        let inner = total.minus(&lower).minus(&upper);
+
+        let quadratic = 0.5 * (inner.wy2 - 2.0 * m * inner.wy + m * m * inner.w);
+        let linear = d * (m * lower.w - lower.wy + upper.wy - m * upper.w) - 0.5 * d * d * (lower.w + upper.w);
+        quadratic.max(0.0) + linear.max(0.0)
+    }
+}
+
+impl<T, D> SplitCriterion<T> for HuberCriterion<D>
+where T: Continuous,
+      D: HuberDelta
+{
+    fn new() -> Self {
+        HuberCriterion {
+            stats: WeightedOrderStatistics::new(),
+            _delta: PhantomData,
+        }
+    }
+
+    fn add_sample<S: SampleDescription<Target=T>>(&mut self, sample: &S) {
+        self.stats.add(sample.target().as_float(), sample.weight());
+    }
+
+    fn remove_sample<S: SampleDescription<Target=T>>(&mut self, sample: &S) {
+        self.stats.remove(sample.target().as_float(), sample.weight());
+    }
+
+    fn get(&self) -> f64 {
+        let w = self.stats.total().w;
+        if w <= 0.0 {
+            0.0
+        } else {
+            self.sum_loss() / w
+        }
+    }
+
+    fn get_weighted(&self) -> f64 {
+        self.sum_loss()
+    }
+}
+
+/// Smallest mean predicted by the leaves of the deviance criteria. It keeps the deviance (and the
+/// log of predictions) finite for leaves whose training targets are all zero.
+pub const MIN_DEVIANCE_MEAN: f64 = 1e-10;
+
+/// Weighted mean of the targets in `data`, but at least `MIN_DEVIANCE_MEAN`
+fn deviance_leaf_value<S, D>(data: &D) -> f64
+    where S: SampleDescription,
+          S::Target: Continuous,
+          D: ?Sized + TrainingData<S>,
+{
+    let mut w = 0.0;
+    let mut wy = 0.0;
+    data.visit_samples(|sample| {
+        w += sample.weight();
+        wy += sample.weight() * sample.target().as_float();
+    });
+    if w > 0.0 {
+        (wy / w).max(MIN_DEVIANCE_MEAN)
+    } else {
+        MIN_DEVIANCE_MEAN
+    }
+}
+
+/// Poisson deviance criterion for evaluating splits of count targets (or other non-negative
+/// targets whose variance grows with the mean)
+///
+/// The criterion is the mean Poisson deviance of the targets from their mean. Children whose
+/// targets are all zero have zero deviance. Samples are counted according to their `weight()`.
+/// Adding a negative target panics.
+pub struct PoissonDevianceCriterion {
+    w: f64,
+    wy: f64,
+    wylogy: f64,
+}
+
+impl PoissonDevianceCriterion {
+    /// Matching leaf predictor: the weighted mean of the targets, but at least `MIN_DEVIANCE_MEAN`
+    pub fn leaf_value<S, D>(data: &D) -> f64
+        where S: SampleDescription,
+              S::Target: Continuous,
+              D: ?Sized + TrainingData<S>,
+    {
+        deviance_leaf_value(data)
+    }
+}
+
+impl<T> SplitCriterion<T> for PoissonDevianceCriterion
+where T: Continuous
+{
+    fn new() -> Self {
+        PoissonDevianceCriterion {
+            w: 0.0,
+            wy: 0.0,
+            wylogy: 0.0,
+        }
+    }
+
+    fn add_sample<S: SampleDescription<Target=T>>(&mut self, sample: &S) {
+        let y = sample.target().as_float();
+        assert!(y >= 0.0, "Poisson deviance requires non-negative targets, got {}", y);
+        let w = sample.weight();
+        self.w += w;
+        self.wy += w * y;
+        self.wylogy += w * xlogx(y);
+    }
+
+    fn remove_sample<S: SampleDescription<Target=T>>(&mut self, sample: &S) {
+        let y = sample.target().as_float();
+        let w = sample.weight();
+        if self.w - w <= 0.0 {
+            *self = <Self as SplitCriterion<T>>::new();
+            return
+        }
+        self.w -= w;
+        self.wy -= w * y;
+        self.wylogy -= w * xlogx(y);
+    }
+
+    fn get(&self) -> f64 {
+        if self.w <= 0.0 {
+            0.0
+        } else {
+            SplitCriterion::<T>::get_weighted(self) / self.w
+        }
+    }
+
+    fn get_weighted(&self) -> f64 {
+        if self.w <= 0.0 || self.wy <= 0.0 {
+            return 0.0
+        }
+        let mean = self.wy / self.w;
+        (2.0 * (self.wylogy - self.wy * mean.ln())).max(0.0)
+    }
+
+    fn category_order_key(&self) -> Option<f64> {
+        if self.w <= 0.0 { None } else { Some(self.wy / self.w) }
+    }
+}
+
+/// Gamma deviance criterion for evaluating splits of positive targets (such as durations) whose
+/// standard deviation grows with the mean
+///
+/// The criterion is the mean Gamma deviance of the targets from their mean. Targets must be
+/// positive; adding any other target panics. Samples are counted according to their `weight()`.
+pub struct GammaDevianceCriterion {
+    w: f64,
+    wy: f64,
+    wlogy: f64,
+}
+
+impl GammaDevianceCriterion {
+    /// Matching leaf predictor: the weighted mean of the targets, but at least `MIN_DEVIANCE_MEAN`
+    pub fn leaf_value<S, D>(data: &D) -> f64
+        where S: SampleDescription,
+              S::Target: Continuous,
+              D: ?Sized + TrainingData<S>,
+    {
+        deviance_leaf_value(data)
+    }
+}
+
+impl<T> SplitCriterion<T> for GammaDevianceCriterion
+where T: Continuous
+{
+    fn new() -> Self {
+        GammaDevianceCriterion {
+            w: 0.0,
+            wy: 0.0,
+            wlogy: 0.0,
+        }
+    }
+
+    fn add_sample<S: SampleDescription<Target=T>>(&mut self, sample: &S) {
+        let y = sample.target().as_float();
+        assert!(y > 0.0, "Gamma deviance requires positive targets, got {}", y);
+        let w = sample.weight();
+        self.w += w;
+        self.wy += w * y;
+        self.wlogy += w * y.ln();
+    }
+
+    fn remove_sample<S: SampleDescription<Target=T>>(&mut self, sample: &S) {
+        let y = sample.target().as_float();
+        let w = sample.weight();
+        if self.w - w <= 0.0 {
+            *self = <Self as SplitCriterion<T>>::new();
+            return
+        }
+        self.w -= w;
+        self.wy -= w * y;
+        self.wlogy -= w * y.ln();
+    }
+
+    fn get(&self) -> f64 {
+        if self.w <= 0.0 {
+            0.0
+        } else {
+            SplitCriterion::<T>::get_weighted(self) / self.w
+        }
+    }
+
+    fn get_weighted(&self) -> f64 {
+        if self.w <= 0.0 || self.wy <= 0.0 {
+            return 0.0
+        }
+        let mean = self.wy / self.w;
+        (2.0 * (self.w * mean.ln() - self.wlogy)).max(0.0)
+    }
+
+    fn category_order_key(&self) -> Option<f64> {
+        if self.w <= 0.0 { None } else { Some(self.wy / self.w) }
+    }
 }
 
 /// GINI criterion for evaluating splits in a classification task with an
 /// arbitrary number of classes.
+///
+/// Samples are counted according to their `weight()`.
 pub struct GiniCriterion {
     counts: GenericCatCounter,
-    n: usize,
 }
 
 impl<T> SplitCriterion<T> for GiniCriterion
@@ -101,18 +508,15 @@ where T: Categorical
     fn new() -> Self {
         GiniCriterion {
             counts: GenericCatCounter::new(),
-            n: 0,
         }
     }
 
     fn add_sample<S: SampleDescription<Target=T>>(&mut self, sample: &S) {
-        self.counts.add(sample.target());
-        self.n += 1;
+        self.counts.add_weighted(sample.target(), sample.weight());
     }
 
     fn remove_sample<S: SampleDescription<Target=T>>(&mut self, sample: &S) {
-        self.counts.remove(sample.target());
-        self.n -= 1;
+        self.counts.remove_weighted(sample.target(), sample.weight());
     }
 
     fn get(&self) -> f64 {
@@ -124,6 +528,692 @@ where T: Categorical
     fn get_weighted(&self) -> f64 {
         let mut gini = 0.0;
         self.counts.probs(|p| gini += p * (1.0 - p));
-        gini * self.n as f64
+        gini * self.counts.total()
+    }
+
+    fn category_order_key(&self) -> Option<f64> {
+        binary_order_key(&self.counts)
+    }
+}
+
+/// With at most two classes, the mean class id orders categories by the frequency of the second
+/// class.
+fn binary_order_key(counts: &GenericCatCounter) -> Option<f64> {
+    let mut n_classes = 0;
+    let mut sum = 0.0;
+    counts.visit_counts(|c, w| {
+        if w > 0.0 {
+            n_classes += 1;
+            sum += c as f64 * w;
+        }
+    });
+    if n_classes <= 2 {
+        Some(sum / counts.total())
+    } else {
+        None
+    }
+}
+
+/// GINI criterion for evaluating splits in a multi-label classification task
+///
+/// Each label is treated as a binary classification, and the criterion is the sum of the
+/// per-label GINI impurities (the mean impurity times the number of labels). Samples are counted
+/// according to their `weight()`. Adding or removing a sample takes time proportional to its
+/// number of labels.
+pub struct MultiLabelGiniCriterion {
+    w: f64,
+    counts: Vec<f64>,
+    sum: f64,
+    sum_squares: f64,
+}
+
+impl MultiLabelGiniCriterion {
+    fn update(&mut self, labels: &LabelSet, w: f64) {
+        let counts = &mut self.counts;
+        let mut sum = self.sum;
+        let mut sum_squares = self.sum_squares;
+        labels.visit_labels(|i| {
+            if i >= counts.len() {
+                counts.resize(i + 1, 0.0);
+            }
+            let before = counts[i];
+            counts[i] += w;
+            sum += w;
+            sum_squares += counts[i] * counts[i] - before * before;
+        });
+        self.sum = sum;
+        self.sum_squares = sum_squares;
+        self.w += w;
+    }
+}
+
+impl SplitCriterion<LabelSet> for MultiLabelGiniCriterion {
+    fn new() -> Self {
+        MultiLabelGiniCriterion {
+            w: 0.0,
+            counts: Vec::new(),
+            sum: 0.0,
+            sum_squares: 0.0,
+        }
+    }
+
+    fn add_sample<S: SampleDescription<Target=LabelSet>>(&mut self, sample: &S) {
+        self.update(&sample.target(), sample.weight());
+    }
+
+    fn remove_sample<S: SampleDescription<Target=LabelSet>>(&mut self, sample: &S) {
+        if self.w - sample.weight() <= 0.0 {
+            *self = Self::new();
+            return
+        }
+        self.update(&sample.target(), -sample.weight());
+    }
+
+    fn get(&self) -> f64 {
+        if self.w <= 0.0 {
+            0.0
+        } else {
+            self.get_weighted() / self.w
+        }
+    }
+
+    fn get_weighted(&self) -> f64 {
+        // sum over labels of 2 * w * p * (1 - p), with p = count / w
+        if self.w <= 0.0 {
+            0.0
+        } else {
+            (2.0 * (self.sum - self.sum_squares / self.w)).max(0.0)
+        }
+    }
+}
+
+/// Entropy criterion (information gain) for evaluating splits in a classification task with an
+/// arbitrary number of classes.
+///
+/// The entropy is measured in nats. Samples are counted according to their `weight()`. The sum of
+/// `w * ln(w)` over the class weights is updated with each sample, so evaluating the criterion
+/// does not depend on the number of classes.
+pub struct EntropyCriterion {
+    counts: GenericCatCounter,
+    sum_wlogw: f64,
+}
+
+/// `x * ln(x)`, continuously extended to `0.0` at `x <= 0.0`
+fn xlogx(x: f64) -> f64 {
+    if x <= 0.0 { 0.0 } else { x * x.ln() }
+}
+
+impl EntropyCriterion {
+    fn update<T: Categorical>(&mut self, c: T, w: f64) {
+        let before = self.counts.count(c.as_usize());
+        self.counts.add_weighted(c, w);
+        self.sum_wlogw += xlogx(before + w) - xlogx(before);
+    }
+}
+
+impl<T> SplitCriterion<T> for EntropyCriterion
+where T: Categorical
+{
+    fn new() -> Self {
+        EntropyCriterion {
+            counts: GenericCatCounter::new(),
+            sum_wlogw: 0.0,
+        }
+    }
+
+    fn add_sample<S: SampleDescription<Target=T>>(&mut self, sample: &S) {
+        self.update(sample.target(), sample.weight());
+    }
+
+    fn remove_sample<S: SampleDescription<Target=T>>(&mut self, sample: &S) {
+        self.update(sample.target(), -sample.weight());
+    }
+
+    fn get(&self) -> f64 {
+        let total = self.counts.total();
+        if total <= 0.0 {
+            0.0
+        } else {
+            (total.ln() - self.sum_wlogw / total).max(0.0)
+        }
+    }
+
+    fn get_weighted(&self) -> f64 {
+        (xlogx(self.counts.total()) - self.sum_wlogw).max(0.0)
+    }
+
+    fn category_order_key(&self) -> Option<f64> {
+        binary_order_key(&self.counts)
+    }
+}
+
+/// Ridge added to the covariance diagonal by `LogDetCriterion`
+const LOG_DET_RIDGE: f64 = 1e-6;
+
+/// Unsupervised criterion for density estimation.
+///
+/// The criterion is the log-determinant of the samples' covariance matrix, which is the entropy of
+/// a Gaussian fitted to the samples up to a constant. Samples are points in a `d`-dimensional
+/// space, and their target must return the point's coordinates (there is no supervised target).
+///
+/// A small ridge is added to the diagonal of the covariance, and the criterion is shifted so that
+/// it is zero when all samples coincide. Features should therefore be scaled to the order of one.
+pub struct LogDetCriterion {
+    n: usize,
+    mean: Vec<f64>,
+    m2: Vec<f64>,
+}
+
+impl LogDetCriterion {
+    fn dim(&mut self, d: usize) -> usize {
+        if self.mean.is_empty() {
+            self.mean = vec![0.0; d];
+            self.m2 = vec![0.0; d * d];
+        }
+        debug_assert_eq!(self.mean.len(), d);
+        d
+    }
+
+    /// Rank-one update of the scatter matrix with `sign * a * b'`
+    fn update_m2(&mut self, a: &[f64], b: &[f64], sign: f64) {
+        for (row, ai) in self.m2.chunks_mut(b.len()).zip(a) {
+            for (m, bj) in row.iter_mut().zip(b) {
+                *m += sign * ai * bj;
+            }
+        }
+    }
+}
+
+impl<T> SplitCriterion<T> for LogDetCriterion
+    where T: AsRef<[f64]>
+{
+    fn new() -> Self {
+        LogDetCriterion {
+            n: 0,
+            mean: Vec::new(),
+            m2: Vec::new(),
+        }
+    }
+
+    fn add_sample<S: SampleDescription<Target=T>>(&mut self, sample: &S) {
+        let target = sample.target();
+        let x = target.as_ref();
+        self.dim(x.len());
+        self.n += 1;
+        let n = self.n as f64;
+        let delta: Vec<f64> = x.iter().zip(&self.mean).map(|(xi, m)| xi - m).collect();
+        for (m, di) in self.mean.iter_mut().zip(&delta) {
+            *m += di / n;
+        }
+        let delta2: Vec<f64> = x.iter().zip(&self.mean).map(|(xi, m)| xi - m).collect();
+        self.update_m2(&delta, &delta2, 1.0);
+    }
+
+    fn remove_sample<S: SampleDescription<Target=T>>(&mut self, sample: &S) {
+        debug_assert!(self.n > 0);
+        let target = sample.target();
+        let x = target.as_ref();
+        let d = self.dim(x.len());
+        if self.n == 1 {
+            self.mean = vec![0.0; d];
+            self.m2 = vec![0.0; d * d];
+        } else {
+            let n = self.n as f64;
+            let delta: Vec<f64> = x.iter().zip(&self.mean).map(|(xi, m)| xi - m).collect();
+            for (m, xi) in self.mean.iter_mut().zip(x) {
+                *m = (n * *m - xi) / (n - 1.0);
+            }
+            let delta2: Vec<f64> = x.iter().zip(&self.mean).map(|(xi, m)| xi - m).collect();
+            self.update_m2(&delta, &delta2, -1.0);
+        }
+        self.n -= 1;
+    }
+
+    fn get(&self) -> f64 {
+        if self.n == 0 {
+            return 0.0
+        }
+
+        // Cholesky decomposition of the regularized covariance matrix
+        let d = self.mean.len();
+        let mut l = vec![0.0; d * d];
+        let mut logdet = 0.0;
+        for j in 0..d {
+            let mut s = self.m2[j * d + j] / self.n as f64 + LOG_DET_RIDGE;
+            for k in 0..j {
+                s -= l[j * d + k] * l[j * d + k];
+            }
+            let ljj = s.max(LOG_DET_RIDGE).sqrt();
+            l[j * d + j] = ljj;
+            logdet += 2.0 * ljj.ln();
+            for i in j + 1..d {
+                let mut s = self.m2[i * d + j] / self.n as f64;
+                for k in 0..j {
+                    s -= l[i * d + k] * l[j * d + k];
+                }
+                l[i * d + j] = s / ljj;
+            }
+        }
+
+        (logdet - d as f64 * LOG_DET_RIDGE.ln()).max(0.0)
+    }
+
+    fn get_weighted(&self) -> f64 {
+        SplitCriterion::<T>::get(self) * self.n as f64
+    }
+}
+
+
+/// Criterion that rates every split as perfect
+///
+/// Useful for unsupervised methods such as isolation forests, whose samples may not have a
+/// meaningful target.
+pub struct NoCriterion;
+
+impl<T> SplitCriterion<T> for NoCriterion {
+    fn new() -> Self {
+        NoCriterion
+    }
+
+    fn add_sample<S: SampleDescription<Target=T>>(&mut self, _sample: &S) {}
+
+    fn remove_sample<S: SampleDescription<Target=T>>(&mut self, _sample: &S) {}
+
+    fn get(&self) -> f64 {
+        0.0
+    }
+
+    fn get_weighted(&self) -> f64 {
+        0.0
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use data::feature_bounds_of;
+    use testdata::ClassSample;
+
+    struct Point(Vec<f64>);
+
+    impl SampleDescription for Point {
+        type ThetaSplit = usize;
+        type ThetaLeaf = ();
+        type Feature = f64;
+        type Target = Vec<f64>;
+        type Prediction = ();
+
+        fn target(&self) -> Vec<f64> { self.0.clone() }
+        fn sample_as_split_feature(&self, theta: &usize) -> f64 { self.0[*theta] }
+        fn sample_predict(&self, _: &()) {}
+    }
+
+    struct Weighted(f64, f64);
+
+    impl SampleDescription for Weighted {
+        type ThetaSplit = ();
+        type ThetaLeaf = ();
+        type Feature = f64;
+        type Target = f64;
+        type Prediction = ();
+
+        fn target(&self) -> f64 { self.0 }
+        fn sample_as_split_feature(&self, _: &()) -> f64 { self.0 }
+        fn sample_predict(&self, _: &()) {}
+        fn weight(&self) -> f64 { self.1 }
+    }
+
+    #[test]
+    fn weighted_variance() {
+        let mut crit: VarianceCriterion = SplitCriterion::<f64>::new();
+        crit.add_sample(&Weighted(1.0, 3.0));
+        crit.add_sample(&Weighted(5.0, 1.0));
+        assert_eq!(SplitCriterion::<f64>::get(&crit), 3.0);
+        assert_eq!(SplitCriterion::<f64>::get_weighted(&crit), 12.0);
+
+        crit.add_sample(&Weighted(2.0, 2.0));
+        crit.remove_sample(&Weighted(2.0, 2.0));
+        assert!((SplitCriterion::<f64>::get(&crit) - 3.0).abs() < 1e-12);
+
+        crit.remove_sample(&Weighted(5.0, 1.0));
+        assert_eq!(SplitCriterion::<f64>::get(&crit), 0.0);
+        crit.remove_sample(&Weighted(1.0, 3.0));
+        assert_eq!(SplitCriterion::<f64>::get(&crit), 0.0);
+    }
+
+    /// Number of samples left of the best split of the ordered targets `ys`
+    fn best_position<C: SplitCriterion<f64>>(ys: &[f64]) -> usize {
+        let mut left = C::new();
+        let mut right = C::new();
+        for &y in ys {
+            right.add_sample(&Weighted(y, 1.0));
+        }
+        let mut best = (f64::INFINITY, 0);
+        for (i, &y) in ys[..ys.len() - 1].iter().enumerate() {
+            left.add_sample(&Weighted(y, 1.0));
+            right.remove_sample(&Weighted(y, 1.0));
+            let crit = left.get_weighted() + right.get_weighted();
+            if crit < best.0 - 1e-12 {
+                best = (crit, i + 1);
+            }
+        }
+        best.1
+    }
+
+    struct Multi(Vec<f64>, f64);
+
+    impl SampleDescription for Multi {
+        type ThetaSplit = ();
+        type ThetaLeaf = ();
+        type Feature = f64;
+        type Target = Vec<f64>;
+        type Prediction = ();
+
+        fn target(&self) -> Vec<f64> { self.0.clone() }
+        fn sample_as_split_feature(&self, _: &()) -> f64 { 0.0 }
+        fn sample_predict(&self, _: &()) {}
+        fn weight(&self) -> f64 { self.1 }
+    }
+
+    #[test]
+    fn multi_output_variance() {
+        let mut crit: MultiOutputVarianceCriterion = SplitCriterion::<Vec<f64>>::new();
+        crit.add_sample(&Multi(vec![1.0, 0.0], 3.0));
+        crit.add_sample(&Multi(vec![5.0, 0.0], 1.0));
+        assert_eq!(SplitCriterion::<Vec<f64>>::get(&crit), 3.0);
+
+        crit.add_sample(&Multi(vec![0.0, 2.0], 1.0));
+        crit.add_sample(&Multi(vec![0.0, -2.0], 1.0));
+        crit.remove_sample(&Multi(vec![1.0, 0.0], 3.0));
+        crit.remove_sample(&Multi(vec![5.0, 0.0], 1.0));
+        assert!((SplitCriterion::<Vec<f64>>::get(&crit) - 4.0).abs() < 1e-12);
+        assert!((SplitCriterion::<Vec<f64>>::get_weighted(&crit) - 8.0).abs() < 1e-12);
+
+        crit.add_sample(&Multi(vec![0.0, 2.0], 1.0));
+        crit.remove_sample(&Multi(vec![0.0, -2.0], 1.0));
+        crit.remove_sample(&Multi(vec![0.0, 2.0], 1.0));
+        assert!(SplitCriterion::<Vec<f64>>::get(&crit).abs() < 1e-12);
+    }
+
+    #[test]
+    fn multi_label_gini() {
+        use testdata::TagSample;
+
+        let x = [0.0];
+        let a = TagSample::new(&x, vec![0u8].into_iter().collect());
+        let b = TagSample::new(&x, vec![0u8, 1].into_iter().collect());
+        let c = TagSample::new(&x, vec![2u8].into_iter().collect());
+
+        let mut crit = MultiLabelGiniCriterion::new();
+        crit.add_sample(&a);
+        crit.add_sample(&b);
+        assert_eq!(crit.get(), 0.5);
+        assert_eq!(crit.get_weighted(), 1.0);
+
+        // 2/3 * 1/3 * 2 for labels 0 and 2, and 1/3 * 2/3 * 2 for label 1
+        crit.add_sample(&c);
+        assert!((crit.get() - 4.0 / 3.0).abs() < 1e-12);
+
+        crit.remove_sample(&b);
+        crit.remove_sample(&c);
+        assert_eq!(crit.get(), 0.0);
+    }
+
+    #[test]
+    fn robust_criteria() {
+        let mut mae: MeanAbsoluteErrorCriterion = SplitCriterion::<f64>::new();
+        let mut huber: HuberCriterion = SplitCriterion::<f64>::new();
+        for &y in &[3.0, 100.0, 1.0, 2.0] {
+            mae.add_sample(&Weighted(y, 1.0));
+            huber.add_sample(&Weighted(y, 1.0));
+        }
+        assert_eq!(SplitCriterion::<f64>::get_weighted(&mae), 100.0);
+        assert_eq!(SplitCriterion::<f64>::get(&mae), 25.0);
+        assert_eq!(SplitCriterion::<f64>::get_weighted(&huber), 98.5);
+        // sorting categories by their median is not exact, so subset splits are searched greedily
+        assert_eq!(SplitCriterion::<f64>::category_order_key(&mae), None);
+        assert_eq!(SplitCriterion::<f64>::category_order_key(&huber), None);
+
+        struct HalfDelta;
+        impl HuberDelta for HalfDelta { const DELTA: f64 = 0.5; }
+        struct LargeDelta;
+        impl HuberDelta for LargeDelta { const DELTA: f64 = 100.0; }
+        let mut half: HuberCriterion<HalfDelta> = SplitCriterion::<f64>::new();
+        let mut large: HuberCriterion<LargeDelta> = SplitCriterion::<f64>::new();
+        for &y in &[3.0, 100.0, 1.0, 2.0] {
+            half.add_sample(&Weighted(y, 1.0));
+            large.add_sample(&Weighted(y, 1.0));
+        }
+        assert_eq!(SplitCriterion::<f64>::get_weighted(&half), 49.625);
+        assert_eq!(SplitCriterion::<f64>::get_weighted(&large), 4803.0);
+
+        mae.add_sample(&Weighted(2.5, 2.0));
+        assert_eq!(SplitCriterion::<f64>::get_weighted(&mae), 100.0);
+        mae.remove_sample(&Weighted(2.5, 2.0));
+        mae.remove_sample(&Weighted(100.0, 1.0));
+        assert_eq!(SplitCriterion::<f64>::get_weighted(&mae), 2.0);
+        for &y in &[1.0, 2.0, 3.0] {
+            mae.remove_sample(&Weighted(y, 1.0));
+        }
+        assert_eq!(SplitCriterion::<f64>::get(&mae), 0.0);
+
+        // an outlier pulls the variance's split away from the step, but not the robust criteria's
+        let mut ys = vec![0.0; 10];
+        ys[5] = 1000.0;
+        ys.extend(vec![10.0; 10]);
+        assert_eq!(best_position::<VarianceCriterion>(&ys), 6);
+        assert_eq!(best_position::<MeanAbsoluteErrorCriterion>(&ys), 10);
+        assert_eq!(best_position::<HuberCriterion>(&ys), 10);
+    }
+
+    /// Sample with a count target
+    #[derive(Clone)]
+    struct Count(f64, f64);
+
+    impl SampleDescription for Count {
+        type ThetaSplit = ();
+        type ThetaLeaf = f64;
+        type Feature = f64;
+        type Target = f64;
+        type Prediction = f64;
+
+        fn target(&self) -> f64 { self.1 }
+        fn sample_as_split_feature(&self, _: &()) -> f64 { self.0 }
+        fn sample_predict(&self, w: &f64) -> f64 { *w }
+    }
+
+    impl TrainingData<Count> for [Count] {
+        type Criterion = PoissonDevianceCriterion;
+
+        fn n_samples(&self) -> usize { self.len() }
+        fn gen_split_feature<R: Rng>(&self, _: &mut R) {}
+        fn all_split_features(&self) -> Option<Box<Iterator<Item=()>>> { Some(Box::new(Some(()).into_iter())) }
+        fn train_leaf_predictor(&self) -> f64 { PoissonDevianceCriterion::leaf_value(self) }
+
+        fn feature_bounds(&self, theta: &()) -> (f64, f64) {
+            feature_bounds_of(self, theta)
+        }
+    }
+
+    #[test]
+    fn deviance() {
+        let ys = [(0.0, 1.0), (1.0, 2.0), (3.0, 1.0), (4.0, 0.5)];
+        let (w, wy) = ys.iter().fold((0.0, 0.0), |(w, wy), &(y, wi)| (w + wi, wy + wi * y));
+        let mean = wy / w;
+
+        let mut poisson: PoissonDevianceCriterion = SplitCriterion::<f64>::new();
+        for &(y, wi) in &ys {
+            poisson.add_sample(&Weighted(y, wi));
+        }
+        let expected: f64 = ys.iter().map(|&(y, wi)| 2.0 * wi * (xlogx(y) - y * mean.ln() - y + mean)).sum();
+        assert!((SplitCriterion::<f64>::get_weighted(&poisson) - expected).abs() < 1e-12);
+        assert!((SplitCriterion::<f64>::get(&poisson) - expected / w).abs() < 1e-12);
+
+        // zero-mean children don't produce NaN
+        let mut zeros: PoissonDevianceCriterion = SplitCriterion::<f64>::new();
+        zeros.add_sample(&Weighted(0.0, 1.0));
+        zeros.add_sample(&Weighted(0.0, 2.0));
+        assert_eq!(SplitCriterion::<f64>::get(&zeros), 0.0);
+        poisson.remove_sample(&Weighted(1.0, 2.0));
+        poisson.remove_sample(&Weighted(3.0, 1.0));
+        poisson.remove_sample(&Weighted(4.0, 0.5));
+        assert_eq!(SplitCriterion::<f64>::get(&poisson), 0.0);
+
+        let ys = [(0.5, 1.0), (1.0, 2.0), (3.0, 1.0), (4.0, 0.5)];
+        let (w, wy) = ys.iter().fold((0.0, 0.0), |(w, wy), &(y, wi)| (w + wi, wy + wi * y));
+        let mean = wy / w;
+
+        let mut gamma: GammaDevianceCriterion = SplitCriterion::<f64>::new();
+        for &(y, wi) in &ys {
+            gamma.add_sample(&Weighted(y, wi));
+        }
+        let expected: f64 = ys.iter().map(|&(y, wi)| 2.0 * wi * ((y - mean) / mean - (y / mean).ln())).sum();
+        assert!((SplitCriterion::<f64>::get_weighted(&gamma) - expected).abs() < 1e-12);
+        gamma.remove_sample(&Weighted(0.5, 1.0));
+        gamma.remove_sample(&Weighted(3.0, 1.0));
+        gamma.remove_sample(&Weighted(4.0, 0.5));
+        assert!(SplitCriterion::<f64>::get(&gamma).abs() < 1e-12);
+    }
+
+    #[test]
+    #[should_panic(expected = "non-negative targets")]
+    fn poisson_negative_target() {
+        let mut poisson: PoissonDevianceCriterion = SplitCriterion::<f64>::new();
+        poisson.add_sample(&Weighted(-1.0, 1.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "positive targets")]
+    fn gamma_zero_target() {
+        let mut gamma: GammaDevianceCriterion = SplitCriterion::<f64>::new();
+        gamma.add_sample(&Weighted(0.0, 1.0));
+    }
+
+    #[test]
+    fn poisson_tree() {
+        use dtree::DeterministicTreeBuilder;
+        use split::BestSplit;
+
+        let mut data: Vec<_> = (0..20)
+            .map(|i| Count(i as f64, if i < 10 { 0.0 } else { [3.0, 5.0, 4.0][i % 3] }))
+            .collect();
+
+        let tree = DeterministicTreeBuilder::new(2, BestSplit::new()).with_max_depth(1).fit(&mut data[..]);
+        assert_eq!(tree.predict(&Count(5.0, 0.0)), MIN_DEVIANCE_MEAN);
+        assert_eq!(tree.predict(&Count(15.0, 0.0)), 4.1);
+    }
+
+    /// The first ten samples of each class in the iris data set
+    const IRIS: [([f64; 4], u8); 30] = [
+        ([5.1, 3.5, 1.4, 0.2], 0), ([4.9, 3.0, 1.4, 0.2], 0), ([4.7, 3.2, 1.3, 0.2], 0),
+        ([4.6, 3.1, 1.5, 0.2], 0), ([5.0, 3.6, 1.4, 0.2], 0), ([5.4, 3.9, 1.7, 0.4], 0),
+        ([4.6, 3.4, 1.4, 0.3], 0), ([5.0, 3.4, 1.5, 0.2], 0), ([4.4, 2.9, 1.4, 0.2], 0),
+        ([4.9, 3.1, 1.5, 0.1], 0),
+        ([7.0, 3.2, 4.7, 1.4], 1), ([6.4, 3.2, 4.5, 1.5], 1), ([6.9, 3.1, 4.9, 1.5], 1),
+        ([5.5, 2.3, 4.0, 1.3], 1), ([6.5, 2.8, 4.6, 1.5], 1), ([5.7, 2.8, 4.5, 1.3], 1),
+        ([6.3, 3.3, 4.7, 1.6], 1), ([4.9, 2.4, 3.3, 1.0], 1), ([6.6, 2.9, 4.6, 1.3], 1),
+        ([5.2, 2.7, 3.9, 1.4], 1),
+        ([6.3, 3.3, 6.0, 2.5], 2), ([5.8, 2.7, 5.1, 1.9], 2), ([7.1, 3.0, 5.9, 2.1], 2),
+        ([6.3, 2.9, 5.6, 1.8], 2), ([6.5, 3.0, 5.8, 2.2], 2), ([7.6, 3.0, 6.6, 2.1], 2),
+        ([4.9, 2.5, 4.5, 1.7], 2), ([7.3, 2.9, 6.3, 1.8], 2), ([6.7, 2.5, 5.8, 1.8], 2),
+        ([7.2, 3.6, 6.1, 2.5], 2),
+    ];
+
+    /// Best (feature, threshold) found by sweeping the sorted samples with criterion `C`
+    fn best_split<C: SplitCriterion<u8>>(data: &[ClassSample<f64>]) -> (usize, f64) {
+        let mut best = (f64::INFINITY, 0, 0.0);
+        for theta in 0..4 {
+            let mut sorted = data.to_vec();
+            sorted.sort_by(|a, b| a.x[theta].partial_cmp(&b.x[theta]).unwrap());
+            let mut left = C::new();
+            let mut right = C::new();
+            for sample in &sorted {
+                right.add_sample(sample);
+            }
+            for (i, sample) in sorted[..sorted.len() - 1].iter().enumerate() {
+                left.add_sample(sample);
+                right.remove_sample(sample);
+                if sample.x[theta] == sorted[i + 1].x[theta] {
+                    continue
+                }
+                let crit = left.get_weighted() + right.get_weighted();
+                if crit < best.0 - 1e-12 {
+                    best = (crit, theta, sample.x[theta]);
+                }
+            }
+        }
+        (best.1, best.2)
+    }
+
+    #[test]
+    fn entropy() {
+        let data: Vec<_> = IRIS.iter().map(|&(ref x, y)| ClassSample::new(x, y)).collect();
+
+        let mut crit: EntropyCriterion = SplitCriterion::<u8>::new();
+        for sample in &data {
+            crit.add_sample(sample);
+        }
+        assert!((SplitCriterion::<u8>::get(&crit) - 3.0f64.ln()).abs() < 1e-12);
+        assert!((SplitCriterion::<u8>::get_weighted(&crit) - 30.0 * 3.0f64.ln()).abs() < 1e-12);
+
+        for sample in &data[10..] {
+            crit.remove_sample(sample);
+        }
+        assert!(SplitCriterion::<u8>::get(&crit).abs() < 1e-12);
+        for sample in &data[..10] {
+            crit.remove_sample(sample);
+        }
+        assert_eq!(SplitCriterion::<u8>::get(&crit), 0.0);
+
+        // both criteria separate setosa at the first feature that allows it
+        assert_eq!(best_split::<EntropyCriterion>(&data), (2, 1.7));
+        assert_eq!(best_split::<GiniCriterion>(&data), (2, 1.7));
+
+        // versicolor vs. virginica
+        assert_eq!(best_split::<EntropyCriterion>(&data[10..]), best_split::<GiniCriterion>(&data[10..]));
+    }
+
+    fn get(c: &LogDetCriterion) -> f64 {
+        SplitCriterion::<Vec<f64>>::get(c)
+    }
+
+    #[test]
+    fn log_det() {
+        let points = [
+            Point(vec![0.0, 1.0]),
+            Point(vec![1.0, 3.0]),
+            Point(vec![2.0, 2.0]),
+            Point(vec![5.0, 0.0]),
+        ];
+
+        let mut crit: LogDetCriterion = SplitCriterion::<Vec<f64>>::new();
+        assert_eq!(get(&crit), 0.0);
+
+        crit.add_sample(&points[0]);
+        assert_eq!(get(&crit), 0.0);
+
+        for p in &points[1..] {
+            crit.add_sample(p);
+        }
+
+        // covariance is [[3.5, -1.25], [-1.25, 1.25]]
+        let cov_det = (3.5 + LOG_DET_RIDGE) * (1.25 + LOG_DET_RIDGE) - 1.25 * 1.25;
+        let expected = cov_det.ln() - 2.0 * LOG_DET_RIDGE.ln();
+        assert!((get(&crit) - expected).abs() < 1e-9);
+        assert!((SplitCriterion::<Vec<f64>>::get_weighted(&crit) - 4.0 * expected).abs() < 1e-8);
+
+        crit.remove_sample(&points[3]);
+        let mut reference: LogDetCriterion = SplitCriterion::<Vec<f64>>::new();
+        for p in &points[..3] {
+            reference.add_sample(p);
+        }
+        assert!((get(&crit) - get(&reference)).abs() < 1e-9);
+
+        for p in &points[..3] {
+            crit.remove_sample(p);
+        }
+        assert_eq!(get(&crit), 0.0);
     }
 }
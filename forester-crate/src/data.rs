@@ -2,10 +2,14 @@
 //!
 //! This module defines the traits required to define data sets for use with the forester crate.
 
+use std::slice;
+
+use num_traits::Bounded;
+use rand::Rng;
 use rand::distributions::range::SampleRange;
-use rand::thread_rng;
 
-use array_ops::{Partition, resample};
+use array_ops::{Partition, resample_with_counts};
+use categorical::Category;
 use criterion::SplitCriterion;
 use split::Split;
 use split_between::SplitBetween;
@@ -31,10 +35,67 @@ pub trait SampleDescription {
     fn target(&self) -> Self::Target;
 
     /// Compute the value of a leaf feature for a given sample
+    ///
+    /// A missing value is represented by a feature that does not compare to itself, such as
+    /// `NAN` (see `is_missing`).
     fn sample_as_split_feature(&self, theta: &Self::ThetaSplit) -> Self::Feature;
 
+    /// Return the category of a nominal split feature, or `None` if the feature is ordered.
+    ///
+    /// Categorical features are split into subsets of categories rather than at a threshold.
+    /// A feature must be either categorical or ordered for all samples; samples whose category is
+    /// unknown return `Some(Category::missing())`.
+    fn sample_as_category(&self, _theta: &Self::ThetaSplit) -> Option<Category> { None }
+
     /// Compute the leaf prediction for a given sample
     fn sample_predict(&self, w: &Self::ThetaLeaf) -> Self::Prediction;
+
+    /// Weight of the sample in training; unweighted samples count as 1.0
+    fn weight(&self) -> f64 { 1.0 }
+}
+
+/// Return true if the feature value `x` is missing.
+///
+/// Values that can't be compared to themselves (e.g. floating point `NAN`) are considered missing.
+pub fn is_missing<F: PartialOrd>(x: &F) -> bool {
+    x.partial_cmp(x).is_none()
+}
+
+/// A sample that wraps another sample, such as the reweighted samples of boosting ensembles.
+pub trait WrappedSample {
+    type Inner;
+
+    /// The wrapped sample
+    fn inner(&self) -> &Self::Inner;
+}
+
+/// Data set of the first sample wrapped by `data`, without copying any samples.
+///
+/// Data sets of wrapped samples delegate `gen_split_feature` and `all_split_features` to it. Like
+/// the data sets in this crate, the inner data set must draw split features from the feature space
+/// rather than from the values of individual samples.
+///
+/// Panics if `data` is empty; split features are only generated for nodes with samples.
+pub fn first_inner<W: WrappedSample>(data: &[W]) -> &[W::Inner] {
+    slice::from_ref(data.first().expect("no samples to generate split features from").inner())
+}
+
+/// Minimum and maximum value of a feature over `samples`.
+///
+/// Data sets use this to implement `TrainingData::feature_bounds`. Features that compare false
+/// with everything (e.g. missing `NAN` values) are ignored.
+pub fn feature_bounds_of<'a, S, I>(samples: I, theta: &S::ThetaSplit) -> (S::Feature, S::Feature)
+    where S: 'a + SampleDescription,
+          S::Feature: Bounded + Clone,
+          I: IntoIterator<Item=&'a S>
+{
+    samples.into_iter()
+        .map(|sample| sample.sample_as_split_feature(theta))
+        .fold((S::Feature::max_value(), S::Feature::min_value()),
+              |(min, max), x| {
+                  (if x < min {x.clone()} else {min},
+                   if x > max {x} else {max})
+              })
 }
 
 /// Data set that can be used for training decision trees
@@ -46,8 +107,15 @@ pub trait TrainingData<Sample>: DataSet<Sample>
     /// Return number of samples in the data set
     fn n_samples(&self) -> usize;
 
-    /// Generate a new split feature (typically, this will be randomized)
-    fn gen_split_feature(&self) -> Sample::ThetaSplit;
+    /// Return the sum of all sample weights in the data set
+    fn total_weight(&self) -> f64 {
+        let mut w = 0.0;
+        self.visit_samples(|sample| w += sample.weight());
+        w
+    }
+
+    /// Generate a new split feature (typically, this will be randomized using `rng`)
+    fn gen_split_feature<R: Rng>(&self, rng: &mut R) -> Sample::ThetaSplit;
 
     /// Return an iterator over all features.
     ///
@@ -69,21 +137,27 @@ pub trait DataSet<Sample>
     /// Partition data set in-place according to a split
     fn partition_data(&mut self, split: &Split<Sample::ThetaSplit, Sample::Feature>) -> (&mut Self, &mut Self);
 
-    /// Sort data set in-place by feature
+    /// Sort data set in-place by feature; samples where the feature is missing come last
     fn sort_data(&mut self, theta: &Sample::ThetaSplit);
 
-    /// Draw `n` samples from this data set with replacement
-    fn bootstrap_resample(&self, n: usize) -> Vec<Sample>;
+    /// Draw `n` samples from this data set with replacement.
+    ///
+    /// Also returns how often each sample was drawn (the in-bag counts), in the order the samples
+    /// are visited by `visit_samples`.
+    fn bootstrap_resample<R: Rng>(&self, n: usize, rng: &mut R) -> (Vec<Sample>, Vec<usize>);
 
     /// call `visitor` for each sample in the data set
     fn visit_samples<F: FnMut(&Sample)>(&self, visitor: F);
+
+    /// Return the first sample visited by `visit_samples`, or `None` if the data set is empty
+    fn first_sample(&self) -> Option<&Sample>;
 }
 
 impl<Sample> DataSet<Sample> for [Sample]
     where Sample: SampleDescription + Clone
 {
     fn partition_data(&mut self, split: &Split<Sample::ThetaSplit, Sample::Feature>) -> (&mut Self, &mut Self) {
-        let i = self.partition(|sample| sample.sample_as_split_feature(&split.theta) <= split.threshold);
+        let i = self.partition(|sample| split.sample_goes_left(sample));
         self.split_at_mut(i)
     }
 
@@ -93,13 +167,16 @@ impl<Sample> DataSet<Sample> for [Sample]
             let fb = b.sample_as_split_feature(theta);
             match fa.partial_cmp(&fb) {
                 Some(ordering) => ordering,
-                None => panic!("Could not compare samples (this is likely caused by a NaN feature"),
+                None => match (is_missing(&fa), is_missing(&fb)) {
+                    (false, false) => panic!("Could not compare samples"),
+                    (ma, mb) => ma.cmp(&mb),
+                },
             }
         })
     }
 
-    fn bootstrap_resample(&self, n: usize) -> Vec<Sample> {
-        resample(self, n, &mut thread_rng())
+    fn bootstrap_resample<R: Rng>(&self, n: usize, rng: &mut R) -> (Vec<Sample>, Vec<usize>) {
+        resample_with_counts(self, n, rng)
     }
 
     fn visit_samples<F: FnMut(&Sample)>(&self, mut visitor: F) {
@@ -107,4 +184,8 @@ impl<Sample> DataSet<Sample> for [Sample]
             visitor(sample);
         }
     }
+
+    fn first_sample(&self) -> Option<&Sample> {
+        self.first()
+    }
 }
@@ -4,16 +4,50 @@
 
 use std::fmt;
 
+use rand::{thread_rng, Rng, SeedableRng};
+use rand::prng::ChaChaRng;
+#[cfg(feature = "rayon")]
+use rayon::{ThreadPoolBuilder, prelude::*};
+
+use continuous::Continuous;
 use data::{SampleDescription, TrainingData};
-use dtree::{DeterministicTree, DeterministicTreeBuilder};
+use dtree::{self, DeterministicTree, DeterministicTreeBuilder};
 use iter_mean::IterMean;
 use split::SplitFinder;
+use vec2d::Vec2D;
+
+/// Number of samples per work item in parallel batch prediction
+#[cfg(feature = "rayon")]
+const PAR_CHUNK_SIZE: usize = 256;
 
 /// An ensemble of deterministic decision trees.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(bound(
+    serialize = "Sample::ThetaSplit: ::serde::Serialize, \
+                 Sample::ThetaLeaf: ::serde::Serialize, \
+                 Sample::Feature: ::serde::Serialize",
+    deserialize = "Sample::ThetaSplit: ::serde::Deserialize<'de>, \
+                   Sample::ThetaLeaf: ::serde::Deserialize<'de>, \
+                   Sample::Feature: ::serde::Deserialize<'de>")))]
 pub struct DeterministicForest<Sample>
     where Sample: SampleDescription
 {
-    estimators: Vec<DeterministicTree<Sample>>
+    pub(crate) estimators: Vec<DeterministicTree<Sample>>,
+
+    /// In-bag counts of the training samples, one vector per tree (only with bootstrapping or OOB
+    /// estimation)
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) in_bag: Vec<Vec<usize>>,
+
+    /// Whether the trees were trained on bootstrap samples
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) bootstrapped: bool,
+
+    #[cfg_attr(feature = "serde", serde(skip))]
+    oob_predictions: Vec<Option<Sample::Prediction>>,
+
+    #[cfg_attr(feature = "serde", serde(skip))]
+    oob_targets: Vec<Sample::Target>,
 }
 
 impl<Sample: SampleDescription> fmt::Debug for DeterministicForest<Sample>
@@ -29,6 +63,97 @@ impl<Sample: SampleDescription> fmt::Debug for DeterministicForest<Sample>
 impl<Sample> DeterministicForest<Sample>
     where Sample: SampleDescription
 {
+    pub(crate) fn new_with_estimators(estimators: Vec<DeterministicTree<Sample>>) -> Self {
+        DeterministicForest {
+            estimators,
+            in_bag: vec![],
+            bootstrapped: false,
+            oob_predictions: vec![],
+            oob_targets: vec![],
+        }
+    }
+
+    /// How often each training sample was drawn when fitting the `i`th tree.
+    ///
+    /// Only available if the forest was fit with bootstrapping or OOB estimation enabled.
+    pub fn in_bag_counts(&self, i: usize) -> Option<&[usize]> {
+        self.in_bag.get(i).map(|counts| &counts[..])
+    }
+
+    /// Out-of-bag prediction for the `i`th training sample.
+    ///
+    /// Returns `None` if OOB estimation was not enabled or if every tree was trained on the sample.
+    pub fn oob_prediction(&self, i: usize) -> Option<&Sample::Prediction> {
+        self.oob_predictions.get(i).and_then(|p| p.as_ref())
+    }
+
+    /// Mean of `metric(prediction, target)` over all training samples that have an OOB prediction.
+    pub fn oob_score<F>(&self, mut metric: F) -> Option<f64>
+        where F: FnMut(&Sample::Prediction, &Sample::Target) -> f64
+    {
+        let (n, sum) = self.oob_predictions
+            .iter()
+            .zip(self.oob_targets.iter())
+            .filter_map(|(p, t)| p.as_ref().map(|p| metric(p, t)))
+            .fold((0, 0.0), |(n, sum), m| (n + 1, sum + m));
+
+        if n == 0 {
+            None
+        } else {
+            Some(sum / n as f64)
+        }
+    }
+
+    /// Call `visitor` with the split parameter and relative impurity decrease of each split node
+    /// in the forest.
+    ///
+    /// The decreases are normalized per tree and averaged over trees, so they sum to one over the
+    /// whole forest (unless no tree has any split).
+    pub fn visit_split_importances<F>(&self, mut visitor: F)
+        where F: FnMut(&Sample::ThetaSplit, f64)
+    {
+        let n = self.estimators.len() as f64;
+        for tree in &self.estimators {
+            let total: f64 = tree.impurity_decrease.iter().sum();
+            if total > 0.0 {
+                tree.visit_split_importances(|theta, decrease| visitor(theta, decrease / total / n));
+            }
+        }
+    }
+
+    /// Compute OOB predictions for the training `data` from the in-bag counts.
+    fn compute_oob<Training>(&mut self, data: &Training)
+        where Training: ?Sized + TrainingData<Sample>,
+              Sample::Prediction: IterMean,
+    {
+        let mut predictions = Vec::with_capacity(data.n_samples());
+        let mut targets = Vec::with_capacity(data.n_samples());
+
+        {
+            let estimators = &self.estimators;
+            let in_bag = &self.in_bag;
+            data.visit_samples(|sample| {
+                let i = targets.len();
+                let oob: Vec<_> = estimators
+                    .iter()
+                    .zip(in_bag.iter())
+                    .filter(|&(_, counts)| counts[i] == 0)
+                    .map(|(tree, _)| tree.predict(sample))
+                    .collect();
+
+                predictions.push(if oob.is_empty() {
+                    None
+                } else {
+                    Some(Sample::Prediction::mean(oob.into_iter()))
+                });
+                targets.push(sample.target());
+            });
+        }
+
+        self.oob_predictions = predictions;
+        self.oob_targets = targets;
+    }
+
     // Making the predict function generic allows the user to pass in any sample that's compatible
     // with the tree's sample type
     pub fn predict<TestingSample>(&self, sample: &TestingSample) -> TestingSample::Prediction
@@ -42,6 +167,191 @@ impl<Sample> DeterministicForest<Sample>
             .map(|tree| tree.predict(sample));
         TestingSample::Prediction::mean(iter)
     }
+
+    /// Predictions of the individual trees
+    pub fn predict_trees<TestingSample>(&self, sample: &TestingSample) -> Vec<TestingSample::Prediction>
+        where TestingSample: SampleDescription<ThetaSplit=Sample::ThetaSplit,
+            ThetaLeaf=Sample::ThetaLeaf,
+            Feature=Sample::Feature> + ?Sized,
+    {
+        self.estimators
+            .iter()
+            .map(|tree| tree.predict(sample))
+            .collect()
+    }
+
+    /// Variance of the tree predictions around their mean
+    pub fn prediction_spread<TestingSample>(&self, sample: &TestingSample) -> f64
+        where TestingSample: SampleDescription<ThetaSplit=Sample::ThetaSplit,
+            ThetaLeaf=Sample::ThetaLeaf,
+            Feature=Sample::Feature> + ?Sized,
+              TestingSample::Prediction: Continuous,
+    {
+        let predictions = self.float_predictions(sample);
+        let mean = predictions.iter().sum::<f64>() / predictions.len() as f64;
+        predictions.iter().map(|p| (p - mean) * (p - mean)).sum::<f64>() / predictions.len() as f64
+    }
+
+    /// Bias-corrected infinitesimal jackknife estimate of the variance of the forest's prediction
+    /// (Wager, Hastie and Efron, 2014).
+    ///
+    /// The estimate is based on the covariance between each training sample's in-bag counts and
+    /// the tree predictions, so it requires a forest fit with bootstrapping. Returns `None`
+    /// otherwise. Negative estimates, which the bias correction can
+    /// produce for small forests, are clipped to zero.
+    pub fn ij_variance<TestingSample>(&self, sample: &TestingSample) -> Option<f64>
+        where TestingSample: SampleDescription<ThetaSplit=Sample::ThetaSplit,
+            ThetaLeaf=Sample::ThetaLeaf,
+            Feature=Sample::Feature> + ?Sized,
+              TestingSample::Prediction: Continuous,
+    {
+        self.ij_variance_of(&self.float_predictions(sample))
+    }
+
+    /// Predict the mean of the tree predictions and its standard error, estimated with the
+    /// infinitesimal jackknife (see `ij_variance`).
+    pub fn predict_with_std_error<TestingSample>(&self, sample: &TestingSample) -> Option<(f64, f64)>
+        where TestingSample: SampleDescription<ThetaSplit=Sample::ThetaSplit,
+            ThetaLeaf=Sample::ThetaLeaf,
+            Feature=Sample::Feature> + ?Sized,
+              TestingSample::Prediction: Continuous,
+    {
+        let predictions = self.float_predictions(sample);
+        let variance = self.ij_variance_of(&predictions)?;
+        let mean = predictions.iter().sum::<f64>() / predictions.len() as f64;
+        Some((mean, variance.sqrt()))
+    }
+
+    /// Infinitesimal jackknife variance, given the tree predictions for a sample
+    fn ij_variance_of(&self, predictions: &[f64]) -> Option<f64> {
+        if !self.bootstrapped || self.in_bag.is_empty() {
+            return None
+        }
+
+        let n_trees = predictions.len() as f64;
+        let n_samples = self.in_bag[0].len();
+        let mean = predictions.iter().sum::<f64>() / n_trees;
+        let centered: Vec<f64> = predictions.iter().map(|p| p - mean).collect();
+
+        let mut variance = 0.0;
+        for i in 0..n_samples {
+            let mean_count = self.in_bag.iter().map(|counts| counts[i] as f64).sum::<f64>() / n_trees;
+            let cov = self.in_bag.iter()
+                .zip(&centered)
+                .map(|(counts, c)| (counts[i] as f64 - mean_count) * c)
+                .sum::<f64>() / n_trees;
+            variance += cov * cov;
+        }
+
+        let spread = centered.iter().map(|c| c * c).sum::<f64>() / n_trees;
+        let correction = n_samples as f64 * spread / n_trees;
+        Some((variance - correction).max(0.0))
+    }
+
+    fn float_predictions<TestingSample>(&self, sample: &TestingSample) -> Vec<f64>
+        where TestingSample: SampleDescription<ThetaSplit=Sample::ThetaSplit,
+            ThetaLeaf=Sample::ThetaLeaf,
+            Feature=Sample::Feature> + ?Sized,
+              TestingSample::Prediction: Continuous,
+    {
+        self.estimators
+            .iter()
+            .map(|tree| tree.predict(sample).as_float())
+            .collect()
+    }
+
+    /// Predict a batch of samples.
+    pub fn predict_batch<TestingSample>(&self, samples: &[TestingSample]) -> Vec<TestingSample::Prediction>
+        where TestingSample: SampleDescription<ThetaSplit=Sample::ThetaSplit,
+            ThetaLeaf=Sample::ThetaLeaf,
+            Feature=Sample::Feature>,
+              TestingSample::Prediction: IterMean,
+    {
+        samples.iter().map(|sample| self.predict(sample)).collect()
+    }
+
+    /// Predict each row of a two-dimensional array.
+    ///
+    /// `to_sample` converts a row into a sample that can be passed to the trees.
+    pub fn predict_rows<'a, T, TestingSample, F>(&self, x: &'a Vec2D<T>, to_sample: F) -> Vec<TestingSample::Prediction>
+        where TestingSample: SampleDescription<ThetaSplit=Sample::ThetaSplit,
+            ThetaLeaf=Sample::ThetaLeaf,
+            Feature=Sample::Feature>,
+              TestingSample::Prediction: IterMean,
+              F: Fn(&'a [T]) -> TestingSample,
+    {
+        x.iter().map(|row| self.predict(&to_sample(row))).collect()
+    }
+}
+
+impl<Sample> DeterministicForest<Sample>
+    where Sample: SampleDescription<ThetaSplit=usize>
+{
+    /// Mean decrease in impurity of each feature, normalized to sum to one.
+    pub fn feature_importances(&self, n_features: usize) -> Vec<f64> {
+        let mut importances = vec![0.0; n_features];
+        self.visit_split_importances(|&theta, decrease| importances[theta] += decrease);
+        dtree::normalize(&mut importances);
+        importances
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<Sample> DeterministicForest<Sample>
+    where Sample: SampleDescription,
+          Sample::ThetaSplit: Sync,
+          Sample::ThetaLeaf: Sync,
+          Sample::Feature: Sync,
+{
+    /// Predict a batch of samples in parallel.
+    ///
+    /// The samples are split into chunks, and within each chunk the trees are evaluated in
+    /// parallel before their predictions are combined.
+    pub fn par_predict_batch<TestingSample>(&self, samples: &[TestingSample]) -> Vec<TestingSample::Prediction>
+        where TestingSample: SampleDescription<ThetaSplit=Sample::ThetaSplit,
+            ThetaLeaf=Sample::ThetaLeaf,
+            Feature=Sample::Feature> + Sync,
+              TestingSample::Prediction: IterMean + Send,
+    {
+        let estimators = &self.estimators;
+        let chunks: Vec<Vec<_>> = samples
+            .par_chunks(PAR_CHUNK_SIZE)
+            .map(|chunk| {
+                let mut per_tree: Vec<_> = estimators
+                    .par_iter()
+                    .map(|tree| chunk.iter().map(|sample| tree.predict(sample)).collect::<Vec<_>>())
+                    .collect::<Vec<_>>()
+                    .into_iter()
+                    .map(|predictions| predictions.into_iter())
+                    .collect();
+
+                chunk.iter()
+                    .map(|_| {
+                        let iter = per_tree
+                            .iter_mut()
+                            .map(|predictions| predictions.next().unwrap());
+                        TestingSample::Prediction::mean(iter)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        chunks.into_iter().flat_map(|chunk| chunk.into_iter()).collect()
+    }
+
+    /// Predict each row of a two-dimensional array in parallel.
+    ///
+    /// `to_sample` converts a row into a sample that can be passed to the trees.
+    pub fn par_predict_rows<'a, T, TestingSample, F>(&self, x: &'a Vec2D<T>, to_sample: F) -> Vec<TestingSample::Prediction>
+        where TestingSample: SampleDescription<ThetaSplit=Sample::ThetaSplit,
+            ThetaLeaf=Sample::ThetaLeaf,
+            Feature=Sample::Feature> + Sync,
+              TestingSample::Prediction: IterMean + Send,
+              F: Fn(&'a [T]) -> TestingSample,
+    {
+        let samples: Vec<_> = x.iter().map(to_sample).collect();
+        self.par_predict_batch(&samples)
+    }
 }
 
 /// Fit a `DeterministicForest` to `TrainingData`.
@@ -51,6 +361,10 @@ pub struct DeterministicForestBuilder<SF, Sample>
 {
     n_estimators: usize,
     tree_builder: DeterministicTreeBuilder<SF, Sample>,
+    seed: Option<u64>,
+    oob_score: bool,
+    #[cfg(feature = "rayon")]
+    n_jobs: usize,
 }
 
 impl<SF, Sample> DeterministicForestBuilder<SF, Sample>
@@ -61,21 +375,162 @@ impl<SF, Sample> DeterministicForestBuilder<SF, Sample>
         DeterministicForestBuilder {
             n_estimators,
             tree_builder,
+            seed: None,
+            oob_score: false,
+            #[cfg(feature = "rayon")]
+            n_jobs: 0,
         }
     }
 
+    /// Seed the random number generator, so that fitting the same data twice produces identical
+    /// forests. This overrides any seed set on the tree builder; without a forest seed, the tree
+    /// builder's seed is used.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Record which samples each tree was trained on and compute out-of-bag predictions.
+    ///
+    /// This requires bootstrapping in the tree builder; without it every sample is in-bag.
+    pub fn with_oob_score(mut self) -> Self {
+        self.oob_score = true;
+        self
+    }
+
+    fn make_rng(&self) -> ChaChaRng {
+        match self.seed.or(self.tree_builder.seed) {
+            None => ChaChaRng::from_rng(thread_rng()).expect("Could not seed random number generator."),
+            Some(seed) => ChaChaRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Set the number of threads used by `par_fit`.
+    ///
+    /// The default of 0 uses rayon's global thread pool.
+    #[cfg(feature = "rayon")]
+    pub fn with_n_jobs(mut self, n_jobs: usize) -> Self {
+        self.n_jobs = n_jobs;
+        self
+    }
+
     pub fn fit<Training>(&self, data: &mut Training) -> DeterministicForest<Sample>
         where Training: ?Sized + TrainingData<Sample>,
-              [Sample]: TrainingData<Sample>
+              [Sample]: TrainingData<Sample>,
+              Sample::Prediction: IterMean,
+    {
+        self.fit_with_rng(data, &mut self.make_rng())
+    }
+
+    /// Fit a forest, drawing all randomness from `rng`. The builder's seed is ignored.
+    ///
+    /// Like in `par_fit`, each tree gets its own random number generator, seeded from `rng`, so
+    /// both fit the same bootstrapped forest from the same seed.
+    pub fn fit_with_rng<Training, R>(&self, data: &mut Training, rng: &mut R) -> DeterministicForest<Sample>
+        where Training: ?Sized + TrainingData<Sample>,
+              [Sample]: TrainingData<Sample>,
+              Sample::Prediction: IterMean,
+              R: Rng
     {
         let mut estimators = Vec::with_capacity(self.n_estimators);
+        let mut in_bag = Vec::new();
         for _ in 0..self.n_estimators {
-            estimators.push(self.tree_builder.fit(data));
+            let mut tree_rng = ChaChaRng::seed_from_u64(rng.gen());
+            let (tree, counts) = self.tree_builder.fit_in_bag(data, &mut tree_rng);
+            estimators.push(tree);
+            if self.records_in_bag() {
+                in_bag.push(counts.unwrap_or_else(|| vec![1; data.n_samples()]));
+            }
         }
 
-        DeterministicForest {
-            estimators
+        self.finish(estimators, in_bag, data)
+    }
+
+    fn finish<Training>(&self,
+                        estimators: Vec<DeterministicTree<Sample>>,
+                        in_bag: Vec<Vec<usize>>,
+                        data: &Training)
+        -> DeterministicForest<Sample>
+        where Training: ?Sized + TrainingData<Sample>,
+              Sample::Prediction: IterMean,
+    {
+        let mut forest = DeterministicForest::new_with_estimators(estimators);
+        if self.records_in_bag() {
+            forest.in_bag = in_bag;
+            forest.bootstrapped = self.tree_builder.bootstrap.is_some();
         }
+        if self.oob_score {
+            forest.compute_oob(data);
+        }
+        forest
+    }
+
+    /// In-bag counts are needed for OOB estimation and, with bootstrapping, for `ij_variance`.
+    fn records_in_bag(&self) -> bool {
+        self.oob_score || self.tree_builder.bootstrap.is_some()
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<SF, Sample> DeterministicForestBuilder<SF, Sample>
+    where SF: SplitFinder + Sync,
+          Sample: SampleDescription + Clone + Send + Sync,
+          Sample::ThetaSplit: Send,
+          Sample::ThetaLeaf: Send,
+          Sample::Feature: Send,
+          Sample::Prediction: IterMean,
+          [Sample]: TrainingData<Sample>
+{
+    /// Fit a `DeterministicForest`, training the trees in parallel.
+    ///
+    /// The caller's data is left untouched. Bootstrapped trees are fit to samples drawn directly
+    /// from `data`. Otherwise, fitting a tree reorders the training data in place, so each worker
+    /// thread trains its trees on one copy of `data`, in whatever order the previous tree left it
+    /// (as `fit` does with the caller's data).
+    ///
+    /// Each tree gets its own random number generator, seeded from the builder's generator before
+    /// training starts in the same way as in `fit`. With bootstrapping, the result therefore does
+    /// not depend on the number of threads and equals the forest `fit` returns for the same seed.
+    /// Without it, the order of a worker's copy can change the trees by floating point rounding.
+    pub fn par_fit(&self, data: &[Sample]) -> DeterministicForest<Sample> {
+        let mut rng = self.make_rng();
+        let seeds: Vec<u64> = (0..self.n_estimators).map(|_| rng.gen()).collect();
+
+        let fit_all = || {
+            seeds
+                .par_iter()
+                .map_init(|| None, |copy: &mut Option<Vec<Sample>>, &seed| {
+                    let mut tree_rng = ChaChaRng::seed_from_u64(seed);
+                    let (tree, counts) = match self.tree_builder.bootstrap {
+                        Some(n) => {
+                            let (tree, counts) = self.tree_builder.fit_bootstrap(data, n, &mut tree_rng);
+                            (tree, Some(counts))
+                        }
+                        None => {
+                            let copy = copy.get_or_insert_with(|| data.to_vec());
+                            self.tree_builder.fit_in_bag(&mut copy[..], &mut tree_rng)
+                        }
+                    };
+                    match (self.records_in_bag(), counts) {
+                        (false, _) => (tree, vec![]),
+                        (true, Some(counts)) => (tree, counts),
+                        (true, None) => (tree, vec![1; data.len()]),
+                    }
+                })
+                .unzip()
+        };
+
+        let (estimators, in_bag): (Vec<_>, Vec<_>) = if self.n_jobs == 0 {
+            fit_all()
+        } else {
+            ThreadPoolBuilder::new()
+                .num_threads(self.n_jobs)
+                .build()
+                .expect("Could not create thread pool.")
+                .install(fit_all)
+        };
+
+        self.finish(estimators, in_bag, data)
     }
 }
 
@@ -88,20 +543,20 @@ mod tests {
 
     #[test]
     fn fmt() {
-        let forest: DeterministicForest<Sample<_, _>> = DeterministicForest {
-            estimators: vec![
+        let forest: DeterministicForest<Sample<_, _>> = DeterministicForest::new_with_estimators(
+            vec![
                 DeterministicTree::new_with_nodes(vec![
-                    Node::Split { theta: 1, threshold: 2.3, left: 1, right: 2 },
+                    Node::Split { theta: 1, threshold: 2.3, left: 1, right: 2, missing_left: false },
                     Node::Leaf(4.5),
                     Node::Invalid,
                 ]),
                 DeterministicTree::new_with_nodes(vec![
-                    Node::Split { theta: 1, threshold: 2.3, left: 1, right: 2 },
+                    Node::Split { theta: 1, threshold: 2.3, left: 1, right: 2, missing_left: false },
                     Node::Leaf(4.5),
                     Node::Invalid,
                 ])
             ]
-        };
+        );
 
         let formatted = format!("{:?}", forest);
 
@@ -109,4 +564,288 @@ mod tests {
 
         assert_eq!(formatted, format!("Forest: [\n{},\n{}\n]", tree_expected, tree_expected));
     }
+
+    #[test]
+    fn predict_batch() {
+        use api::extra_trees_regressor::{ExtraTreesRegressor, Sample};
+
+        let x = Vec2D::from_slice(&[1.0, 2.0, 3.0, 7.0, 8.0, 9.0], 1);
+        let y = vec![5.0, 5.0, 5.0, 2.0, 2.0, 2.0];
+        let forest = ExtraTreesRegressor::new().fit(&x, &y);
+
+        let x_test = Vec2D::from_slice(&[-1.0, 2.0, 5.0, 8.0, 100.0], 1);
+        let samples: Vec<_> = x_test.iter().map(|row| Sample::new(row, ())).collect();
+        let expected: Vec<_> = samples.iter().map(|sample| forest.predict(sample)).collect();
+
+        assert_eq!(forest.predict_batch(&samples), expected);
+        assert_eq!(forest.predict_rows(&x_test, |row| Sample::new(row, ())), expected);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_predict_batch() {
+        use api::extra_trees_regressor::{ExtraTreesRegressor, Sample};
+
+        let x = Vec2D::from_vec((0..100).map(|i| i as f64).collect(), 1);
+        let y: Vec<_> = (0..100).map(|i| (i % 7) as f64).collect();
+        let forest = ExtraTreesRegressor::new().fit(&x, &y);
+
+        let x_test = Vec2D::from_vec((0..1000).map(|i| i as f64 / 10.0).collect(), 1);
+        let expected = forest.predict_rows(&x_test, |row| Sample::new(row, ()));
+
+        assert_eq!(forest.par_predict_rows(&x_test, |row| Sample::new(row, ())), expected);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_fit() {
+        use dtree::DeterministicTreeBuilder;
+        use split::BestRandomSplit;
+
+        let data: &[_] = &[
+            Sample::new(&[0.0], 1.0),
+            Sample::new(&[1.0], 2.0),
+            Sample::new(&[2.0], 1.0),
+            Sample::new(&[3.0], 2.0),
+            Sample::new(&[4.0], 11.0),
+            Sample::new(&[5.0], 12.0),
+            Sample::new(&[6.0], 11.0),
+            Sample::new(&[7.0], 12.0),
+        ];
+
+        let forest = DeterministicForestBuilder::new(
+            20,
+            DeterministicTreeBuilder::new(2, BestRandomSplit::new(1))
+        ).with_n_jobs(4).par_fit(data);
+
+        assert_eq!(forest.estimators.len(), 20);
+        for sample in data {
+            assert_eq!(forest.predict(sample), sample.y);
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_fit_matches_fit() {
+        use dtree::DeterministicTreeBuilder;
+        use split::BestRandomSplit;
+
+        let x: Vec<_> = (0..40).map(|i| [i as f64]).collect();
+        let data: Vec<_> = x.iter().map(|xi| Sample::new(xi, (xi[0] / 5.0).sin())).collect();
+
+        let builder = DeterministicForestBuilder::new(
+            10,
+            DeterministicTreeBuilder::new(2, BestRandomSplit::new(1)).with_bootstrap(40)
+        ).with_seed(7).with_n_jobs(3);
+        let forest = builder.fit(&mut data.clone()[..]);
+        let par_forest = builder.par_fit(&data);
+
+        for t in 0..10 {
+            assert_eq!(par_forest.in_bag_counts(t), forest.in_bag_counts(t));
+        }
+        let x_test: Vec<_> = (0..100).map(|i| [i as f64 / 2.5]).collect();
+        for xi in &x_test {
+            let sample = Sample::new(xi, 0.0);
+            assert_eq!(par_forest.predict(&sample), forest.predict(&sample));
+        }
+    }
+
+    #[test]
+    fn oob() {
+        use dtree::DeterministicTreeBuilder;
+        use split::BestRandomSplit;
+
+        let x: Vec<_> = (0..40).map(|i| [i as f64]).collect();
+        let data: &mut Vec<_> = &mut x.iter()
+            .map(|xi| Sample::new(xi, if xi[0] < 20.0 { 1.0 } else { 5.0 }))
+            .collect();
+
+        let forest = DeterministicForestBuilder::new(
+            50,
+            DeterministicTreeBuilder::new(2, BestRandomSplit::new(10)).with_bootstrap(40)
+        ).with_oob_score().with_seed(0).fit(&mut data[..]);
+
+        for t in 0..50 {
+            assert_eq!(forest.in_bag_counts(t).unwrap().iter().sum::<usize>(), 40);
+        }
+        assert_eq!(forest.in_bag_counts(50), None);
+
+        for (i, sample) in data.iter().enumerate() {
+            let p = *forest.oob_prediction(i).unwrap();
+            assert!((1.0..=5.0).contains(&p));
+            if sample.x[0] < 10.0 || sample.x[0] > 30.0 {
+                assert!((p - sample.y).abs() < 1e-9);
+            }
+        }
+
+        let mse = forest.oob_score(|p, t| (p - t) * (p - t)).unwrap();
+        assert!(mse < 1.0);
+
+        let forest = DeterministicForestBuilder::new(
+            5,
+            DeterministicTreeBuilder::new(2, BestRandomSplit::new(10)).with_bootstrap(40)
+        ).fit(&mut data[..]);
+        assert_eq!(forest.oob_prediction(0), None);
+        assert_eq!(forest.oob_score(|p, t| (p - t) * (p - t)), None);
+    }
+
+    #[test]
+    fn std_error() {
+        use dtree::DeterministicTreeBuilder;
+        use split::BestRandomSplit;
+
+        // the target is constant for x < 50 and noisy above
+        let x: Vec<_> = (0..100).map(|i| [i as f64]).collect();
+        let data: &mut Vec<_> = &mut x.iter()
+            .map(|xi| Sample::new(xi, if xi[0] < 50.0 { 1.0 } else { ((xi[0] as usize * 37) % 11) as f64 }))
+            .collect();
+
+        let builder = DeterministicForestBuilder::new(
+            100,
+            DeterministicTreeBuilder::new(5, BestRandomSplit::new(1)).with_bootstrap(100)
+        ).with_seed(1);
+
+        let forest = builder.fit(&mut data[..]);
+        assert_eq!(forest.predict_trees(&Sample::new(&[75.0], 0.0)).len(), 100);
+        assert!(forest.prediction_spread(&Sample::new(&[75.0], 0.0)) > 0.0);
+        assert_eq!(forest.oob_score(|p, t| (p - t) * (p - t)), None);
+
+        // the in-bag counts are recorded for bootstrapped forests even without OOB estimation
+        let (_, se) = forest.predict_with_std_error(&Sample::new(&[75.0], 0.0)).unwrap();
+        assert!(se > 0.0);
+
+        let forest = builder.with_oob_score().fit(&mut data[..]);
+
+        let (mean, se) = forest.predict_with_std_error(&Sample::new(&[10.0], 0.0)).unwrap();
+        assert_eq!(forest.prediction_spread(&Sample::new(&[10.0], 0.0)), 0.0);
+        assert!((mean - 1.0).abs() < 1e-9);
+        assert_eq!(se, 0.0);
+
+        let (mean, se) = forest.predict_with_std_error(&Sample::new(&[75.0], 0.0)).unwrap();
+        assert!((mean - forest.predict(&Sample::new(&[75.0], 0.0))).abs() < 1e-9);
+        assert!(se > 0.0);
+        assert!(se * se <= forest.prediction_spread(&Sample::new(&[75.0], 0.0)));
+
+        // without bootstrapping every sample is in-bag for every tree
+        let forest = DeterministicForestBuilder::new(
+            10,
+            DeterministicTreeBuilder::new(5, BestRandomSplit::new(1))
+        ).with_oob_score().fit(&mut data[..]);
+        assert!(forest.in_bag_counts(0).is_some());
+        assert_eq!(forest.ij_variance(&Sample::new(&[75.0], 0.0)), None);
+        assert_eq!(forest.predict_with_std_error(&Sample::new(&[75.0], 0.0)), None);
+    }
+
+    #[test]
+    fn feature_importances() {
+        use dtree::DeterministicTreeBuilder;
+        use split::BestRandomSplit;
+
+        // only the second feature is informative
+        let x: Vec<_> = (0..40).map(|i| [(i * 7 % 40) as f64, i as f64, 0.0]).collect();
+        let data: &mut Vec<_> = &mut x.iter()
+            .map(|xi| Sample::new(xi, if xi[1] < 20.0 { 1.0 } else { 5.0 }))
+            .collect();
+
+        let forest = DeterministicForestBuilder::new(
+            20,
+            DeterministicTreeBuilder::new(2, BestRandomSplit::new(5)).with_max_depth(1)
+        ).with_seed(3).fit(&mut data[..]);
+
+        let importances = forest.feature_importances(3);
+        assert!((importances.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert!(importances[1] > importances[0]);
+        assert_eq!(importances[2], 0.0);
+    }
+
+    #[test]
+    fn multi_output() {
+        use dtree::DeterministicTreeBuilder;
+        use split::BestSplit;
+        use testdata::VecSample;
+
+        let data: &mut [_] = &mut [
+            VecSample::new(&[0.0], vec![1.0, -1.0, 0.0]),
+            VecSample::new(&[1.0], vec![1.0, -1.0, 0.0]),
+            VecSample::new(&[2.0], vec![3.0, 1.0, 0.0]),
+            VecSample::new(&[3.0], vec![5.0, 3.0, 0.0]),
+        ];
+
+        let builder = DeterministicForestBuilder::new(
+            2,
+            DeterministicTreeBuilder::new(2, BestSplit::new()).with_max_depth(1)
+        );
+        let forest = builder.fit(data);
+
+        assert_eq!(forest.predict(&VecSample::new(&[0.5], vec![])), vec![1.0, -1.0, 0.0]);
+        assert_eq!(forest.predict(&VecSample::new(&[3.0], vec![])), vec![4.0, 2.0, 0.0]);
+    }
+
+    #[test]
+    fn seed() {
+        use dtree::DeterministicTreeBuilder;
+        use split::BestRandomSplit;
+
+        let data: &mut [_] = &mut [
+            Sample::new(&[0.0, 5.0], 1.0),
+            Sample::new(&[1.0, 3.0], 2.0),
+            Sample::new(&[2.0, 7.0], 1.0),
+            Sample::new(&[3.0, 1.0], 2.0),
+            Sample::new(&[4.0, 6.0], 11.0),
+            Sample::new(&[5.0, 0.0], 12.0),
+            Sample::new(&[6.0, 4.0], 11.0),
+            Sample::new(&[7.0, 2.0], 12.0),
+        ];
+
+        let builder = DeterministicForestBuilder::new(
+            5,
+            DeterministicTreeBuilder::new(2, BestRandomSplit::new(1)).with_bootstrap(8)
+        );
+
+        let a = builder.with_seed(42);
+        let forest_a = format!("{:?}", a.fit(data));
+        assert_eq!(format!("{:?}", a.fit(data)), forest_a);
+
+        // without a forest seed, the tree builder's seed is used
+        let c = DeterministicForestBuilder::new(
+            5,
+            DeterministicTreeBuilder::new(2, BestRandomSplit::new(1)).with_bootstrap(8).with_seed(7)
+        );
+        assert_eq!(format!("{:?}", c.fit(data)), format!("{:?}", c.fit(data)));
+
+        #[cfg(feature = "rayon")]
+        {
+            let forest_a = format!("{:?}", a.par_fit(data));
+            let b = DeterministicForestBuilder::new(
+                5,
+                DeterministicTreeBuilder::new(2, BestRandomSplit::new(1)).with_bootstrap(8)
+            ).with_seed(42).with_n_jobs(3);
+            assert_eq!(format!("{:?}", b.par_fit(data)), forest_a);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        use serde_json;
+        use api::extra_trees_classifier::{Classes, ExtraTreesClassifier, Sample};
+        use vec2d::Vec2D;
+
+        let x = Vec2D::from_slice(&[1.0, 2.0, 3.0, 7.0, 8.0, 9.0], 1);
+        let y = vec![1, 1, 1, 2, 2, 2];
+
+        let forest = ExtraTreesClassifier::new()
+            .with_n_estimators(10)
+            .fit(&x, &y);
+
+        let json = serde_json::to_string(&forest).unwrap();
+        let loaded: DeterministicForest<Sample<f64, Classes>> = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(format!("{:?}", loaded), format!("{:?}", forest));
+        for xi in [[-1.0], [2.0], [5.0], [8.0], [100.0]].iter() {
+            let sample = Sample::new(xi, ());
+            assert_eq!(format!("{:?}", loaded.predict(&sample)),
+                       format!("{:?}", forest.predict(&sample)));
+        }
+    }
 }
@@ -4,29 +4,107 @@
 //! depending on feature and threshold. (This stands in contrast to probabilistic trees, where
 //! samples are passed down both sides with certain probabilities.)
 
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
 use std::fmt;
 use std::marker::PhantomData;
 
+use rand::{thread_rng, Rng, SeedableRng};
+use rand::prng::ChaChaRng;
+
+use criterion::SplitCriterion;
 use data::{SampleDescription, TrainingData};
-use split::{Split, SplitFinder};
+use split::{goes_left, in_subset, Split, SplitFinder, SplitRule};
 
 /// A decision tree node.
 ///
 /// Can be either a split node with a Splitter and two children, or a leaf
 /// node with a LeafPredictor.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(bound(
+    serialize = "T::ThetaSplit: ::serde::Serialize, \
+                 T::ThetaLeaf: ::serde::Serialize, \
+                 T::Feature: ::serde::Serialize",
+    deserialize = "T::ThetaSplit: ::serde::Deserialize<'de>, \
+                   T::ThetaLeaf: ::serde::Deserialize<'de>, \
+                   T::Feature: ::serde::Deserialize<'de>")))]
 pub enum Node<T>
     where T: SampleDescription
 {
     Invalid,  // placeholder used during tree construction
-    Split{ theta: T::ThetaSplit, threshold: T::Feature, left: usize, right: usize},
+    Split{
+        theta: T::ThetaSplit,
+        threshold: T::Feature,
+        left: usize,
+        right: usize,
+        /// Pass samples with a missing feature to the left child
+        #[cfg_attr(feature = "serde", serde(default))]
+        missing_left: bool,
+    },
+    /// Split of a categorical feature; samples with a category in the sorted `categories` go left
+    SubsetSplit{
+        theta: T::ThetaSplit,
+        categories: Vec<usize>,
+        left: usize,
+        right: usize,
+        /// Pass samples with a missing category to the left child
+        missing_left: bool,
+    },
     Leaf(T::ThetaLeaf),
 }
 
 /// A deterministic decision tree.
+///
+/// Deserialized trees are validated (see `TreeData`), because `predict` relies on the child
+/// indices.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "TreeData<Sample>"))]
+#[cfg_attr(feature = "serde", serde(bound(
+    serialize = "Sample::ThetaSplit: ::serde::Serialize, \
+                 Sample::ThetaLeaf: ::serde::Serialize, \
+                 Sample::Feature: ::serde::Serialize",
+    deserialize = "Sample::ThetaSplit: ::serde::Deserialize<'de>, \
+                   Sample::ThetaLeaf: ::serde::Deserialize<'de>, \
+                   Sample::Feature: ::serde::Deserialize<'de>")))]
 pub struct DeterministicTree<Sample>
     where Sample: SampleDescription
 {
-    nodes: Vec<Node<Sample>>
+    pub(crate) nodes: Vec<Node<Sample>>,
+
+    /// Weighted decrease of the split criterion at each node (zero for leaves)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) impurity_decrease: Vec<f64>,
+}
+
+/// Deserialized nodes of a `DeterministicTree`, before they are validated
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(bound(
+    deserialize = "Sample::ThetaSplit: ::serde::Deserialize<'de>, \
+                   Sample::ThetaLeaf: ::serde::Deserialize<'de>, \
+                   Sample::Feature: ::serde::Deserialize<'de>"))]
+struct TreeData<Sample>
+    where Sample: SampleDescription
+{
+    nodes: Vec<Node<Sample>>,
+    #[serde(default)]
+    impurity_decrease: Vec<f64>,
+}
+
+#[cfg(feature = "serde")]
+impl<Sample> TryFrom<TreeData<Sample>> for DeterministicTree<Sample>
+    where Sample: SampleDescription
+{
+    type Error = &'static str;
+
+    fn try_from(data: TreeData<Sample>) -> Result<Self, Self::Error> {
+        let tree = DeterministicTree {
+            nodes: data.nodes,
+            impurity_decrease: data.impurity_decrease,
+        };
+        tree.validate()?;
+        Ok(tree)
+    }
 }
 
 impl<Sample: SampleDescription> fmt::Debug for DeterministicTree<Sample>
@@ -62,32 +140,137 @@ impl<Sample: SampleDescription> DeterministicTree<Sample>
         match *node {
             Node::Invalid => write!(f, " *** Invalid ***")?,
             Node::Leaf(ref l) => write!(f, " {:?}", l)?,
-            Node::Split{ref theta, ref threshold, left, right} => {
+            Node::Split{ref theta, ref threshold, left, right, missing_left} => {
                 write!(f, "({:?}) <= {:?}", theta, threshold)?;
-                if let Some(&" +--") = prefix.last() {
-                    prefix.pop();
-                    prefix.push(" |  ");
+                if missing_left {
+                    write!(f, " or missing")?;
                 }
-                prefix.push(" +--");
-                self.recursive_fmt(left, prefix, false, f)?;
-                prefix.pop();
-                prefix.push(" +--");
-                self.recursive_fmt(right, prefix, true, f)?;
-                prefix.pop();
+                self.fmt_children(left, right, prefix, f)?;
+            }
+            Node::SubsetSplit{ref theta, ref categories, left, right, missing_left} => {
+                write!(f, "({:?}) in {:?}", theta, categories)?;
+                if missing_left {
+                    write!(f, " or missing")?;
+                }
+                self.fmt_children(left, right, prefix, f)?;
             }
         }
 
         Ok(())
     }
+
+    fn fmt_children(&self, left: usize, right: usize, prefix: &mut Vec<&str>, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(&" +--") = prefix.last() {
+            prefix.pop();
+            prefix.push(" |  ");
+        }
+        prefix.push(" +--");
+        self.recursive_fmt(left, prefix, false, f)?;
+        prefix.pop();
+        prefix.push(" +--");
+        self.recursive_fmt(right, prefix, true, f)?;
+        prefix.pop();
+        Ok(())
+    }
 }
 
 impl<Sample> DeterministicTree<Sample>
     where Sample: SampleDescription
 {
-    #[cfg(test)]
     pub(crate) fn new_with_nodes(nodes: Vec<Node<Sample>>) -> Self {
         DeterministicTree {
-            nodes
+            impurity_decrease: vec![0.0; nodes.len()],
+            nodes,
+        }
+    }
+
+    /// Check that every node can be reached safely by `predict`.
+    ///
+    /// The tree must not be empty or contain `Invalid` nodes, children must be in bounds and come
+    /// after their parent (which guarantees that every path ends in a leaf), and subset categories
+    /// must be sorted and unique.
+    pub(crate) fn validate(&self) -> Result<(), &'static str> {
+        let n = self.nodes.len();
+        if n == 0 {
+            return Err("empty tree")
+        }
+        for (i, node) in self.nodes.iter().enumerate() {
+            let (left, right) = match *node {
+                Node::Invalid => return Err("invalid node"),
+                Node::Leaf(_) => continue,
+                Node::Split { left, right, .. } => (left, right),
+                Node::SubsetSplit { ref categories, left, right, .. } => {
+                    if categories.windows(2).any(|w| w[0] >= w[1]) {
+                        return Err("subset categories are not sorted and unique")
+                    }
+                    (left, right)
+                }
+            };
+            if left >= n || right >= n {
+                return Err("node index out of range")
+            }
+            if left <= i || right <= i {
+                return Err("child node precedes its parent")
+            }
+        }
+        Ok(())
+    }
+
+    /// Return the index of the leaf node `sample` ends up in.
+    ///
+    /// In contrast to `predict`, the sample only needs to be compatible with the tree's split
+    /// parameters and features.
+    pub fn leaf_index<TestingSample>(&self, sample: &TestingSample) -> usize
+        where TestingSample: SampleDescription<ThetaSplit=Sample::ThetaSplit,
+            Feature=Sample::Feature> + ?Sized,
+    {
+        let mut n = 0;
+        loop {
+            match self.nodes[n] {
+                Node::Split { ref theta, ref threshold, left, right, missing_left } => {
+                    if goes_left(&sample.sample_as_split_feature(theta), threshold, missing_left) {
+                        n = left;
+                    } else {
+                        n = right;
+                    }
+                }
+                Node::SubsetSplit { ref theta, ref categories, left, right, missing_left } => {
+                    if in_subset(sample.sample_as_category(theta), categories, missing_left) {
+                        n = left;
+                    } else {
+                        n = right;
+                    }
+                }
+                Node::Leaf(_) => return n,
+                Node::Invalid => panic!("Invalid node found. Tree may not be fully constructed.")
+            }
+        }
+    }
+
+    /// Return the parameters of the leaf `sample` ends up in.
+    pub fn predict_leaf<TestingSample>(&self, sample: &TestingSample) -> &Sample::ThetaLeaf
+        where TestingSample: SampleDescription<ThetaSplit=Sample::ThetaSplit,
+            Feature=Sample::Feature> + ?Sized,
+    {
+        match self.nodes[self.leaf_index(sample)] {
+            Node::Leaf(ref l) => l,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Call `visitor` with the split parameter and the weighted decrease of the split criterion
+    /// of each split node.
+    ///
+    /// This is the building block for impurity-based feature importances with arbitrary
+    /// `ThetaSplit` types.
+    pub fn visit_split_importances<F>(&self, mut visitor: F)
+        where F: FnMut(&Sample::ThetaSplit, f64)
+    {
+        for (node, &decrease) in self.nodes.iter().zip(self.impurity_decrease.iter()) {
+            match *node {
+                Node::Split { ref theta, .. } | Node::SubsetSplit { ref theta, .. } => visitor(theta, decrease),
+                Node::Leaf(_) | Node::Invalid => {}
+            }
         }
     }
 
@@ -103,11 +286,18 @@ impl<Sample> DeterministicTree<Sample>
         unsafe {
             loop {
                 match *node {
-                    Node::Split { ref theta, ref threshold, left, right } => {
-                        if &sample.sample_as_split_feature(theta) <= threshold {
-                            node = start.offset(left as isize);
+                    Node::Split { ref theta, ref threshold, left, right, missing_left } => {
+                        if goes_left(&sample.sample_as_split_feature(theta), threshold, missing_left) {
+                            node = start.add(left);
                         } else {
-                            node = start.offset(right as isize);
+                            node = start.add(right);
+                        }
+                    }
+                    Node::SubsetSplit { ref theta, ref categories, left, right, missing_left } => {
+                        if in_subset(sample.sample_as_category(theta), categories, missing_left) {
+                            node = start.add(left);
+                        } else {
+                            node = start.add(right);
                         }
                     }
                     Node::Leaf(ref l) => {
@@ -120,6 +310,28 @@ impl<Sample> DeterministicTree<Sample>
     }
 }
 
+impl<Sample> DeterministicTree<Sample>
+    where Sample: SampleDescription<ThetaSplit=usize>
+{
+    /// Mean decrease in impurity of each feature, normalized to sum to one.
+    pub fn feature_importances(&self, n_features: usize) -> Vec<f64> {
+        let mut importances = vec![0.0; n_features];
+        self.visit_split_importances(|&theta, decrease| importances[theta] += decrease);
+        normalize(&mut importances);
+        importances
+    }
+}
+
+/// Scale `x` to sum to one, unless all values are zero.
+pub(crate) fn normalize(x: &mut [f64]) {
+    let total: f64 = x.iter().sum();
+    if total > 0.0 {
+        for xi in x.iter_mut() {
+            *xi /= total;
+        }
+    }
+}
+
 /// Fit a `DeterministicTree` to `TrainingData`.
 pub struct DeterministicTreeBuilder<SF, Sample>
     where SF: SplitFinder,
@@ -128,8 +340,10 @@ pub struct DeterministicTreeBuilder<SF, Sample>
     pub(crate) _p: PhantomData<Sample>,
     pub(crate) min_samples_split: usize,
     pub(crate) min_samples_leaf: usize,
+    pub(crate) min_samples_weighted: bool,
     pub(crate) max_depth: Option<usize>,
     pub(crate) bootstrap: Option<usize>,
+    pub(crate) seed: Option<u64>,
     pub(crate) split_finder: SF,
 }
 
@@ -141,9 +355,11 @@ impl<SF, Sample> DeterministicTreeBuilder<SF, Sample>
         DeterministicTreeBuilder {
             min_samples_split,
             min_samples_leaf: 1,
+            min_samples_weighted: false,
             split_finder,
             max_depth: None,
             bootstrap: None,
+            seed: None,
             _p: PhantomData,
         }
     }
@@ -153,85 +369,169 @@ impl<SF, Sample> DeterministicTreeBuilder<SF, Sample>
         self
     }
 
+    pub fn with_min_samples_leaf(mut self, n: usize) -> Self {
+        self.min_samples_leaf = n;
+        self
+    }
+
+    /// Compare `min_samples_split` and `min_samples_leaf` against the total sample weight of a
+    /// node rather than its number of samples.
+    pub fn with_min_samples_weighted(mut self) -> Self {
+        self.min_samples_weighted = true;
+        self
+    }
+
     pub fn with_bootstrap(mut self, n: usize) -> Self {
         self.bootstrap = Some(n);
         self
     }
 
+    /// Seed the random number generator, so that fitting the same data twice produces identical
+    /// trees.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
     pub fn fit<Training>(&self, data: &mut Training) -> DeterministicTree<Sample>
         where Training: ?Sized + TrainingData<Sample>,
               [Sample]: TrainingData<Sample>
     {
-        let mut nodes = vec![Node::Invalid];
+        match self.seed {
+            None => self.fit_with_rng(data, &mut thread_rng()),
+            Some(seed) => self.fit_with_rng(data, &mut ChaChaRng::seed_from_u64(seed)),
+        }
+    }
+
+    /// Fit a tree, drawing all randomness from `rng`. The builder's seed is ignored.
+    pub fn fit_with_rng<Training, R>(&self, data: &mut Training, rng: &mut R) -> DeterministicTree<Sample>
+        where Training: ?Sized + TrainingData<Sample>,
+              [Sample]: TrainingData<Sample>,
+              R: Rng
+    {
+        self.fit_in_bag(data, rng).0
+    }
 
+    /// Fit a tree and, if bootstrapping, return how often each sample in `data` was drawn.
+    pub(crate) fn fit_in_bag<Training, R>(&self, data: &mut Training, rng: &mut R)
+        -> (DeterministicTree<Sample>, Option<Vec<usize>>)
+        where Training: ?Sized + TrainingData<Sample>,
+              [Sample]: TrainingData<Sample>,
+              R: Rng
+    {
         match self.bootstrap {
-            None => self.recursive_fit(&mut nodes, data, 0, 0),
+            None => {
+                let mut tree = DeterministicTree::new_with_nodes(vec![Node::Invalid]);
+                self.recursive_fit(&mut tree, data, 0, 0, rng);
+                (tree, None)
+            }
             Some(n) => {
-                let mut bdat = data.bootstrap_resample(n);
-                self.recursive_fit(&mut nodes, bdat.as_mut_slice(), 0, 0);
+                let (tree, counts) = self.fit_bootstrap(data, n, rng);
+                (tree, Some(counts))
             }
         }
+    }
 
-        DeterministicTree {
-            nodes
-        }
+    /// Fit a tree to `n` samples drawn from `data` with replacement, and return how often each
+    /// sample was drawn. `data` itself is not reordered.
+    pub(crate) fn fit_bootstrap<Training, R>(&self, data: &Training, n: usize, rng: &mut R)
+        -> (DeterministicTree<Sample>, Vec<usize>)
+        where Training: ?Sized + TrainingData<Sample>,
+              [Sample]: TrainingData<Sample>,
+              R: Rng
+    {
+        let mut tree = DeterministicTree::new_with_nodes(vec![Node::Invalid]);
+        let (mut bdat, counts) = data.bootstrap_resample(n, rng);
+        self.recursive_fit(&mut tree, bdat.as_mut_slice(), 0, 0, rng);
+        (tree, counts)
     }
 
-    fn recursive_fit<Training>(&self,
-                               nodes: &mut Vec<Node<Sample>>,
-                               data: &mut Training,
-                               node: usize,
-                               depth: usize)
-        where Training: ?Sized + TrainingData<Sample>
+    fn recursive_fit<Training, R>(&self,
+                                  tree: &mut DeterministicTree<Sample>,
+                                  data: &mut Training,
+                                  node: usize,
+                                  depth: usize,
+                                  rng: &mut R)
+        where Training: ?Sized + TrainingData<Sample>,
+              R: Rng
     {
         if let Some(md) = self.max_depth {
             if depth >= md {
-                nodes[node] = Node::Leaf(data.train_leaf_predictor());
+                tree.nodes[node] = Node::Leaf(data.train_leaf_predictor());
                 return
             }
         }
 
-        if data.n_samples() < self.min_samples_split {
-            nodes[node] = Node::Leaf(data.train_leaf_predictor());
+        if self.node_size(data) < self.min_samples_split as f64 {
+            tree.nodes[node] = Node::Leaf(data.train_leaf_predictor());
             return
         }
 
-        let split = self.split_finder.find_split(data);
+        let split = self.split_finder.find_split(data, rng);
         match split {
             None => {},
             Some(split) => {
+                let parent = Training::Criterion::from_dataset(data).get_weighted();
                 let (left, right) = data.partition_data(&split);
 
-                if left.n_samples() >= self.min_samples_leaf
-                    && right.n_samples() >= self.min_samples_leaf
+                if self.node_size(left) >= self.min_samples_leaf as f64
+                    && self.node_size(right) >= self.min_samples_leaf as f64
                  {
-                    let (l, r) = Self::split_node(nodes, node, split);
+                    let decrease = parent - Training::Criterion::split_score(
+                        &Training::Criterion::from_dataset(left),
+                        &Training::Criterion::from_dataset(right));
+
+                    let (l, r) = Self::split_node(tree, node, split, decrease);
 
-                    self.recursive_fit(nodes, left, l, depth + 1);
-                    self.recursive_fit(nodes, right, r, depth + 1);
+                    self.recursive_fit(tree, left, l, depth + 1, rng);
+                    self.recursive_fit(tree, right, r, depth + 1, rng);
 
                     return
                 }
             }
         }
 
-        nodes[node] = Node::Leaf(data.train_leaf_predictor())
+        tree.nodes[node] = Node::Leaf(data.train_leaf_predictor())
     }
 
-    fn split_node(nodes: &mut Vec<Node<Sample>>,
+    /// Size of a node as compared against the `min_samples_*` limits
+    fn node_size<Training>(&self, data: &Training) -> f64
+        where Training: ?Sized + TrainingData<Sample>
+    {
+        if self.min_samples_weighted {
+            data.total_weight()
+        } else {
+            data.n_samples() as f64
+        }
+    }
+
+    fn split_node(tree: &mut DeterministicTree<Sample>,
                   n: usize,
-                  split: Split<Sample::ThetaSplit, Sample::Feature>)
+                  split: Split<Sample::ThetaSplit, Sample::Feature>,
+                  decrease: f64)
                   -> (usize, usize)
     {
-        let left = nodes.len();
+        let left = tree.nodes.len();
         let right = left + 1;
-        nodes.push(Node::Invalid);
-        nodes.push(Node::Invalid);
-        nodes[n] = Node::Split{
-            theta: split.theta,
-            threshold:split.threshold,
-            left,
-            right};
+        tree.nodes.push(Node::Invalid);
+        tree.nodes.push(Node::Invalid);
+        tree.impurity_decrease.push(0.0);
+        tree.impurity_decrease.push(0.0);
+        tree.impurity_decrease[n] = decrease;
+        tree.nodes[n] = match split.rule {
+            SplitRule::Subset(categories) => Node::SubsetSplit{
+                theta: split.theta,
+                categories,
+                left,
+                right,
+                missing_left: split.missing_left},
+            SplitRule::Threshold(threshold) => Node::Split{
+                theta: split.theta,
+                threshold,
+                left,
+                right,
+                missing_left: split.missing_left},
+        };
         (left, right)
     }
 }
@@ -239,7 +539,7 @@ impl<SF, Sample> DeterministicTreeBuilder<SF, Sample>
 #[cfg(test)]
 mod tests {
     use super::*;
-    use split::BestRandomSplit;
+    use split::{BestRandomSplit, BestSplit};
     use testdata::Sample;
 
     #[test]
@@ -259,15 +559,22 @@ mod tests {
             _p: PhantomData,
             min_samples_split: 2,
             min_samples_leaf: 1,
+            min_samples_weighted: false,
             max_depth: None,
             split_finder: BestRandomSplit::new(1),
             bootstrap: None,
+            seed: None,
         };
 
         let tree = dtb.fit(data);
 
         for sample in data {
             assert_eq!(tree.predict(sample), sample.y);
+            assert_eq!(*tree.predict_leaf(sample), sample.y);
+            match tree.nodes[tree.leaf_index(sample)] {
+                Node::Leaf(l) => assert_eq!(l, sample.y),
+                _ => panic!("leaf_index returned a non-leaf node"),
+            }
         }
 
     }
@@ -294,18 +601,109 @@ mod tests {
         }
     }
 
+    #[test]
+    fn seed() {
+        let data: &mut [_] = &mut [
+            Sample::new(&[0.0, 3.0], 1.0),
+            Sample::new(&[1.0, 1.0], 2.0),
+            Sample::new(&[2.0, 2.0], 1.0),
+            Sample::new(&[3.0, 0.0], 2.0),
+        ];
+
+        let dtb = DeterministicTreeBuilder::new(2, BestRandomSplit::new(1))
+            .with_bootstrap(4)
+            .with_seed(123);
+
+        let tree = format!("{:?}", dtb.fit(data));
+        for _ in 0..10 {
+            assert_eq!(format!("{:?}", dtb.fit(data)), tree);
+        }
+    }
+
+    #[test]
+    fn feature_importances() {
+        let data: &mut [_] = &mut [
+            Sample::new(&[0.0, 5.0, 1.0], 1.0),
+            Sample::new(&[1.0, 3.0, 1.0], 1.0),
+            Sample::new(&[2.0, 7.0, 1.0], 1.0),
+            Sample::new(&[3.0, 1.0, 1.0], 1.0),
+            Sample::new(&[4.0, 6.0, 1.0], 11.0),
+            Sample::new(&[5.0, 0.0, 1.0], 11.0),
+            Sample::new(&[6.0, 4.0, 1.0], 11.0),
+            Sample::new(&[7.0, 2.0, 1.0], 11.0),
+        ];
+
+        let tree = DeterministicTreeBuilder::new(2, BestSplit::new()).fit(data);
+
+        assert_eq!(tree.feature_importances(3), vec![1.0, 0.0, 0.0]);
+
+        let mut total = 0.0;
+        tree.visit_split_importances(|_, decrease| total += decrease);
+        assert!((total - 200.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn missing_values() {
+        let data: &mut [_] = &mut [
+            Sample::new(&[0.0], 1.0),
+            Sample::new(&[1.0], 1.0),
+            Sample::new(&[f64::NAN], 1.0),
+            Sample::new(&[2.0], 5.0),
+            Sample::new(&[3.0], 5.0),
+        ];
+
+        let tree = DeterministicTreeBuilder::new(2, BestSplit::new()).fit(data);
+
+        assert_eq!(tree.predict(&Sample::new(&[f64::NAN], 0.0)), 1.0);
+        assert_eq!(tree.predict(&Sample::new(&[3.0], 0.0)), 5.0);
+        assert!(format!("{:?}", tree).contains("or missing"));
+    }
+
     #[test]
     fn fmt() {
-        let tree: DeterministicTree<Sample<_, _>> = DeterministicTree {
-            nodes: vec![
-                Node::Split { theta: 1, threshold: 2.3, left: 1, right: 2},
-                Node::Leaf(4.5),
-                Node::Invalid,
-            ]
-        };
+        let tree: DeterministicTree<Sample<_, _>> = DeterministicTree::new_with_nodes(vec![
+            Node::Split { theta: 1, threshold: 2.3, left: 1, right: 2, missing_left: false},
+            Node::Leaf(4.5),
+            Node::Invalid,
+        ]);
 
         let formatted = format!("{:?}", tree);
 
         assert_eq!(formatted, "Tree:\n(1) <= 2.3\n +-- 4.5\n +-- *** Invalid ***");
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        use serde_json;
+
+        let data: &mut [_] = &mut [
+            Sample::new(&[0.0], 1.0),
+            Sample::new(&[1.0], 2.0),
+            Sample::new(&[2.0], 1.0),
+            Sample::new(&[3.0], 2.0),
+        ];
+
+        let tree = DeterministicTreeBuilder::new(2, BestRandomSplit::new(1)).fit(data);
+
+        let json = serde_json::to_string(&tree).unwrap();
+        let loaded: DeterministicTree<Sample<_, _>> = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(format!("{:?}", loaded), format!("{:?}", tree));
+        for sample in data {
+            assert_eq!(loaded.predict(sample), tree.predict(sample));
+        }
+
+        // children that are out of range, cyclic or invalid are rejected
+        let leaf = r#"{"Leaf":1.0}"#;
+        for &(left, right) in &[(1, 3), (0, 2), (3, 1)] {
+            let json = format!(r#"{{"nodes":[{{"Split":{{"theta":0,"threshold":0.5,"left":{},"right":{}}}}},{},{}]}}"#,
+                               left, right, leaf, leaf);
+            assert!(serde_json::from_str::<DeterministicTree<Sample<f64, f64>>>(&json).is_err());
+        }
+        let json = format!(r#"{{"nodes":[{{"Split":{{"theta":0,"threshold":0.5,"left":1,"right":2}}}},{},"Invalid"]}}"#, leaf);
+        assert!(serde_json::from_str::<DeterministicTree<Sample<f64, f64>>>(&json).is_err());
+        let json = format!(r#"{{"nodes":[{{"Split":{{"theta":0,"threshold":0.5,"left":1,"right":2}}}},{},{}]}}"#, leaf, leaf);
+        assert!(serde_json::from_str::<DeterministicTree<Sample<f64, f64>>>(&json).is_ok());
+    }
 }
@@ -0,0 +1,102 @@
+//! Model-agnostic feature importances.
+
+use rand::Rng;
+
+use vec2d::Vec2D;
+
+/// Result of `permutation_importance`.
+#[derive(Debug, Clone)]
+pub struct PermutationImportance {
+    /// Mean decrease of the score when permuting each feature
+    pub mean: Vec<f64>,
+
+    /// Standard deviation of the score decrease over repetitions
+    pub std: Vec<f64>,
+}
+
+/// Estimate feature importances by permuting one column of `x` at a time.
+///
+/// `score` evaluates a model on a (possibly permuted) copy of `x`, where larger scores are better.
+/// Typically it predicts each row with a forest and compares the predictions with held-out
+/// targets. The importance of a feature is the decrease of the score when that feature's column
+/// is shuffled, averaged over `n_repeats` shuffles.
+///
+/// Because the model only sees the permuted data through `score`, this works for any model, and
+/// in contrast to impurity-based importances it is not biased toward high-cardinality features.
+pub fn permutation_importance<T, F, R>(x: &Vec2D<T>, n_repeats: usize, rng: &mut R, mut score: F)
+    -> PermutationImportance
+    where T: Clone,
+          F: FnMut(&Vec2D<T>) -> f64,
+          R: Rng,
+{
+    assert!(n_repeats > 0);
+
+    let baseline = score(x);
+
+    let mut x = x.clone();
+    let mut mean = Vec::with_capacity(x.n_cols());
+    let mut std = Vec::with_capacity(x.n_cols());
+
+    for c in 0..x.n_cols() {
+        let original: Vec<T> = (0..x.n_rows()).map(|r| x[(r, c)].clone()).collect();
+        let mut column = original.clone();
+
+        let decreases: Vec<f64> = (0..n_repeats)
+            .map(|_| {
+                rng.shuffle(&mut column);
+                for (r, xi) in column.iter().enumerate() {
+                    x[(r, c)] = xi.clone();
+                }
+                baseline - score(&x)
+            })
+            .collect();
+
+        for (r, xi) in original.into_iter().enumerate() {
+            x[(r, c)] = xi;
+        }
+
+        let m = decreases.iter().sum::<f64>() / n_repeats as f64;
+        let var = decreases.iter().map(|d| (d - m) * (d - m)).sum::<f64>() / n_repeats as f64;
+        mean.push(m);
+        std.push(var.sqrt());
+    }
+
+    PermutationImportance { mean, std }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, prng::ChaChaRng};
+    use api::extra_trees_regressor::{ExtraTreesRegressor, Sample};
+
+    #[test]
+    fn permutation_importance() {
+        // the target only depends on the first feature
+        let x: Vec<_> = (0..100)
+            .flat_map(|i| vec![((i * 37) % 100) as f64, (i % 7) as f64])
+            .collect();
+        let x = Vec2D::from_vec(x, 2);
+        let y: Vec<_> = x.iter().map(|row| if row[0] < 50.0 { 1.0 } else { 3.0 }).collect();
+
+        let forest = ExtraTreesRegressor::new()
+            .with_n_estimators(20)
+            .with_seed(0)
+            .fit(&x, &y);
+
+        let neg_mse = |x: &Vec2D<f64>| {
+            let p = forest.predict_rows(x, |row| Sample::new(row, ()));
+            -p.iter().zip(y.iter()).map(|(p, y)| (p - y) * (p - y)).sum::<f64>() / y.len() as f64
+        };
+
+        let mut rng = ChaChaRng::seed_from_u64(1);
+        let result = super::permutation_importance(&x, 5, &mut rng, neg_mse);
+
+        assert_eq!(result.mean.len(), 2);
+        assert_eq!(result.std.len(), 2);
+        assert!(result.mean[0] > 1.0);
+        assert!(result.mean[0] > 10.0 * result.mean[1].abs());
+        assert!(result.std.iter().all(|&s| s >= 0.0));
+    }
+}
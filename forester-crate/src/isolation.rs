@@ -0,0 +1,343 @@
+//! Isolation forests for unsupervised anomaly detection (Liu et al., 2008).
+//!
+//! Each tree partitions a random subsample of the data with purely random splits until every
+//! sample is isolated or a depth limit is reached. Anomalies are few and different, so they tend
+//! to be isolated after fewer splits than normal samples. The anomaly score maps the average path
+//! length over all trees to the interval (0, 1]; scores close to 1 indicate anomalies and scores
+//! well below 0.5 indicate normal samples.
+//!
+//! Only split features are used, so isolation forests work with any `SampleDescription` and its
+//! `TrainingData`, regardless of the target. Data without a target can use `NoCriterion`.
+
+use rand::{thread_rng, Rng, SeedableRng};
+use rand::prng::ChaChaRng;
+use rand::seq::sample_indices;
+
+use data::{SampleDescription, TrainingData};
+use dtree::{DeterministicTree, DeterministicTreeBuilder, Node};
+use split::RandomSplit;
+
+/// Number of features drawn before a node whose features appear constant becomes a leaf
+const SPLIT_TRIES: usize = 10;
+
+/// Euler-Mascheroni constant
+const EULER_GAMMA: f64 = 0.577_215_664_901_532_9;
+
+/// Average path length of an unsuccessful search in a binary search tree of `n` samples, `c(n)`.
+///
+/// Normalizes path lengths, and estimates the remaining path length in leaves that were not
+/// split down to single samples.
+pub fn average_path_length(n: usize) -> f64 {
+    match n {
+        0 | 1 => 0.0,
+        2 => 1.0,
+        _ => {
+            let n = n as f64;
+            2.0 * ((n - 1.0).ln() + EULER_GAMMA) - 2.0 * (n - 1.0) / n
+        }
+    }
+}
+
+/// A tree of random splits that measures how quickly samples are isolated.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(bound(
+    serialize = "Sample::ThetaSplit: ::serde::Serialize, \
+                 Sample::ThetaLeaf: ::serde::Serialize, \
+                 Sample::Feature: ::serde::Serialize",
+    deserialize = "Sample::ThetaSplit: ::serde::Deserialize<'de>, \
+                   Sample::ThetaLeaf: ::serde::Deserialize<'de>, \
+                   Sample::Feature: ::serde::Deserialize<'de>")))]
+pub struct IsolationTree<Sample>
+    where Sample: SampleDescription
+{
+    tree: DeterministicTree<Sample>,
+    /// Depth of each leaf plus the average path length of its training samples (zero for splits)
+    path_lengths: Vec<f64>,
+}
+
+impl<Sample> IsolationTree<Sample>
+    where Sample: SampleDescription
+{
+    /// Record the path length of each leaf of `tree`, which was fit to `data`.
+    fn new(tree: DeterministicTree<Sample>, data: &[Sample]) -> Self {
+        let mut depths = vec![0; tree.nodes.len()];
+        for (i, node) in tree.nodes.iter().enumerate() {
+            match *node {
+                Node::Split { left, right, .. } | Node::SubsetSplit { left, right, .. } => {
+                    depths[left] = depths[i] + 1;
+                    depths[right] = depths[i] + 1;
+                }
+                Node::Leaf(_) | Node::Invalid => {}
+            }
+        }
+
+        let mut sizes = vec![0; tree.nodes.len()];
+        for sample in data {
+            sizes[tree.leaf_index(sample)] += 1;
+        }
+
+        let path_lengths = tree.nodes.iter()
+            .zip(depths.iter().zip(&sizes))
+            .map(|(node, (&depth, &n))| match *node {
+                Node::Leaf(_) => depth as f64 + average_path_length(n),
+                _ => 0.0,
+            })
+            .collect();
+
+        IsolationTree { tree, path_lengths }
+    }
+
+    /// Number of splits needed to isolate `sample`, with the estimated remainder for leaves that
+    /// contain several training samples.
+    pub fn path_length<TestingSample>(&self, sample: &TestingSample) -> f64
+        where TestingSample: SampleDescription<ThetaSplit=Sample::ThetaSplit,
+            Feature=Sample::Feature> + ?Sized,
+    {
+        self.path_lengths[self.tree.leaf_index(sample)]
+    }
+}
+
+/// An ensemble of isolation trees.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(bound(
+    serialize = "Sample::ThetaSplit: ::serde::Serialize, \
+                 Sample::ThetaLeaf: ::serde::Serialize, \
+                 Sample::Feature: ::serde::Serialize",
+    deserialize = "Sample::ThetaSplit: ::serde::Deserialize<'de>, \
+                   Sample::ThetaLeaf: ::serde::Deserialize<'de>, \
+                   Sample::Feature: ::serde::Deserialize<'de>")))]
+pub struct IsolationForest<Sample>
+    where Sample: SampleDescription
+{
+    estimators: Vec<IsolationTree<Sample>>,
+    /// Number of samples each tree was trained on
+    subsample_size: usize,
+}
+
+impl<Sample> IsolationForest<Sample>
+    where Sample: SampleDescription
+{
+    pub fn estimators(&self) -> &[IsolationTree<Sample>] {
+        &self.estimators
+    }
+
+    /// Path length of `sample`, averaged over all trees.
+    ///
+    /// A forest without trees returns `c(n)` for the subsample size `n`, the path length expected
+    /// of a normal sample.
+    pub fn path_length<TestingSample>(&self, sample: &TestingSample) -> f64
+        where TestingSample: SampleDescription<ThetaSplit=Sample::ThetaSplit,
+            Feature=Sample::Feature> + ?Sized,
+    {
+        if self.estimators.is_empty() {
+            return average_path_length(self.subsample_size)
+        }
+        let total: f64 = self.estimators.iter().map(|tree| tree.path_length(sample)).sum();
+        total / self.estimators.len() as f64
+    }
+
+    /// Anomaly score `2^(-E[h(x)] / c(n))` of `sample`, where `E[h(x)]` is the average path length
+    /// and `c(n)` the average path length of the trees' subsample size.
+    ///
+    /// Subsamples of at most one sample cannot isolate anything (`c(n)` is zero), so every sample
+    /// scores 0.5, like a forest without trees.
+    pub fn anomaly_score<TestingSample>(&self, sample: &TestingSample) -> f64
+        where TestingSample: SampleDescription<ThetaSplit=Sample::ThetaSplit,
+            Feature=Sample::Feature> + ?Sized,
+    {
+        let normalizer = average_path_length(self.subsample_size);
+        if normalizer <= 0.0 {
+            return 0.5
+        }
+        (-self.path_length(sample) / normalizer).exp2()
+    }
+}
+
+/// Fit an `IsolationForest` to `TrainingData`.
+pub struct IsolationForestBuilder {
+    n_estimators: usize,
+    subsample_size: usize,
+    max_depth: Option<usize>,
+    seed: Option<u64>,
+}
+
+impl IsolationForestBuilder {
+    /// Build `n_estimators` trees on subsamples of 256 samples each.
+    pub fn new(n_estimators: usize) -> Self {
+        IsolationForestBuilder {
+            n_estimators,
+            subsample_size: 256,
+            max_depth: None,
+            seed: None,
+        }
+    }
+
+    /// Number of samples drawn (without replacement) to train each tree. Data sets with fewer
+    /// samples are used in full.
+    pub fn with_subsample_size(mut self, n: usize) -> Self {
+        self.subsample_size = n;
+        self
+    }
+
+    /// Maximum depth of the trees. The default is `ceil(log2(subsample_size))`, the average depth
+    /// of a balanced tree, beyond which splits no longer help to tell anomalies apart.
+    pub fn with_max_depth(mut self, md: usize) -> Self {
+        self.max_depth = Some(md);
+        self
+    }
+
+    /// Seed the random number generator, so that fitting the same data twice produces identical
+    /// forests.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn fit<Sample, Training>(&self, data: &Training) -> IsolationForest<Sample>
+        where Sample: SampleDescription + Clone,
+              Training: ?Sized + TrainingData<Sample>,
+              [Sample]: TrainingData<Sample>,
+    {
+        match self.seed {
+            None => self.fit_with_rng(data, &mut ChaChaRng::from_rng(thread_rng())
+                .expect("Could not seed random number generator.")),
+            Some(seed) => self.fit_with_rng(data, &mut ChaChaRng::seed_from_u64(seed)),
+        }
+    }
+
+    /// Fit a forest, drawing all randomness from `rng`. The builder's seed is ignored.
+    pub fn fit_with_rng<Sample, Training, R>(&self, data: &Training, rng: &mut R) -> IsolationForest<Sample>
+        where Sample: SampleDescription + Clone,
+              Training: ?Sized + TrainingData<Sample>,
+              [Sample]: TrainingData<Sample>,
+              R: Rng
+    {
+        let mut samples = Vec::with_capacity(data.n_samples());
+        data.visit_samples(|sample| samples.push(sample.clone()));
+
+        let subsample_size = self.subsample_size.min(samples.len());
+        let max_depth = self.max_depth.unwrap_or_else(|| (subsample_size as f64).log2().ceil() as usize);
+
+        let builder = DeterministicTreeBuilder::new(2, RandomSplit::new(SPLIT_TRIES)).with_max_depth(max_depth);
+
+        let estimators = (0..self.n_estimators)
+            .map(|_| {
+                let mut subsample: Vec<Sample> = sample_indices(rng, samples.len(), subsample_size)
+                    .into_iter()
+                    .map(|i| samples[i].clone())
+                    .collect();
+                let tree = builder.fit_with_rng(&mut subsample[..], rng);
+                IsolationTree::new(tree, &subsample)
+            })
+            .collect();
+
+        IsolationForest {
+            estimators,
+            subsample_size,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64;
+    use std::f64::consts::PI;
+    use criterion::NoCriterion;
+    use data::feature_bounds_of;
+    use testdata::ColorSample;
+
+    /// Unlabelled sample that is split along random directions
+    #[derive(Debug, Clone)]
+    struct Point {
+        x: [f64; 2],
+    }
+
+    impl SampleDescription for Point {
+        type ThetaSplit = (f64, f64);
+        type ThetaLeaf = ();
+        type Feature = f64;
+        type Target = ();
+        type Prediction = ();
+
+        fn target(&self) {}
+
+        fn sample_as_split_feature(&self, theta: &(f64, f64)) -> f64 {
+            self.x[0] * theta.0 + self.x[1] * theta.1
+        }
+
+        fn sample_predict(&self, _: &()) {}
+    }
+
+    impl TrainingData<Point> for [Point] {
+        type Criterion = NoCriterion;
+
+        fn n_samples(&self) -> usize { self.len() }
+
+        fn gen_split_feature<R: Rng>(&self, rng: &mut R) -> (f64, f64) {
+            let a: f64 = rng.gen::<f64>() * PI;
+            (a.sin(), a.cos())
+        }
+
+        fn train_leaf_predictor(&self) {}
+
+        fn feature_bounds(&self, theta: &(f64, f64)) -> (f64, f64) {
+            feature_bounds_of(self, theta)
+        }
+    }
+
+    #[test]
+    fn path_length_normalization() {
+        assert_eq!(average_path_length(1), 0.0);
+        assert_eq!(average_path_length(2), 1.0);
+        assert!((average_path_length(256) - 10.2448).abs() < 1e-4);
+    }
+
+    #[test]
+    fn isolation_forest() {
+        // a grid around the origin and a single outlier
+        let mut data: Vec<_> = (0..100)
+            .map(|i| Point { x: [(i % 10) as f64, (i / 10) as f64] })
+            .collect();
+        data.push(Point { x: [30.0, 30.0] });
+
+        let forest = IsolationForestBuilder::new(100)
+            .with_subsample_size(64)
+            .with_seed(3)
+            .fit(&data[..]);
+        assert_eq!(forest.estimators().len(), 100);
+
+        let outlier = forest.anomaly_score(&Point { x: [30.0, 30.0] });
+        let inlier = forest.anomaly_score(&Point { x: [4.5, 4.5] });
+        assert!(outlier > 0.6);
+        assert!(inlier < 0.5);
+        assert!(forest.path_length(&Point { x: [30.0, 30.0] }) < forest.path_length(&Point { x: [4.5, 4.5] }));
+    }
+
+    #[test]
+    fn categorical_isolation() {
+        // the only sample with color 3 is isolated by subset splits
+        let data: Vec<_> = (0..50)
+            .map(|i| ColorSample::new(if i == 0 { 3 } else { i % 3 }, 0.0, 0.0))
+            .collect();
+
+        let forest = IsolationForestBuilder::new(50).with_seed(0).fit(&data[..]);
+        let rare = forest.path_length(&ColorSample::new(3, 0.0, 0.0));
+        let common = forest.path_length(&ColorSample::new(1, 0.0, 0.0));
+        assert!(rare < common);
+    }
+
+    #[test]
+    fn degenerate_forests() {
+        let data: Vec<_> = (0..10).map(|i| Point { x: [i as f64, 0.0] }).collect();
+
+        let forest = IsolationForestBuilder::new(10).with_subsample_size(1).with_seed(0).fit(&data[..]);
+        assert_eq!(forest.path_length(&Point { x: [100.0, 0.0] }), 0.0);
+        assert_eq!(forest.anomaly_score(&Point { x: [100.0, 0.0] }), 0.5);
+
+        let forest = IsolationForestBuilder::new(0).with_seed(0).fit(&data[..]);
+        assert_eq!(forest.path_length(&Point { x: [100.0, 0.0] }), average_path_length(10));
+        assert_eq!(forest.anomaly_score(&Point { x: [100.0, 0.0] }), 0.5);
+    }
+}
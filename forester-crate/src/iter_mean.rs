@@ -55,6 +55,64 @@ impl_mean! { u64 -> f64 }
 impl_mean! { isize -> f64 }
 impl_mean! { usize -> f64 }
 
+/// Trait for computing the weighted mean of an iterator's values.
+///
+/// The iterator yields pairs of `(weight, value)`. Weights do not need to sum to one.
+pub trait WeightedMean<A=Self>
+{
+    fn weighted_mean<I: Iterator<Item=(f64, A)>>(iter: I) -> Self;
+}
+
+macro_rules! impl_weighted_mean {
+    ($t:ident) => (
+        impl WeightedMean for $t {
+            fn weighted_mean<I: Iterator<Item=(f64, $t)>>(iter: I) -> $t {
+                let (w, sum) = iter.fold((0.0, 0.0), |(w, sum), (wi, x)| (w + wi, sum + wi * x as f64));
+                (sum / w) as $t
+            }
+        }
+    )
+}
+
+impl_weighted_mean! { f32 }
+impl_weighted_mean! { f64 }
+
+/// Element-wise mean of vectors (e.g. multi-output predictions). All vectors must have the same
+/// length.
+impl IterMean for Vec<f64> {
+    fn mean<I: ExactSizeIterator<Item=Vec<f64>>>(iter: I) -> Vec<f64> {
+        Vec::<f64>::weighted_mean(iter.map(|x| (1.0, x)))
+    }
+}
+
+impl<'a> IterMean<&'a Vec<f64>> for Vec<f64> {
+    fn mean<I: ExactSizeIterator<Item=&'a Vec<f64>>>(iter: I) -> Vec<f64> {
+        Vec::<f64>::weighted_mean(iter.map(|x| (1.0, x.clone())))
+    }
+}
+
+/// Element-wise weighted mean of vectors. All vectors must have the same length.
+impl WeightedMean for Vec<f64> {
+    fn weighted_mean<I: Iterator<Item=(f64, Vec<f64>)>>(iter: I) -> Vec<f64> {
+        let mut w = 0.0;
+        let mut sum: Vec<f64> = Vec::new();
+        for (wi, x) in iter {
+            if sum.is_empty() {
+                sum = vec![0.0; x.len()];
+            }
+            assert_eq!(sum.len(), x.len());
+            for (s, xi) in sum.iter_mut().zip(x) {
+                *s += wi * xi;
+            }
+            w += wi;
+        }
+        for s in sum.iter_mut() {
+            *s /= w;
+        }
+        sum
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -134,4 +192,23 @@ mod tests {
         assert_eq!(f64::mean(x.iter()), 2.5);
         assert_eq!(f64::mean(x.into_iter()), 2.5);
     }
+
+    #[test]
+    fn weighted_mean() {
+        let x = vec![(1.0, 1.0f32), (3.0, 2.0)];
+        assert_eq!(f32::weighted_mean(x.into_iter()), 1.75);
+
+        let x = vec![(0.5, 1.0f64), (0.5, 4.0), (0.0, 100.0)];
+        assert_eq!(f64::weighted_mean(x.into_iter()), 2.5);
+
+        let x = vec![(1.0, vec![1.0, 0.0]), (3.0, vec![2.0, 4.0])];
+        assert_eq!(Vec::<f64>::weighted_mean(x.into_iter()), vec![1.75, 3.0]);
+    }
+
+    #[test]
+    fn vec_mean() {
+        let x = vec![vec![1.0, 2.0, 3.0], vec![3.0, 2.0, 0.0]];
+        assert_eq!(Vec::<f64>::mean(x.iter()), vec![2.0, 2.0, 1.5]);
+        assert_eq!(Vec::<f64>::mean(x.into_iter()), vec![2.0, 2.0, 1.5]);
+    }
 }
@@ -1,17 +1,36 @@
 extern crate num_traits;
 extern crate rand;
 
+#[cfg(feature = "rayon")]
+extern crate rayon;
+
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+
+pub mod adaboost;
 pub mod api;
 pub mod array_ops;
+pub mod boosting;
 pub mod categorical;
 pub mod continuous;
 pub mod criterion;
 pub mod data;
 pub mod dforest;
 pub mod dtree;
+pub mod importance;
+pub mod isolation;
 pub mod iter_mean;
+pub mod multilabel;
+pub mod order_stats;
+pub mod persist;
+pub mod ptree;
+pub mod quantile;
 pub mod split;
 pub mod split_between;
+pub mod survival;
 pub mod vec2d;
 
 mod testdata;
@@ -0,0 +1,247 @@
+//! Targets and leaf predictions for multi-label classification, where each sample may have
+//! several labels.
+//!
+//! Samples use a `LabelSet` as `Target` and `MultiLabelGiniCriterion` as split criterion. Leaves
+//! store the (weighted) frequency of each label as `LabelProbabilities`, which forests average.
+
+use std::iter::FromIterator;
+
+use categorical::Categorical;
+use data::{SampleDescription, TrainingData};
+use iter_mean::IterMean;
+use persist::{self, Codec};
+
+/// Set of `Categorical` labels, stored as a bitset
+///
+/// The bitset never ends in a zero word, so equal sets have equal representations.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LabelSet {
+    bits: Vec<u64>,
+}
+
+impl LabelSet {
+    pub fn new() -> Self {
+        LabelSet {
+            bits: Vec::new(),
+        }
+    }
+
+    pub fn insert<C: Categorical>(&mut self, c: C) {
+        let i = c.as_usize();
+        if i / 64 >= self.bits.len() {
+            self.bits.resize(i / 64 + 1, 0);
+        }
+        self.bits[i / 64] |= 1 << (i % 64);
+    }
+
+    pub fn remove<C: Categorical>(&mut self, c: C) {
+        let i = c.as_usize();
+        if let Some(b) = self.bits.get_mut(i / 64) {
+            *b &= !(1 << (i % 64));
+        }
+        while self.bits.last() == Some(&0) {
+            self.bits.pop();
+        }
+    }
+
+    pub fn contains<C: Categorical>(&self, c: C) -> bool {
+        let i = c.as_usize();
+        match self.bits.get(i / 64) {
+            Some(b) => b & (1 << (i % 64)) != 0,
+            None => false,
+        }
+    }
+
+    /// Number of labels in the set
+    pub fn len(&self) -> usize {
+        self.bits.iter().map(|b| b.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|&b| b == 0)
+    }
+
+    /// Call `f` with the id of each label in the set, in increasing order.
+    pub fn visit_labels<F: FnMut(usize)>(&self, mut f: F) {
+        for (k, &b) in self.bits.iter().enumerate() {
+            let mut b = b;
+            while b != 0 {
+                let j = b.trailing_zeros() as usize;
+                f(k * 64 + j);
+                b &= b - 1;
+            }
+        }
+    }
+}
+
+impl<C: Categorical> FromIterator<C> for LabelSet {
+    fn from_iter<I: IntoIterator<Item=C>>(iter: I) -> Self {
+        let mut set = LabelSet::new();
+        for c in iter {
+            set.insert(c);
+        }
+        set
+    }
+}
+
+/// Probability of each label; predicted by the leaves of multi-label trees.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LabelProbabilities {
+    probs: Vec<f64>,
+}
+
+impl LabelProbabilities {
+    /// Weighted frequency of each label among the targets of `data`
+    pub fn from_dataset<S, D>(data: &D) -> Self
+        where S: SampleDescription<Target=LabelSet>,
+              D: ?Sized + TrainingData<S>,
+    {
+        let mut total = 0.0;
+        let mut probs: Vec<f64> = Vec::new();
+        data.visit_samples(|sample| {
+            let w = sample.weight();
+            total += w;
+            sample.target().visit_labels(|i| {
+                if i >= probs.len() {
+                    probs.resize(i + 1, 0.0);
+                }
+                probs[i] += w;
+            });
+        });
+        if total > 0.0 {
+            for p in probs.iter_mut() {
+                *p /= total;
+            }
+        }
+        LabelProbabilities { probs }
+    }
+
+    pub fn probability<C: Categorical>(&self, c: C) -> f64 {
+        self.probs.get(c.as_usize()).cloned().unwrap_or(0.0)
+    }
+
+    /// Set of labels whose probability exceeds `threshold`
+    pub fn labels_above(&self, threshold: f64) -> LabelSet {
+        self.probs.iter()
+            .enumerate()
+            .filter(|&(_, &p)| p > threshold)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    fn mean_of<'a, I: Iterator<Item=&'a LabelProbabilities>>(iter: I, n: usize) -> Self {
+        let mut probs: Vec<f64> = Vec::new();
+        for x in iter {
+            if x.probs.len() > probs.len() {
+                probs.resize(x.probs.len(), 0.0);
+            }
+            for (p, xi) in probs.iter_mut().zip(&x.probs) {
+                *p += xi;
+            }
+        }
+        for p in probs.iter_mut() {
+            *p /= n as f64;
+        }
+        LabelProbabilities { probs }
+    }
+}
+
+impl IterMean for LabelProbabilities {
+    fn mean<I: ExactSizeIterator<Item=LabelProbabilities>>(iter: I) -> Self {
+        let n = iter.len();
+        let all: Vec<_> = iter.collect();
+        Self::mean_of(all.iter(), n)
+    }
+}
+
+impl<'a> IterMean<&'a LabelProbabilities> for LabelProbabilities {
+    fn mean<I: ExactSizeIterator<Item=&'a LabelProbabilities>>(iter: I) -> Self {
+        let n = iter.len();
+        Self::mean_of(iter, n)
+    }
+}
+
+impl Codec for LabelProbabilities {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.probs.encode(buf);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, persist::Error> {
+        Ok(LabelProbabilities { probs: Codec::decode(input)? })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dforest::DeterministicForestBuilder;
+    use dtree::DeterministicTreeBuilder;
+    use split::BestSplit;
+    use testdata::TagSample;
+
+    #[test]
+    fn label_set() {
+        let mut set: LabelSet = vec![3u8, 0, 130].into_iter().collect();
+        assert_eq!(set.len(), 3);
+        assert!(set.contains(3u8));
+        assert!(set.contains(130u8));
+        assert!(!set.contains(1u8));
+        assert!(!set.contains(200u8));
+
+        set.remove(3u8);
+        let mut labels = Vec::new();
+        set.visit_labels(|i| labels.push(i));
+        assert_eq!(labels, vec![0, 130]);
+
+        set.remove(130u8);
+        assert_eq!(set, vec![0u8].into_iter().collect());
+
+        set.remove(0u8);
+        set.remove(130u8);
+        assert!(set.is_empty());
+        assert_eq!(set, LabelSet::new());
+    }
+
+    #[test]
+    fn multilabel_forest() {
+        // label 0 is set for x < 4, label 1 for x >= 2, label 2 for every other sample
+        let x: Vec<_> = (0..8).map(|i| [i as f64]).collect();
+        let data: &mut [_] = &mut x.iter()
+            .map(|xi| {
+                let i = xi[0] as usize;
+                let mut labels = LabelSet::new();
+                if i < 4 { labels.insert(0u8) }
+                if i >= 2 { labels.insert(1u8) }
+                if i & 1 == 0 { labels.insert(2u8) }
+                TagSample::new(xi, labels)
+            })
+            .collect::<Vec<_>>();
+
+        let leaf = LabelProbabilities::from_dataset(&data[..]);
+        assert_eq!(leaf.probability(0u8), 0.5);
+        assert_eq!(leaf.probability(1u8), 0.75);
+        assert_eq!(leaf.probability(2u8), 0.5);
+        assert_eq!(leaf.probability(3u8), 0.0);
+
+        let forest = DeterministicForestBuilder::new(
+            3,
+            DeterministicTreeBuilder::new(2, BestSplit::new()).with_max_depth(2)
+        ).fit(data);
+
+        let p = forest.predict(&TagSample::new(&[0.0], LabelSet::new()));
+        assert_eq!(p.probability(0u8), 1.0);
+        assert_eq!(p.probability(1u8), 0.0);
+        assert_eq!(p.labels_above(0.5), vec![0u8].into_iter().collect());
+
+        let p = forest.predict(&TagSample::new(&[3.0], LabelSet::new()));
+        assert_eq!(p.probability(0u8), 1.0);
+        assert_eq!(p.probability(1u8), 1.0);
+
+        let p = forest.predict(&TagSample::new(&[7.0], LabelSet::new()));
+        assert_eq!(p.probability(0u8), 0.0);
+        assert_eq!(p.probability(1u8), 1.0);
+    }
+}
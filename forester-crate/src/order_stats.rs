@@ -0,0 +1,289 @@
+//! Order statistics of weighted values.
+
+const NIL: usize = usize::MAX;
+
+/// Sums of weights, weighted values and weighted squared values.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Moments {
+    /// Sum of weights
+    pub w: f64,
+    /// Sum of `w * y`
+    pub wy: f64,
+    /// Sum of `w * y * y`
+    pub wy2: f64,
+}
+
+impl Moments {
+    fn of(y: f64, w: f64) -> Self {
+        Moments {
+            w,
+            wy: w * y,
+            wy2: w * y * y,
+        }
+    }
+
+    fn plus(&self, other: &Moments) -> Moments {
+        Moments {
+            w: self.w + other.w,
+            wy: self.wy + other.wy,
+            wy2: self.wy2 + other.wy2,
+        }
+    }
+
+    /// Moments of the values in `self` but not in `other`
+    pub fn minus(&self, other: &Moments) -> Moments {
+        Moments {
+            w: self.w - other.w,
+            wy: self.wy - other.wy,
+            wy2: self.wy2 - other.wy2,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    key: f64,
+    priority: u64,
+    own: Moments,
+    sum: Moments,
+    left: usize,
+    right: usize,
+}
+
+/// A multiset of weighted values that supports adding and removing values, weighted quantiles
+/// and prefix sums in `O(log n)`.
+///
+/// The values are kept in a treap whose nodes are augmented with the moments of their subtree.
+/// Nodes of removed values are kept with zero weight, so the size of the structure is bounded by
+/// the number of distinct values ever added. Values must not be NaN.
+#[derive(Debug, Clone)]
+pub struct WeightedOrderStatistics {
+    nodes: Vec<Node>,
+    root: usize,
+    state: u64,
+}
+
+impl WeightedOrderStatistics {
+    pub fn new() -> Self {
+        WeightedOrderStatistics {
+            nodes: Vec::new(),
+            root: NIL,
+            state: 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+
+    /// Add value `y` with weight `w`. A negative weight removes (part of) a previously added value.
+    pub fn add(&mut self, y: f64, w: f64) {
+        debug_assert!(!y.is_nan());
+        let root = self.root;
+        self.root = self.insert(root, y, &Moments::of(y, w));
+    }
+
+    /// Remove value `y` with weight `w`.
+    pub fn remove(&mut self, y: f64, w: f64) {
+        self.add(y, -w)
+    }
+
+    /// Moments of all values
+    pub fn total(&self) -> Moments {
+        self.sum(self.root)
+    }
+
+    /// Moments of the values less than `y` (or less than or equal to `y` if `inclusive`)
+    pub fn below(&self, y: f64, inclusive: bool) -> Moments {
+        let mut acc = Moments::default();
+        let mut t = self.root;
+        while t != NIL {
+            let node = &self.nodes[t];
+            if node.key < y || (inclusive && node.key == y) {
+                acc = acc.plus(&self.sum(node.left)).plus(&node.own);
+                t = node.right;
+            } else {
+                t = node.left;
+            }
+        }
+        acc
+    }
+
+    /// Smallest value whose cumulative weight reaches the fraction `q` of the total weight, or
+    /// `None` if the total weight is not positive.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        let total = self.total().w;
+        if total <= 0.0 {
+            return None
+        }
+        let mut target = q * total;
+        let mut t = self.root;
+        let mut last = None;
+        while t != NIL {
+            let node = &self.nodes[t];
+            let left = self.sum(node.left).w;
+            if left > 0.0 && target <= left {
+                t = node.left;
+            } else if node.own.w > 0.0 && target <= left + node.own.w {
+                return Some(node.key)
+            } else {
+                if node.own.w > 0.0 {
+                    last = Some(node.key);
+                }
+                target -= left + node.own.w;
+                t = node.right;
+            }
+        }
+        // rounding errors made the target overshoot the largest value
+        last.or_else(|| self.max_positive(self.root))
+    }
+
+    /// Weighted median
+    pub fn median(&self) -> Option<f64> {
+        self.quantile(0.5)
+    }
+
+    fn sum(&self, t: usize) -> Moments {
+        if t == NIL {
+            Moments::default()
+        } else {
+            self.nodes[t].sum
+        }
+    }
+
+    fn max_positive(&self, t: usize) -> Option<f64> {
+        if t == NIL {
+            return None
+        }
+        let node = &self.nodes[t];
+        if let Some(y) = self.max_positive(node.right) {
+            return Some(y)
+        }
+        if node.own.w > 0.0 {
+            return Some(node.key)
+        }
+        self.max_positive(node.left)
+    }
+
+    fn update(&mut self, t: usize) {
+        let sum = self.sum(self.nodes[t].left)
+            .plus(&self.nodes[t].own)
+            .plus(&self.sum(self.nodes[t].right));
+        self.nodes[t].sum = sum;
+    }
+
+    fn next_priority(&mut self) -> u64 {
+        // xorshift64
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    fn insert(&mut self, t: usize, key: f64, m: &Moments) -> usize {
+        if t == NIL {
+            let priority = self.next_priority();
+            self.nodes.push(Node {
+                key,
+                priority,
+                own: *m,
+                sum: *m,
+                left: NIL,
+                right: NIL,
+            });
+            return self.nodes.len() - 1
+        }
+
+        if key == self.nodes[t].key {
+            self.nodes[t].own = self.nodes[t].own.plus(m);
+            if self.nodes[t].own.w <= 0.0 {
+                // don't let rounding errors leave a residue of removed values
+                self.nodes[t].own = Moments::default();
+            }
+            self.update(t);
+            return t
+        }
+
+        if key < self.nodes[t].key {
+            let left = self.nodes[t].left;
+            let child = self.insert(left, key, m);
+            self.nodes[t].left = child;
+            if self.nodes[child].priority > self.nodes[t].priority {
+                // rotate right
+                self.nodes[t].left = self.nodes[child].right;
+                self.nodes[child].right = t;
+                self.update(t);
+                self.update(child);
+                return child
+            }
+        } else {
+            let right = self.nodes[t].right;
+            let child = self.insert(right, key, m);
+            self.nodes[t].right = child;
+            if self.nodes[child].priority > self.nodes[t].priority {
+                // rotate left
+                self.nodes[t].right = self.nodes[child].left;
+                self.nodes[child].left = t;
+                self.update(t);
+                self.update(child);
+                return child
+            }
+        }
+        self.update(t);
+        t
+    }
+}
+
+impl Default for WeightedOrderStatistics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn order_statistics() {
+        let mut stats = WeightedOrderStatistics::new();
+        assert_eq!(stats.median(), None);
+
+        for &y in &[5.0, 1.0, 4.0, 2.0, 3.0] {
+            stats.add(y, 1.0);
+        }
+        assert_eq!(stats.median(), Some(3.0));
+        assert_eq!(stats.quantile(0.0), Some(1.0));
+        assert_eq!(stats.quantile(1.0), Some(5.0));
+        assert_eq!(stats.total(), Moments { w: 5.0, wy: 15.0, wy2: 55.0 });
+        assert_eq!(stats.below(3.0, false), Moments { w: 2.0, wy: 3.0, wy2: 5.0 });
+        assert_eq!(stats.below(3.0, true), Moments { w: 3.0, wy: 6.0, wy2: 14.0 });
+
+        stats.add(1.0, 3.0);
+        assert_eq!(stats.median(), Some(1.0));
+
+        stats.remove(1.0, 4.0);
+        assert_eq!(stats.median(), Some(3.0));
+        assert_eq!(stats.quantile(0.0), Some(2.0));
+
+        stats.remove(3.0, 1.0);
+        assert_eq!(stats.median(), Some(4.0));
+        assert_eq!(stats.quantile(0.3), Some(2.0));
+        assert_eq!(stats.quantile(0.7), Some(5.0));
+
+        // compare with a sorted vector
+        let mut stats = WeightedOrderStatistics::new();
+        let mut values = Vec::new();
+        for i in 0..200 {
+            let y = ((i * 7919) % 101) as f64;
+            stats.add(y, 1.0);
+            values.push(y);
+        }
+        for i in 0..150 {
+            let y = ((i * 7919) % 101) as f64;
+            stats.remove(y, 1.0);
+            let pos = values.iter().position(|&v| v == y).unwrap();
+            values.remove(pos);
+        }
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(stats.median(), Some(values[(values.len() - 1) / 2]));
+        assert_eq!(stats.total().wy, values.iter().sum::<f64>());
+    }
+}
@@ -0,0 +1,742 @@
+//! Compact binary model format.
+//!
+//! Forests are stored in a self-contained binary format that does not depend on any serialization
+//! framework. A file consists of a fixed header followed by the payload:
+//!
+//! | field    | size | description                                   |
+//! |----------|------|-----------------------------------------------|
+//! | magic    | 4    | `b"FRST"`                                     |
+//! | version  | 4    | format version (little endian `u32`)          |
+//! | length   | 8    | payload length in bytes (little endian `u64`) |
+//! | checksum | 4    | CRC-32 of the payload (little endian `u32`)   |
+//! | payload  | *    | number of trees, followed by each tree        |
+//!
+//! Each tree is stored as a flattened node table: the number of nodes followed by the nodes in
+//! the order they are stored in the tree. Split nodes refer to their children by index into that
+//! table.
+//!
+//! Since version 2 the trees are followed by the impurity decrease of every node, one vector per
+//! tree. Version 1 files are still read; their impurity decreases are all zero.
+//!
+//! Version 3 stores the class counts of `extra_trees_classifier::ClassCounts` as weights (`f64`)
+//! rather than integers. Older files are converted when read.
+//!
+//! Since version 4 split nodes end with a flag that tells whether samples with a missing feature
+//! go to the left child. In older files they always go to the right.
+//!
+//! Version 5 adds subset split nodes for categorical features. Like split nodes, they end with
+//! the flag for missing values.
+//!
+//! Since version 6 the payload ends with a flag that tells whether the trees were bootstrapped,
+//! followed by the in-bag counts of each tree (empty if they were not recorded), so that
+//! `ij_variance` works on loaded forests. Out-of-bag predictions are not stored; `oob_score` and
+//! `oob_prediction` of a loaded forest return `None`.
+//!
+//! Split and leaf parameters are written with the `Codec` trait. It is implemented for primitive
+//! types, tuples and vectors; user-defined `ThetaSplit`, `ThetaLeaf` or `Feature` types can be
+//! supported by implementing `Codec` for them.
+
+use std::error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::mem;
+
+use data::SampleDescription;
+use dforest::DeterministicForest;
+use dtree::{DeterministicTree, Node};
+
+/// Magic bytes at the start of every model file
+pub const MAGIC: [u8; 4] = *b"FRST";
+
+/// Format version written by this version of the crate
+pub const FORMAT_VERSION: u32 = 6;
+
+const NODE_INVALID: u8 = 0;
+const NODE_SPLIT: u8 = 1;
+const NODE_LEAF: u8 = 2;
+const NODE_SUBSET_SPLIT: u8 = 3;
+
+/// Errors that can occur while reading or writing models.
+#[derive(Debug)]
+pub enum Error {
+    /// An underlying I/O operation failed
+    Io(io::Error),
+
+    /// The data does not start with the expected magic bytes
+    BadMagic,
+
+    /// The model was written in a format version that is not supported by this crate version
+    UnsupportedVersion(u32),
+
+    /// The payload checksum does not match
+    ChecksumMismatch,
+
+    /// The payload ended before a value could be fully decoded
+    UnexpectedEnd,
+
+    /// The payload is structurally invalid (e.g. unknown node type or invalid node index)
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "I/O error: {}", e),
+            Error::BadMagic => write!(f, "not a forester model (bad magic bytes)"),
+            Error::UnsupportedVersion(v) => write!(f, "unsupported model format version {}", v),
+            Error::ChecksumMismatch => write!(f, "model checksum mismatch"),
+            Error::UnexpectedEnd => write!(f, "unexpected end of model data"),
+            Error::Corrupt(what) => write!(f, "corrupt model data: {}", what),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// Binary encoding of model parameters.
+pub trait Codec: Sized {
+    /// Append the binary representation of `self` to `buf`.
+    fn encode(&self, buf: &mut Vec<u8>);
+
+    /// Decode a value from the front of `input` and advance `input` past the consumed bytes.
+    fn decode(input: &mut &[u8]) -> Result<Self, Error>;
+
+    /// Decode a value that was written in format `version`.
+    ///
+    /// Types whose encoding changed between format versions override this to read older files.
+    /// The default ignores the version.
+    fn decode_version(input: &mut &[u8], _version: u32) -> Result<Self, Error> {
+        Self::decode(input)
+    }
+}
+
+/// Split off the first `n` bytes of `input`.
+fn take<'a>(input: &mut &'a [u8], n: usize) -> Result<&'a [u8], Error> {
+    if input.len() < n {
+        return Err(Error::UnexpectedEnd)
+    }
+    let (head, tail) = input.split_at(n);
+    *input = tail;
+    Ok(head)
+}
+
+macro_rules! impl_codec_int {
+    ( $( $t:ident ),* ) => {
+        $(
+            impl Codec for $t {
+                fn encode(&self, buf: &mut Vec<u8>) {
+                    buf.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn decode(input: &mut &[u8]) -> Result<Self, Error> {
+                    let mut bytes = [0; mem::size_of::<$t>()];
+                    bytes.copy_from_slice(take(input, mem::size_of::<$t>())?);
+                    Ok($t::from_le_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+
+impl_codec_int!{u8, u16, u32, u64, i8, i16, i32, i64}
+
+// `usize` and `isize` are always stored with 64 bits so that models are portable between
+// platforms.
+
+impl Codec for usize {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (*self as u64).encode(buf)
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, Error> {
+        let x = u64::decode(input)?;
+        if x > usize::MAX as u64 {
+            return Err(Error::Corrupt("integer too large for this platform"))
+        }
+        Ok(x as usize)
+    }
+}
+
+impl Codec for isize {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (*self as i64).encode(buf)
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, Error> {
+        let x = i64::decode(input)?;
+        if x > isize::MAX as i64 || x < isize::MIN as i64 {
+            return Err(Error::Corrupt("integer too large for this platform"))
+        }
+        Ok(x as isize)
+    }
+}
+
+impl Codec for f32 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.to_bits().encode(buf)
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, Error> {
+        u32::decode(input).map(f32::from_bits)
+    }
+}
+
+impl Codec for f64 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.to_bits().encode(buf)
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, Error> {
+        u64::decode(input).map(f64::from_bits)
+    }
+}
+
+impl Codec for bool {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (*self as u8).encode(buf)
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, Error> {
+        match u8::decode(input)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(Error::Corrupt("invalid boolean")),
+        }
+    }
+}
+
+impl<A: Codec, B: Codec> Codec for (A, B) {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf);
+        self.1.encode(buf);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, Error> {
+        Ok((A::decode(input)?, B::decode(input)?))
+    }
+
+    fn decode_version(input: &mut &[u8], version: u32) -> Result<Self, Error> {
+        Ok((A::decode_version(input, version)?, B::decode_version(input, version)?))
+    }
+}
+
+impl<T: Codec> Codec for Vec<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.len().encode(buf);
+        for x in self {
+            x.encode(buf);
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, Error> {
+        Self::decode_version(input, FORMAT_VERSION)
+    }
+
+    fn decode_version(input: &mut &[u8], version: u32) -> Result<Self, Error> {
+        let n = usize::decode(input)?;
+        // don't trust the length for preallocation; every element needs at least one byte
+        let mut v = Vec::with_capacity(n.min(input.len()));
+        for _ in 0..n {
+            v.push(T::decode_version(input, version)?);
+        }
+        Ok(v)
+    }
+}
+
+impl<Sample> Codec for DeterministicTree<Sample>
+    where Sample: SampleDescription,
+          Sample::ThetaSplit: Codec,
+          Sample::ThetaLeaf: Codec,
+          Sample::Feature: Codec,
+{
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.nodes.len().encode(buf);
+        for node in &self.nodes {
+            match *node {
+                Node::Invalid => NODE_INVALID.encode(buf),
+                Node::Split { ref theta, ref threshold, left, right, missing_left } => {
+                    NODE_SPLIT.encode(buf);
+                    theta.encode(buf);
+                    threshold.encode(buf);
+                    left.encode(buf);
+                    right.encode(buf);
+                    missing_left.encode(buf);
+                }
+                Node::SubsetSplit { ref theta, ref categories, left, right, missing_left } => {
+                    NODE_SUBSET_SPLIT.encode(buf);
+                    theta.encode(buf);
+                    categories.encode(buf);
+                    left.encode(buf);
+                    right.encode(buf);
+                    missing_left.encode(buf);
+                }
+                Node::Leaf(ref l) => {
+                    NODE_LEAF.encode(buf);
+                    l.encode(buf);
+                }
+            }
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, Error> {
+        Self::decode_version(input, FORMAT_VERSION)
+    }
+
+    fn decode_version(input: &mut &[u8], version: u32) -> Result<Self, Error> {
+        let n = usize::decode(input)?;
+        if n == 0 {
+            return Err(Error::Corrupt("empty tree"))
+        }
+
+        let mut nodes = Vec::with_capacity(n.min(input.len()));
+        for _ in 0..n {
+            let node = match u8::decode(input)? {
+                NODE_INVALID => return Err(Error::Corrupt("invalid node")),
+                NODE_SPLIT => {
+                    let theta = Codec::decode_version(input, version)?;
+                    let threshold = Codec::decode_version(input, version)?;
+                    let left = usize::decode(input)?;
+                    let right = usize::decode(input)?;
+                    let missing_left = if version >= 4 { bool::decode(input)? } else { false };
+                    Node::Split { theta, threshold, left, right, missing_left }
+                }
+                NODE_SUBSET_SPLIT if version >= 5 => {
+                    let theta = Codec::decode_version(input, version)?;
+                    let categories = Codec::decode_version(input, version)?;
+                    let left = usize::decode(input)?;
+                    let right = usize::decode(input)?;
+                    let missing_left = bool::decode(input)?;
+                    Node::SubsetSplit { theta, categories, left, right, missing_left }
+                }
+                NODE_LEAF => Node::Leaf(Codec::decode_version(input, version)?),
+                _ => return Err(Error::Corrupt("unknown node type")),
+            };
+            nodes.push(node);
+        }
+
+        // invalid nodes and bad child indices would make `predict` panic or loop
+        let tree = DeterministicTree::new_with_nodes(nodes);
+        tree.validate().map_err(Error::Corrupt)?;
+        Ok(tree)
+    }
+}
+
+impl<Sample> DeterministicForest<Sample>
+    where Sample: SampleDescription,
+          Sample::ThetaSplit: Codec,
+          Sample::ThetaLeaf: Codec,
+          Sample::Feature: Codec,
+{
+    /// Write the forest in the binary model format.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        let mut payload = Vec::new();
+        self.estimators.encode(&mut payload);
+        for tree in &self.estimators {
+            tree.impurity_decrease.encode(&mut payload);
+        }
+        self.bootstrapped.encode(&mut payload);
+        self.in_bag.encode(&mut payload);
+
+        let mut header = Vec::with_capacity(20);
+        header.extend_from_slice(&MAGIC);
+        FORMAT_VERSION.encode(&mut header);
+        (payload.len() as u64).encode(&mut header);
+        crc32(&payload).encode(&mut header);
+
+        writer.write_all(&header)?;
+        writer.write_all(&payload)?;
+        Ok(())
+    }
+
+    /// Read a forest that was stored in the binary model format.
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, Error> {
+        let mut header = [0; 20];
+        reader.read_exact(&mut header).map_err(eof_as_unexpected_end)?;
+        let mut header = &header[..];
+
+        if take(&mut header, 4)? != MAGIC {
+            return Err(Error::BadMagic)
+        }
+
+        let version = u32::decode(&mut header)?;
+        if version == 0 || version > FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion(version))
+        }
+
+        let length = u64::decode(&mut header)?;
+        let checksum = u32::decode(&mut header)?;
+
+        let mut payload = Vec::new();
+        reader.take(length).read_to_end(&mut payload)?;
+        if (payload.len() as u64) < length {
+            return Err(Error::UnexpectedEnd)
+        }
+
+        if crc32(&payload) != checksum {
+            return Err(Error::ChecksumMismatch)
+        }
+
+        let mut input = &payload[..];
+        let mut estimators: Vec<DeterministicTree<Sample>> = Codec::decode_version(&mut input, version)?;
+        if version >= 2 {
+            for tree in &mut estimators {
+                let decrease: Vec<f64> = Codec::decode(&mut input)?;
+                if decrease.len() != tree.nodes.len() {
+                    return Err(Error::Corrupt("impurity decrease does not match node count"))
+                }
+                tree.impurity_decrease = decrease;
+            }
+        }
+        let mut forest = DeterministicForest::new_with_estimators(estimators);
+        if version >= 6 {
+            forest.bootstrapped = bool::decode(&mut input)?;
+            let in_bag: Vec<Vec<usize>> = Codec::decode(&mut input)?;
+            if !in_bag.is_empty() && (in_bag.len() != forest.estimators.len()
+                                      || in_bag.iter().any(|counts| counts.len() != in_bag[0].len())) {
+                return Err(Error::Corrupt("in-bag counts do not match the trees"))
+            }
+            forest.in_bag = in_bag;
+        }
+        if !input.is_empty() {
+            return Err(Error::Corrupt("trailing data"))
+        }
+
+        Ok(forest)
+    }
+}
+
+fn eof_as_unexpected_end(e: io::Error) -> Error {
+    match e.kind() {
+        io::ErrorKind::UnexpectedEof => Error::UnexpectedEnd,
+        _ => Error::Io(e),
+    }
+}
+
+/// CRC-32 (IEEE 802.3) checksum
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (!(crc & 1)).wrapping_add(1);
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use api::extra_trees_classifier::{Classes, ExtraTreesClassifier};
+    use api::extra_trees_regressor::ExtraTreesRegressor;
+    use api::{extra_trees_classifier, extra_trees_regressor};
+    use categorical::CatCount;
+    use vec2d::Vec2D;
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn codec_primitives() {
+        let mut buf = Vec::new();
+        42usize.encode(&mut buf);
+        (-3i16).encode(&mut buf);
+        1.5f32.encode(&mut buf);
+        (7u8, -0.25f64).encode(&mut buf);
+        vec![true, false].encode(&mut buf);
+
+        let mut input = &buf[..];
+        assert_eq!(usize::decode(&mut input).unwrap(), 42);
+        assert_eq!(i16::decode(&mut input).unwrap(), -3);
+        assert_eq!(f32::decode(&mut input).unwrap(), 1.5);
+        assert_eq!(<(u8, f64)>::decode(&mut input).unwrap(), (7, -0.25));
+        assert_eq!(Vec::<bool>::decode(&mut input).unwrap(), vec![true, false]);
+        assert!(input.is_empty());
+
+        match u32::decode(&mut &[1, 2, 3][..]) {
+            Err(Error::UnexpectedEnd) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn regressor_roundtrip() {
+        let x = Vec2D::from_slice(&[1.0, 2.0, 3.0, 7.0, 8.0, 9.0], 1);
+        let y = vec![5.0, 5.0, 5.0, 2.0, 2.0, 2.0];
+
+        let forest = ExtraTreesRegressor::new().fit(&x, &y);
+
+        let mut buf = Vec::new();
+        forest.write_to(&mut buf).unwrap();
+        let loaded: DeterministicForest<extra_trees_regressor::Sample<f64, f64>> =
+            DeterministicForest::read_from(&buf[..]).unwrap();
+
+        assert_eq!(format!("{:?}", loaded), format!("{:?}", forest));
+        for xi in [[-1.0], [2.0], [5.0], [8.0], [100.0]].iter() {
+            let sample = extra_trees_regressor::Sample::new(xi, ());
+            assert_eq!(loaded.predict(&sample), forest.predict(&sample));
+        }
+        assert_eq!(loaded.feature_importances(1), forest.feature_importances(1));
+    }
+
+    /// Encode a tree in the node layout used before format version 4
+    fn encode_without_missing_flag<S>(tree: &DeterministicTree<S>, buf: &mut Vec<u8>)
+        where S: SampleDescription,
+              S::ThetaSplit: Codec,
+              S::ThetaLeaf: Codec,
+              S::Feature: Codec,
+    {
+        tree.nodes.len().encode(buf);
+        for node in &tree.nodes {
+            match *node {
+                Node::Invalid => NODE_INVALID.encode(buf),
+                Node::Split { ref theta, ref threshold, left, right, .. } => {
+                    NODE_SPLIT.encode(buf);
+                    theta.encode(buf);
+                    threshold.encode(buf);
+                    left.encode(buf);
+                    right.encode(buf);
+                }
+                Node::SubsetSplit { .. } => unreachable!(),
+                Node::Leaf(ref l) => {
+                    NODE_LEAF.encode(buf);
+                    l.encode(buf);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bootstrap_roundtrip() {
+        let x = Vec2D::from_slice(&(0..20).map(|i| i as f64).collect::<Vec<_>>(), 1);
+        let y: Vec<f64> = (0..20).map(|i| ((i * 7) % 5) as f64).collect();
+        let forest = ExtraTreesRegressor::new().with_n_estimators(20).with_bootstrap(20).fit(&x, &y);
+
+        let mut buf = Vec::new();
+        forest.write_to(&mut buf).unwrap();
+        let loaded: DeterministicForest<extra_trees_regressor::Sample<f64, f64>> =
+            DeterministicForest::read_from(&buf[..]).unwrap();
+
+        let sample = extra_trees_regressor::Sample::new(&[4.5], ());
+        assert!(forest.ij_variance(&sample).is_some());
+        assert_eq!(loaded.ij_variance(&sample), forest.ij_variance(&sample));
+        assert_eq!(loaded.in_bag_counts(3), forest.in_bag_counts(3));
+    }
+
+    #[test]
+    fn read_version_1() {
+        let x = Vec2D::from_slice(&[1.0, 2.0, 3.0, 7.0, 8.0, 9.0], 1);
+        let y = vec![5.0, 5.0, 5.0, 2.0, 2.0, 2.0];
+        let forest = ExtraTreesRegressor::new().fit(&x, &y);
+
+        let mut payload = Vec::new();
+        forest.estimators.len().encode(&mut payload);
+        for tree in &forest.estimators {
+            encode_without_missing_flag(tree, &mut payload);
+        }
+
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&MAGIC);
+        1u32.encode(&mut buf);
+        (payload.len() as u64).encode(&mut buf);
+        crc32(&payload).encode(&mut buf);
+        buf.extend_from_slice(&payload);
+
+        let loaded: DeterministicForest<extra_trees_regressor::Sample<f64, f64>> =
+            DeterministicForest::read_from(&buf[..]).unwrap();
+
+        assert_eq!(format!("{:?}", loaded), format!("{:?}", forest));
+        assert_eq!(loaded.feature_importances(1), vec![0.0]);
+    }
+
+    #[test]
+    fn classifier_roundtrip() {
+        let x = Vec2D::from_slice(&[1, 2, 3, 7, 8, 9], 1);
+        let y = vec![1, 1, 1, 2, 2, 2];
+
+        let forest = ExtraTreesClassifier::new().fit(&x, &y);
+
+        let mut buf = Vec::new();
+        forest.write_to(&mut buf).unwrap();
+        let loaded: DeterministicForest<extra_trees_classifier::Sample<i32, Classes>> =
+            DeterministicForest::read_from(&buf[..]).unwrap();
+
+        assert_eq!(format!("{:?}", loaded), format!("{:?}", forest));
+    }
+
+    #[test]
+    fn read_version_2_classifier() {
+        // a single leaf with integer class counts [0, 3, 1]
+        let mut payload = Vec::new();
+        1usize.encode(&mut payload);
+        1usize.encode(&mut payload);
+        NODE_LEAF.encode(&mut payload);
+        vec![0usize, 3, 1].encode(&mut payload);
+        vec![0.0f64].encode(&mut payload);
+
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&MAGIC);
+        2u32.encode(&mut buf);
+        (payload.len() as u64).encode(&mut buf);
+        crc32(&payload).encode(&mut buf);
+        buf.extend_from_slice(&payload);
+
+        let loaded: DeterministicForest<extra_trees_classifier::Sample<i32, Classes>> =
+            DeterministicForest::read_from(&buf[..]).unwrap();
+
+        let p = loaded.predict(&extra_trees_classifier::Sample::new(&[0], ()));
+        assert_eq!(p.probability(Classes(1)), 0.75);
+        assert_eq!(p.probability(Classes(2)), 0.25);
+    }
+
+    #[test]
+    fn subset_split_roundtrip() {
+        use dtree::DeterministicTreeBuilder;
+        use split::BestSplit;
+        use testdata::ColorSample;
+
+        let data: &mut [_] = &mut (0..20)
+            .map(|i| ColorSample::new(i % 4, 0.0, [1.0, 5.0, 5.0, 1.0][i % 4]))
+            .collect::<Vec<_>>();
+        let tree = DeterministicTreeBuilder::new(2, BestSplit::new()).fit(data);
+
+        let mut buf = Vec::new();
+        tree.encode(&mut buf);
+        let loaded = DeterministicTree::<ColorSample<f64>>::decode(&mut &buf[..]).unwrap();
+
+        assert_eq!(format!("{:?}", loaded), format!("{:?}", tree));
+        assert!(format!("{:?}", loaded).contains(" in "));
+        for sample in data.iter() {
+            assert_eq!(loaded.predict(sample), sample.y);
+        }
+    }
+
+    #[test]
+    fn invalid_data() {
+        type Forest<'a> = DeterministicForest<extra_trees_regressor::Sample<'a, f64, f64>>;
+
+        let x = Vec2D::from_slice(&[1.0, 2.0, 3.0, 7.0, 8.0, 9.0], 1);
+        let y = vec![5.0, 5.0, 5.0, 2.0, 2.0, 2.0];
+        let mut buf = Vec::new();
+        ExtraTreesRegressor::new().fit(&x, &y).write_to(&mut buf).unwrap();
+
+        let mut bad = buf.clone();
+        bad[0] = b'X';
+        match Forest::read_from(&bad[..]) {
+            Err(Error::BadMagic) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        let mut bad = buf.clone();
+        bad[4] = 99;
+        match Forest::read_from(&bad[..]) {
+            Err(Error::UnsupportedVersion(99)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        let mut bad = buf.clone();
+        let last = bad.len() - 1;
+        bad[last] ^= 0xFF;
+        match Forest::read_from(&bad[..]) {
+            Err(Error::ChecksumMismatch) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        match Forest::read_from(&buf[..buf.len() - 1]) {
+            Err(Error::UnexpectedEnd) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        match Forest::read_from(&buf[..10]) {
+            Err(Error::UnexpectedEnd) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn cyclic_tree() {
+        type Sample<'a> = extra_trees_regressor::Sample<'a, f64, f64>;
+
+        for &(left, right) in &[(0, 2), (1, 0), (2, 1)] {
+            let tree = DeterministicTree::<Sample>::new_with_nodes(vec![
+                Node::Leaf(1.0),
+                Node::Split { theta: 0, threshold: 0.5, left, right, missing_left: false },
+                Node::Leaf(2.0),
+            ]);
+            let mut buf = Vec::new();
+            tree.encode(&mut buf);
+            match DeterministicTree::<Sample>::decode(&mut &buf[..]) {
+                Err(Error::Corrupt(_)) => {}
+                r => panic!("unexpected result: {:?}", r.map(|t| format!("{:?}", t))),
+            }
+        }
+    }
+
+    #[test]
+    fn unsorted_categories() {
+        type Sample<'a> = extra_trees_regressor::Sample<'a, f64, f64>;
+
+        for categories in &[vec![3, 1], vec![1, 1, 3]] {
+            let tree = DeterministicTree::<Sample>::new_with_nodes(vec![
+                Node::SubsetSplit { theta: 0, categories: categories.clone(), left: 1, right: 2, missing_left: false },
+                Node::Leaf(1.0),
+                Node::Leaf(2.0),
+            ]);
+            let mut buf = Vec::new();
+            tree.encode(&mut buf);
+            match DeterministicTree::<Sample>::decode(&mut &buf[..]) {
+                Err(Error::Corrupt(_)) => {}
+                r => panic!("unexpected result: {:?}", r.map(|t| format!("{:?}", t))),
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_nodes() {
+        type Sample<'a> = extra_trees_regressor::Sample<'a, f64, f64>;
+
+        let tree = DeterministicTree::<Sample>::new_with_nodes(vec![
+            Node::Split { theta: 0, threshold: 0.5, left: 1, right: 2, missing_left: false },
+            Node::Leaf(1.0),
+            Node::Invalid,
+        ]);
+        let mut buf = Vec::new();
+        tree.encode(&mut buf);
+        match DeterministicTree::<Sample>::decode(&mut &buf[..]) {
+            Err(Error::Corrupt(_)) => {}
+            r => panic!("unexpected result: {:?}", r.map(|t| format!("{:?}", t))),
+        }
+
+        // subset splits only exist since version 5
+        let tree = DeterministicTree::<Sample>::new_with_nodes(vec![
+            Node::SubsetSplit { theta: 0, categories: vec![1], left: 1, right: 2, missing_left: false },
+            Node::Leaf(1.0),
+            Node::Leaf(2.0),
+        ]);
+        let mut buf = Vec::new();
+        tree.encode(&mut buf);
+        assert!(DeterministicTree::<Sample>::decode_version(&mut &buf[..], 5).is_ok());
+        match DeterministicTree::<Sample>::decode_version(&mut &buf[..], 4) {
+            Err(Error::Corrupt(_)) => {}
+            r => panic!("unexpected result: {:?}", r.map(|t| format!("{:?}", t))),
+        }
+    }
+}
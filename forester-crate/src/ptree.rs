@@ -0,0 +1,447 @@
+//! Probabilistic Tree module
+//!
+//! A probabilistic tree passes each sample down both sides of a split. The probability of going
+//! left is given by a smooth gating function of the feature value, and the tree's prediction is
+//! the mean of all leaf predictions, weighted by the probability of reaching each leaf.
+//!
+//! Trees are grown with hard splits (exactly like deterministic trees), using the same
+//! `TrainingData` and `SplitFinder` traits. Only prediction is soft.
+
+use std::marker::PhantomData;
+
+use rand::{thread_rng, Rng, SeedableRng};
+use rand::prng::ChaChaRng;
+
+use continuous::Continuous;
+use data::{is_missing, SampleDescription, TrainingData};
+use iter_mean::{IterMean, WeightedMean};
+use split::{Split, SplitFinder, SplitRule};
+
+/// Temperature of a split's gating function. Higher temperatures result in softer splits.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Temperature {
+    /// Use the same temperature for every split.
+    Fixed(f64),
+
+    /// Use a fraction of the feature's range in the training data that reaches the split.
+    Relative(f64),
+}
+
+/// Probability of passing a sample with feature value `x` to the left side of a split.
+///
+/// This is a sigmoid of `threshold - x`. A temperature of 0 results in a hard split.
+pub fn gate(x: f64, threshold: f64, temperature: f64) -> f64 {
+    if temperature <= 0.0 {
+        if x <= threshold { 1.0 } else { 0.0 }
+    } else {
+        1.0 / (1.0 + ((x - threshold) / temperature).exp())
+    }
+}
+
+/// A probabilistic tree node.
+pub enum Node<T>
+    where T: SampleDescription
+{
+    Invalid,  // placeholder used during tree construction
+    Split{
+        theta: T::ThetaSplit,
+        threshold: T::Feature,
+        temperature: f64,
+        left: usize,
+        right: usize,
+        /// Pass samples with a missing feature to the left child (with probability one)
+        missing_left: bool,
+    },
+    Leaf(T::ThetaLeaf),
+}
+
+/// A probabilistic (soft-split) decision tree.
+pub struct ProbabilisticTree<Sample>
+    where Sample: SampleDescription
+{
+    nodes: Vec<Node<Sample>>
+}
+
+impl<Sample> ProbabilisticTree<Sample>
+    where Sample: SampleDescription,
+          Sample::Feature: Continuous,
+{
+    pub fn predict<TestingSample>(&self, sample: &TestingSample) -> TestingSample::Prediction
+        where TestingSample: SampleDescription<ThetaSplit=Sample::ThetaSplit,
+            ThetaLeaf=Sample::ThetaLeaf,
+            Feature=Sample::Feature> + ?Sized,
+              TestingSample::Prediction: WeightedMean,
+    {
+        let mut leaves = Vec::new();
+        self.collect_leaves(sample, 0, 1.0, &mut leaves);
+        TestingSample::Prediction::weighted_mean(
+            leaves.into_iter().map(|(p, l)| (p, sample.sample_predict(l))))
+    }
+
+    /// Call `visitor` with the probability of reaching each leaf and the leaf's parameters.
+    ///
+    /// Leaves that cannot be reached are skipped.
+    pub fn visit_leaves<TestingSample, F>(&self, sample: &TestingSample, mut visitor: F)
+        where TestingSample: SampleDescription<ThetaSplit=Sample::ThetaSplit,
+            ThetaLeaf=Sample::ThetaLeaf,
+            Feature=Sample::Feature> + ?Sized,
+              F: FnMut(f64, &Sample::ThetaLeaf),
+    {
+        let mut leaves = Vec::new();
+        self.collect_leaves(sample, 0, 1.0, &mut leaves);
+        for (p, l) in leaves {
+            visitor(p, l);
+        }
+    }
+
+    fn collect_leaves<'a, TestingSample>(&'a self,
+                                         sample: &TestingSample,
+                                         n: usize,
+                                         p: f64,
+                                         leaves: &mut Vec<(f64, &'a Sample::ThetaLeaf)>)
+        where TestingSample: SampleDescription<ThetaSplit=Sample::ThetaSplit,
+            ThetaLeaf=Sample::ThetaLeaf,
+            Feature=Sample::Feature> + ?Sized,
+    {
+        match self.nodes[n] {
+            Node::Split { ref theta, ref threshold, temperature, left, right, missing_left } => {
+                let x = sample.sample_as_split_feature(theta);
+                let p_left = if is_missing(&x) {
+                    if missing_left { 1.0 } else { 0.0 }
+                } else {
+                    gate(x.as_float(), threshold.as_float(), temperature)
+                };
+                if p_left > 0.0 {
+                    self.collect_leaves(sample, left, p * p_left, leaves);
+                }
+                if p_left < 1.0 {
+                    self.collect_leaves(sample, right, p * (1.0 - p_left), leaves);
+                }
+            }
+            Node::Leaf(ref l) => leaves.push((p, l)),
+            Node::Invalid => panic!("Invalid node found. Tree may not be fully constructed."),
+        }
+    }
+}
+
+/// Fit a `ProbabilisticTree` to `TrainingData`.
+pub struct ProbabilisticTreeBuilder<SF, Sample>
+    where SF: SplitFinder,
+          Sample: SampleDescription,
+{
+    pub(crate) _p: PhantomData<Sample>,
+    pub(crate) min_samples_split: usize,
+    pub(crate) min_samples_leaf: usize,
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) temperature: Temperature,
+    pub(crate) seed: Option<u64>,
+    pub(crate) split_finder: SF,
+}
+
+impl<SF, Sample> ProbabilisticTreeBuilder<SF, Sample>
+    where SF: SplitFinder,
+          Sample: SampleDescription,
+          Sample::Feature: Continuous,
+{
+    pub fn new(min_samples_split: usize, split_finder: SF) -> Self {
+        ProbabilisticTreeBuilder {
+            min_samples_split,
+            min_samples_leaf: 1,
+            split_finder,
+            max_depth: None,
+            temperature: Temperature::Relative(0.1),
+            seed: None,
+            _p: PhantomData,
+        }
+    }
+
+    pub fn with_max_depth(mut self, md: usize) -> Self {
+        self.max_depth = Some(md);
+        self
+    }
+
+    pub fn with_temperature(mut self, temperature: Temperature) -> Self {
+        self.temperature = temperature;
+        self
+    }
+
+    /// Seed the random number generator, so that fitting the same data twice produces identical
+    /// trees.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn fit<Training>(&self, data: &mut Training) -> ProbabilisticTree<Sample>
+        where Training: ?Sized + TrainingData<Sample>
+    {
+        match self.seed {
+            None => self.fit_with_rng(data, &mut thread_rng()),
+            Some(seed) => self.fit_with_rng(data, &mut ChaChaRng::seed_from_u64(seed)),
+        }
+    }
+
+    /// Fit a tree, drawing all randomness from `rng`. The builder's seed is ignored.
+    pub fn fit_with_rng<Training, R>(&self, data: &mut Training, rng: &mut R) -> ProbabilisticTree<Sample>
+        where Training: ?Sized + TrainingData<Sample>,
+              R: Rng
+    {
+        let mut nodes = vec![Node::Invalid];
+        self.recursive_fit(&mut nodes, data, 0, 0, rng);
+        ProbabilisticTree {
+            nodes
+        }
+    }
+
+    fn recursive_fit<Training, R>(&self,
+                                  nodes: &mut Vec<Node<Sample>>,
+                                  data: &mut Training,
+                                  node: usize,
+                                  depth: usize,
+                                  rng: &mut R)
+        where Training: ?Sized + TrainingData<Sample>,
+              R: Rng
+    {
+        if let Some(md) = self.max_depth {
+            if depth >= md {
+                nodes[node] = Node::Leaf(data.train_leaf_predictor());
+                return
+            }
+        }
+
+        if data.n_samples() < self.min_samples_split {
+            nodes[node] = Node::Leaf(data.train_leaf_predictor());
+            return
+        }
+
+        // soft gates need ordered features, so categorical subset splits are not supported
+        if let Some(split) = self.split_finder.find_split(data, rng).filter(|split| split.threshold().is_some()) {
+            let temperature = self.split_temperature(data, &split);
+            let (left, right) = data.partition_data(&split);
+
+            if left.n_samples() >= self.min_samples_leaf
+                && right.n_samples() >= self.min_samples_leaf
+            {
+                let l = nodes.len();
+                let r = l + 1;
+                nodes.push(Node::Invalid);
+                nodes.push(Node::Invalid);
+                let threshold = match split.rule {
+                    SplitRule::Threshold(threshold) => threshold,
+                    SplitRule::Subset(_) => unreachable!(),
+                };
+                nodes[node] = Node::Split {
+                    theta: split.theta,
+                    threshold,
+                    temperature,
+                    left: l,
+                    right: r,
+                    missing_left: split.missing_left,
+                };
+
+                self.recursive_fit(nodes, left, l, depth + 1, rng);
+                self.recursive_fit(nodes, right, r, depth + 1, rng);
+
+                return
+            }
+        }
+
+        nodes[node] = Node::Leaf(data.train_leaf_predictor())
+    }
+
+    fn split_temperature<Training>(&self,
+                                   data: &Training,
+                                   split: &Split<Sample::ThetaSplit, Sample::Feature>)
+                                   -> f64
+        where Training: ?Sized + TrainingData<Sample>
+    {
+        match self.temperature {
+            Temperature::Fixed(t) => t,
+            Temperature::Relative(f) => {
+                let (min, max) = data.feature_bounds(&split.theta);
+                f * (max.as_float() - min.as_float())
+            }
+        }
+    }
+}
+
+/// An ensemble of probabilistic decision trees.
+pub struct ProbabilisticForest<Sample>
+    where Sample: SampleDescription
+{
+    estimators: Vec<ProbabilisticTree<Sample>>
+}
+
+impl<Sample> ProbabilisticForest<Sample>
+    where Sample: SampleDescription,
+          Sample::Feature: Continuous,
+{
+    pub fn predict<TestingSample>(&self, sample: &TestingSample) -> TestingSample::Prediction
+        where TestingSample: SampleDescription<ThetaSplit=Sample::ThetaSplit,
+            ThetaLeaf=Sample::ThetaLeaf,
+            Feature=Sample::Feature> + ?Sized,
+              TestingSample::Prediction: WeightedMean + IterMean,
+    {
+        let iter = self.estimators
+            .iter()
+            .map(|tree| tree.predict(sample));
+        TestingSample::Prediction::mean(iter)
+    }
+}
+
+/// Fit a `ProbabilisticForest` to `TrainingData`.
+pub struct ProbabilisticForestBuilder<SF, Sample>
+    where SF: SplitFinder,
+          Sample: SampleDescription,
+{
+    n_estimators: usize,
+    tree_builder: ProbabilisticTreeBuilder<SF, Sample>,
+    seed: Option<u64>,
+}
+
+impl<SF, Sample> ProbabilisticForestBuilder<SF, Sample>
+    where SF: SplitFinder,
+          Sample: SampleDescription,
+          Sample::Feature: Continuous,
+{
+    pub fn new(n_estimators: usize, tree_builder: ProbabilisticTreeBuilder<SF, Sample>) -> Self {
+        ProbabilisticForestBuilder {
+            n_estimators,
+            tree_builder,
+            seed: None,
+        }
+    }
+
+    /// Seed the random number generator, so that fitting the same data twice produces identical
+    /// forests. This overrides any seed set on the tree builder.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn fit<Training>(&self, data: &mut Training) -> ProbabilisticForest<Sample>
+        where Training: ?Sized + TrainingData<Sample>
+    {
+        match self.seed {
+            None => self.fit_with_rng(data, &mut thread_rng()),
+            Some(seed) => self.fit_with_rng(data, &mut ChaChaRng::seed_from_u64(seed)),
+        }
+    }
+
+    /// Fit a forest, drawing all randomness from `rng`. The builder's seed is ignored.
+    pub fn fit_with_rng<Training, R>(&self, data: &mut Training, rng: &mut R) -> ProbabilisticForest<Sample>
+        where Training: ?Sized + TrainingData<Sample>,
+              R: Rng
+    {
+        let estimators = (0..self.n_estimators)
+            .map(|_| self.tree_builder.fit_with_rng(data, rng))
+            .collect();
+
+        ProbabilisticForest {
+            estimators
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use split::{BestRandomSplit, BestSplit};
+    use testdata::Sample;
+
+    #[test]
+    fn gate() {
+        assert_eq!(super::gate(1.0, 1.0, 0.0), 1.0);
+        assert_eq!(super::gate(1.1, 1.0, 0.0), 0.0);
+        assert_eq!(super::gate(1.0, 1.0, 2.0), 0.5);
+        assert!(super::gate(0.0, 1.0, 0.5) > 0.5);
+        assert!(super::gate(2.0, 1.0, 0.5) < 0.5);
+        assert_eq!(super::gate(1e6, 0.0, 1.0), 0.0);
+    }
+
+    #[test]
+    fn tree() {
+        let data: &mut [_] = &mut [
+            Sample::new(&[0.0], 1.0),
+            Sample::new(&[1.0], 1.0),
+            Sample::new(&[2.0], 1.0),
+            Sample::new(&[3.0], 1.0),
+            Sample::new(&[4.0], 11.0),
+            Sample::new(&[5.0], 11.0),
+            Sample::new(&[6.0], 11.0),
+            Sample::new(&[7.0], 11.0),
+        ];
+
+        let hard = ProbabilisticTreeBuilder::new(2, BestSplit::new())
+            .with_temperature(Temperature::Fixed(0.0))
+            .fit(data);
+
+        for sample in data.iter() {
+            assert_eq!(hard.predict(sample), sample.y);
+        }
+
+        let soft = ProbabilisticTreeBuilder::new(2, BestSplit::new())
+            .with_temperature(Temperature::Relative(0.1))
+            .fit(data);
+
+        let p: Vec<f64> = [-100.0, 2.0, 3.5, 5.0, 100.0].iter()
+            .map(|x| soft.predict(&Sample::new(&[*x], 0.0)))
+            .collect();
+
+        assert!((p[0] - 1.0).abs() < 1e-9);
+        assert!((p[2] - 6.0).abs() < 1e-9);
+        assert!((p[4] - 11.0).abs() < 1e-9);
+        assert!(p.windows(2).all(|w| w[0] < w[1]));
+
+        let mut total = 0.0;
+        soft.visit_leaves(&Sample::new(&[3.0], 0.0), |p, _| total += p);
+        assert!((total - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn forest() {
+        let data: &mut [_] = &mut [
+            Sample::new(&[0.0], 1.0),
+            Sample::new(&[1.0], 2.0),
+            Sample::new(&[2.0], 1.0),
+            Sample::new(&[3.0], 2.0),
+            Sample::new(&[4.0], 11.0),
+            Sample::new(&[5.0], 12.0),
+            Sample::new(&[6.0], 11.0),
+            Sample::new(&[7.0], 12.0),
+        ];
+
+        let forest = ProbabilisticForestBuilder::new(
+            10,
+            ProbabilisticTreeBuilder::new(2, BestRandomSplit::new(1))
+                .with_temperature(Temperature::Fixed(0.01))
+        ).with_seed(0).fit(data);
+
+        assert!((forest.predict(&Sample::new(&[-5.0], 0.0)) - 1.0).abs() < 1e-9);
+        assert!((forest.predict(&Sample::new(&[15.0], 0.0)) - 12.0).abs() < 1e-9);
+
+        let p = forest.predict(&Sample::new(&[3.5], 0.0));
+        assert!(p > 2.0 && p < 11.0);
+    }
+
+    #[test]
+    fn missing_values() {
+        // samples with a missing feature belong to the left side
+        let data: &mut [_] = &mut [
+            Sample::new(&[0.0], 1.0),
+            Sample::new(&[1.0], 1.0),
+            Sample::new(&[f64::NAN], 1.0),
+            Sample::new(&[f64::NAN], 1.0),
+            Sample::new(&[2.0], 11.0),
+            Sample::new(&[3.0], 11.0),
+        ];
+
+        for &temperature in &[Temperature::Fixed(0.0), Temperature::Relative(0.1)] {
+            let tree = ProbabilisticTreeBuilder::new(2, BestSplit::new())
+                .with_temperature(temperature)
+                .fit(data);
+            assert_eq!(tree.predict(&Sample::new(&[f64::NAN], 0.0)), 1.0);
+        }
+    }
+}
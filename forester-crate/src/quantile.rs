@@ -0,0 +1,173 @@
+//! Quantile regression forests (Meinshausen, 2006).
+//!
+//! A quantile forest remembers the targets of the training samples that fall into each leaf. To
+//! predict, every training sample is weighted by how often it shares a leaf with the test sample
+//! (normalized by the leaf's size and the number of trees). These weights define a conditional
+//! distribution of the target, from which arbitrary quantiles can be read.
+
+use std::cmp::Ordering;
+use std::f64;
+
+use continuous::Continuous;
+use data::{SampleDescription, TrainingData};
+use dforest::DeterministicForest;
+
+/// Training targets and weights of the samples in a leaf
+type LeafValues = Vec<(f64, f64)>;
+
+/// A forest whose leaves keep the targets of their training samples.
+pub struct QuantileForest<Sample>
+    where Sample: SampleDescription
+{
+    forest: DeterministicForest<Sample>,
+    /// Training targets and weights in each node, one vector per tree
+    leaf_values: Vec<Vec<LeafValues>>,
+}
+
+impl<Sample> QuantileForest<Sample>
+    where Sample: SampleDescription,
+          Sample::Target: Continuous,
+{
+    /// Store the samples of `data` in the leaves of `forest`. `data` is usually the forest's
+    /// training data.
+    pub fn new<Training>(forest: DeterministicForest<Sample>, data: &Training) -> Self
+        where Training: ?Sized + TrainingData<Sample>
+    {
+        let mut leaf_values: Vec<Vec<LeafValues>> = forest.estimators.iter()
+            .map(|tree| vec![Vec::new(); tree.nodes.len()])
+            .collect();
+
+        data.visit_samples(|sample| {
+            let y = sample.target().as_float();
+            for (tree, leaves) in forest.estimators.iter().zip(leaf_values.iter_mut()) {
+                leaves[tree.leaf_index(sample)].push((y, sample.weight()));
+            }
+        });
+
+        QuantileForest {
+            forest,
+            leaf_values,
+        }
+    }
+
+    /// The underlying forest, which predicts conditional means
+    pub fn forest(&self) -> &DeterministicForest<Sample> {
+        &self.forest
+    }
+
+    /// Conditional distribution of the target given the features of `sample`
+    pub fn predict_distribution<TestingSample>(&self, sample: &TestingSample) -> ConditionalDistribution
+        where TestingSample: SampleDescription<ThetaSplit=Sample::ThetaSplit,
+            Feature=Sample::Feature> + ?Sized,
+    {
+        let n_trees = self.forest.estimators.len() as f64;
+        let mut weighted = Vec::new();
+        for (tree, leaves) in self.forest.estimators.iter().zip(&self.leaf_values) {
+            let leaf = &leaves[tree.leaf_index(sample)];
+            let total: f64 = leaf.iter().map(|&(_, w)| w).sum();
+            if total <= 0.0 {
+                continue
+            }
+            weighted.extend(leaf.iter().map(|&(y, w)| (y, w / (total * n_trees))));
+        }
+        ConditionalDistribution::new(weighted)
+    }
+
+    /// Predict the `q`-quantile (`0 <= q <= 1`) of the target given the features of `sample`
+    pub fn predict_quantile<TestingSample>(&self, sample: &TestingSample, q: f64) -> f64
+        where TestingSample: SampleDescription<ThetaSplit=Sample::ThetaSplit,
+            Feature=Sample::Feature> + ?Sized,
+    {
+        self.predict_distribution(sample).quantile(q)
+    }
+}
+
+/// Discrete distribution of a target, as predicted by a `QuantileForest`
+#[derive(Debug, Clone)]
+pub struct ConditionalDistribution {
+    values: Vec<f64>,
+    cumulative: Vec<f64>,
+}
+
+impl ConditionalDistribution {
+    fn new(mut weighted: Vec<(f64, f64)>) -> Self {
+        weighted.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+
+        let total: f64 = weighted.iter().map(|&(_, w)| w).sum();
+        let mut values: Vec<f64> = Vec::new();
+        let mut cumulative: Vec<f64> = Vec::new();
+        let mut acc = 0.0;
+        for (y, w) in weighted {
+            acc += w / total;
+            if values.last() == Some(&y) {
+                *cumulative.last_mut().unwrap() = acc;
+            } else {
+                values.push(y);
+                cumulative.push(acc);
+            }
+        }
+
+        ConditionalDistribution {
+            values,
+            cumulative,
+        }
+    }
+
+    /// Distinct target values with positive probability, in increasing order
+    pub fn values(&self) -> &[f64] {
+        &self.values
+    }
+
+    /// Probability that the target is less than or equal to `y`
+    pub fn cdf(&self, y: f64) -> f64 {
+        match self.values.iter().rposition(|&v| v <= y) {
+            Some(i) => self.cumulative[i].min(1.0),
+            None => 0.0,
+        }
+    }
+
+    /// Smallest value `y` with `cdf(y) >= q`, or NaN if the distribution is empty
+    pub fn quantile(&self, q: f64) -> f64 {
+        // tolerate rounding errors in the cumulative sums
+        let q = q - 1e-12;
+        match self.cumulative.iter().position(|&c| c >= q) {
+            Some(i) => self.values[i],
+            None => self.values.last().cloned().unwrap_or(f64::NAN),
+        }
+    }
+
+    /// Mean of the distribution
+    pub fn mean(&self) -> f64 {
+        let mut prev = 0.0;
+        let mut mean = 0.0;
+        for (&y, &c) in self.values.iter().zip(&self.cumulative) {
+            mean += y * (c - prev);
+            prev = c;
+        }
+        mean
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conditional_distribution() {
+        let dist = ConditionalDistribution::new(vec![(3.0, 0.25), (1.0, 0.25), (3.0, 0.25), (2.0, 0.25)]);
+        assert_eq!(dist.values(), &[1.0, 2.0, 3.0]);
+        assert_eq!(dist.cdf(0.0), 0.0);
+        assert_eq!(dist.cdf(1.0), 0.25);
+        assert_eq!(dist.cdf(2.5), 0.5);
+        assert_eq!(dist.cdf(10.0), 1.0);
+        assert_eq!(dist.quantile(0.0), 1.0);
+        assert_eq!(dist.quantile(0.25), 1.0);
+        assert_eq!(dist.quantile(0.3), 2.0);
+        assert_eq!(dist.quantile(0.5), 2.0);
+        assert_eq!(dist.quantile(1.0), 3.0);
+        assert_eq!(dist.mean(), 2.25);
+
+        assert!(ConditionalDistribution::new(vec![]).quantile(0.5).is_nan());
+    }
+}
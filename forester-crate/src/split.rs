@@ -1,31 +1,149 @@
 //! Module for working with splits.
 
+use std::cmp::Ordering;
 use std::f64;
 
-use rand::{thread_rng, Rng};
+use rand::Rng;
 
+use categorical::Category;
 use criterion::SplitCriterion;
-use data::{SampleDescription, TrainingData};
+use data::{is_missing, SampleDescription, TrainingData};
 use split_between::SplitBetween;
 
 /// Parametric representation of a split.
 ///
 /// A split consists of a data-set dependent parameter `theta` that corresponds to a feature, and
-/// a threshold to split the feature into two half-spaces.
+/// a `rule` that divides the values of the feature into two sides. Samples whose feature is
+/// missing (see `data::is_missing` and `categorical::Category::missing`) go to the left if
+/// `missing_left` is set, and to the right otherwise.
 #[derive(Debug)]
 pub struct Split<Theta, Threshold> {
     pub theta: Theta,
-    pub threshold: Threshold,
+    pub rule: SplitRule<Threshold>,
+    pub missing_left: bool,
+}
+
+/// How a split divides the values of a feature
+#[derive(Debug, Clone, PartialEq)]
+pub enum SplitRule<Threshold> {
+    /// Samples whose ordered feature is less than or equal to the threshold go to the left
+    Threshold(Threshold),
+
+    /// Samples of a categorical feature (see `SampleDescription::sample_as_category`) whose
+    /// category is in the sorted list of ids go to the left
+    Subset(Vec<usize>),
+}
+
+impl<Theta, Threshold: PartialOrd> Split<Theta, Threshold> {
+    /// Split ordered feature `theta` at `threshold`; missing values go to the right.
+    pub fn at_threshold(theta: Theta, threshold: Threshold) -> Self {
+        Split {
+            theta,
+            rule: SplitRule::Threshold(threshold),
+            missing_left: false,
+        }
+    }
+
+    /// Split categorical feature `theta` into the sorted `categories` and the rest; missing
+    /// values go to the right.
+    pub fn into_subset(theta: Theta, categories: Vec<usize>) -> Self {
+        Split {
+            theta,
+            rule: SplitRule::Subset(categories),
+            missing_left: false,
+        }
+    }
+
+    /// Threshold of a split of an ordered feature
+    pub fn threshold(&self) -> Option<&Threshold> {
+        match self.rule {
+            SplitRule::Threshold(ref threshold) => Some(threshold),
+            SplitRule::Subset(_) => None,
+        }
+    }
+
+    /// Categories that go to the left in a split of a categorical feature
+    pub fn categories(&self) -> Option<&[usize]> {
+        match self.rule {
+            SplitRule::Threshold(_) => None,
+            SplitRule::Subset(ref categories) => Some(categories),
+        }
+    }
+
+    /// Return whether a sample with feature value `x` is passed to the left side.
+    ///
+    /// Subset splits send samples left by category rather than by feature value, so they return
+    /// `None`; use `sample_goes_left` for those.
+    pub fn goes_left(&self, x: &Threshold) -> Option<bool> {
+        match self.rule {
+            SplitRule::Threshold(ref threshold) => Some(goes_left(x, threshold, self.missing_left)),
+            SplitRule::Subset(_) => None,
+        }
+    }
+
+    /// Return true if `sample` is passed to the left side.
+    pub fn sample_goes_left<S>(&self, sample: &S) -> bool
+        where S: SampleDescription<ThetaSplit=Theta, Feature=Threshold> + ?Sized
+    {
+        self.side(sample).unwrap_or(self.missing_left)
+    }
+
+    /// Return `Some(true)` if `sample` goes to the left side, `Some(false)` if it goes to the
+    /// right side, and `None` if its feature is missing.
+    fn side<S>(&self, sample: &S) -> Option<bool>
+        where S: SampleDescription<ThetaSplit=Theta, Feature=Threshold> + ?Sized
+    {
+        match self.rule {
+            SplitRule::Threshold(ref threshold) => {
+                let x = sample.sample_as_split_feature(&self.theta);
+                if is_missing(&x) {
+                    None
+                } else {
+                    Some(x <= *threshold)
+                }
+            }
+            SplitRule::Subset(ref categories) => {
+                category_id(sample.sample_as_category(&self.theta))
+                    .map(|c| categories.binary_search(&c).is_ok())
+            }
+        }
+    }
+}
+
+/// Return true if a feature value `x` is passed to the left side of a split.
+pub(crate) fn goes_left<F: PartialOrd>(x: &F, threshold: &F, missing_left: bool) -> bool {
+    if is_missing(x) {
+        missing_left
+    } else {
+        x <= threshold
+    }
+}
+
+/// Return true if `category` is passed to the left side of a split into the sorted list of
+/// `categories`.
+pub(crate) fn in_subset(category: Option<Category>, categories: &[usize], missing_left: bool) -> bool {
+    match category_id(category) {
+        Some(c) => categories.binary_search(&c).is_ok(),
+        None => missing_left,
+    }
+}
+
+/// Id of a category, or `None` if it is missing
+fn category_id(category: Option<Category>) -> Option<usize> {
+    category.and_then(|c| c.id())
 }
 
 /// Find split
 pub trait SplitFinder
 {
     /// Attempt to find a split for the given data set.
-    fn find_split<Sample, Training>(&self, data: &mut Training)
+    ///
+    /// All randomness involved in finding the split is drawn from `rng`.
+    fn find_split<Sample, Training, R>(&self, data: &mut Training, rng: &mut R)
         -> Option<Split<Sample::ThetaSplit, Sample::Feature>>
         where Sample: SampleDescription,
-              Training: ?Sized + TrainingData<Sample>;
+              Training: ?Sized + TrainingData<Sample>,
+              R: Rng;
 }
 
 /// Find best random split.
@@ -48,35 +166,28 @@ impl BestRandomSplit {
 
 impl SplitFinder for BestRandomSplit
 {
-    fn find_split<Sample, Training>(&self, data: &mut Training)
-                                    -> Option<Split<Sample::ThetaSplit, Sample::Feature>>
+    fn find_split<Sample, Training, R>(&self, data: &mut Training, rng: &mut R)
+                                       -> Option<Split<Sample::ThetaSplit, Sample::Feature>>
         where Sample: SampleDescription,
-              Training: ?Sized + TrainingData<Sample>
+              Training: ?Sized + TrainingData<Sample>,
+              R: Rng
     {
-        let n = data.n_samples() as f64;
+        let n = data.total_weight();
         //let mut best_criterion = data.split_criterion();
         let mut best_criterion = Training::Criterion::from_dataset(data).get();
         let mut best_split = None;
 
-        let mut rng = thread_rng();
-
         for _ in 0..self.n_splits {
-            let theta = data.gen_split_feature();
-
-            let (min, max) = data.feature_bounds(&theta);
+            let theta = data.gen_split_feature(rng);
 
-            if max <= min {
-                continue
-            }
-
-            let threshold = rng.gen_range(min, max);
+            let mut split = match random_split(data, theta, rng) {
+                Some(split) => split,
+                None => continue,
+            };
 
-            let split = Split{theta, threshold};
-            let (left, right) = data.partition_data(&split);
-
-            let left_crit = Training::Criterion::from_dataset(left).get_weighted();
-            let right_crit = Training::Criterion::from_dataset(right).get_weighted();
-            let criterion = (left_crit + right_crit) / n;
+            let (criterion, missing_left) = evaluate_split(data, &split);
+            split.missing_left = missing_left;
+            let criterion = criterion / n;
 
             if criterion <= best_criterion {
                 best_criterion = criterion;
@@ -94,6 +205,61 @@ impl SplitFinder for BestRandomSplit
     }
 }
 
+/// Find a purely random split.
+///
+/// Draws a random feature and a random threshold between the feature's bounds, ignoring the
+/// split criterion. If the feature is constant in the data, another one is drawn, up to a total
+/// of `n_tries` times.
+pub struct RandomSplit {
+    n_tries: usize,
+}
+
+impl RandomSplit {
+    pub fn new(n_tries: usize) -> Self {
+        RandomSplit {
+            n_tries
+        }
+    }
+}
+
+impl SplitFinder for RandomSplit
+{
+    fn find_split<Sample, Training, R>(&self, data: &mut Training, rng: &mut R)
+                                       -> Option<Split<Sample::ThetaSplit, Sample::Feature>>
+        where Sample: SampleDescription,
+              Training: ?Sized + TrainingData<Sample>,
+              R: Rng
+    {
+        for _ in 0..self.n_tries {
+            let theta = data.gen_split_feature(rng);
+            if let Some(split) = random_split(data, theta, rng) {
+                return Some(split)
+            }
+        }
+        None
+    }
+}
+
+/// Draw a random split of feature `theta`, or return `None` if the feature is constant.
+fn random_split<Sample, Training, R>(data: &Training, theta: Sample::ThetaSplit, rng: &mut R)
+    -> Option<Split<Sample::ThetaSplit, Sample::Feature>>
+    where Sample: SampleDescription,
+          Training: ?Sized + TrainingData<Sample>,
+          R: Rng
+{
+    if is_categorical(data, &theta) {
+        return random_subset(data, &theta, rng).map(|categories| Split::into_subset(theta, categories))
+    }
+
+    let (min, max) = data.feature_bounds(&theta);
+    if min.partial_cmp(&max) != Some(Ordering::Less) {
+        return None
+    }
+
+    let threshold = rng.gen_range(min, max);
+    Some(Split::at_threshold(theta, threshold))
+}
+
 /// Find best split, in a number of randomly selected features.
 ///
 /// Normally, exactly `n_features` are tested. However, there are two notable exceptions:
@@ -114,10 +280,11 @@ impl BestSplitRandomFeature {
 
 impl SplitFinder for BestSplitRandomFeature
 {
-    fn find_split<Sample, Training>(&self, data: &mut Training)
-                                    -> Option<Split<Sample::ThetaSplit, Sample::Feature>>
+    fn find_split<Sample, Training, R>(&self, data: &mut Training, rng: &mut R)
+                                       -> Option<Split<Sample::ThetaSplit, Sample::Feature>>
         where Sample: SampleDescription,
-              Training: ?Sized + TrainingData<Sample>
+              Training: ?Sized + TrainingData<Sample>,
+              R: Rng
     {
         let mut best_criterion = Training::Criterion::from_dataset(data).get();
         let mut best_split = None;
@@ -127,7 +294,7 @@ impl SplitFinder for BestSplitRandomFeature
             .expect("Dataset does not support iteration over features.")
             .collect();
 
-        thread_rng().shuffle(&mut features);
+        rng.shuffle(&mut features);
 
         let mut n_to_check = self.n_features;
 
@@ -173,10 +340,11 @@ impl BestSplit {
 
 impl SplitFinder for BestSplit
 {
-    fn find_split<Sample, Training>(&self, data: &mut Training)
-                                    -> Option<Split<Sample::ThetaSplit, Sample::Feature>>
+    fn find_split<Sample, Training, R>(&self, data: &mut Training, _rng: &mut R)
+                                       -> Option<Split<Sample::ThetaSplit, Sample::Feature>>
         where Sample: SampleDescription,
-              Training: ?Sized + TrainingData<Sample>
+              Training: ?Sized + TrainingData<Sample>,
+              R: Rng
     {
         let mut best_criterion = Training::Criterion::from_dataset(data).get();
         let mut best_split = None;
@@ -200,47 +368,144 @@ impl SplitFinder for BestSplit
     }
 }
 
+/// Weighted criterion of a split, and whether missing samples should go to the left.
+fn evaluate_split<Sample, Training>(data: &Training, split: &Split<Sample::ThetaSplit, Sample::Feature>) -> (f64, bool)
+    where Sample: SampleDescription,
+          Training: ?Sized + TrainingData<Sample>
+{
+    let mut left = Training::Criterion::new();
+    let mut right = Training::Criterion::new();
+    let mut any_missing = false;
+
+    data.visit_samples(|sample| {
+        match split.side(sample) {
+            Some(true) => left.add_sample(sample),
+            Some(false) => right.add_sample(sample),
+            None => any_missing = true,
+        }
+    });
+
+    if !any_missing {
+        return combine_criteria::<Sample, _>(&left, &right, &None)
+    }
+
+    let mut with_missing = (Training::Criterion::new(), Training::Criterion::new());
+    data.visit_samples(|sample| {
+        let side = split.side(sample);
+        if side != Some(false) {
+            with_missing.0.add_sample(sample);
+        }
+        if side != Some(true) {
+            with_missing.1.add_sample(sample);
+        }
+    });
+
+    combine_criteria::<Sample, _>(&left, &right, &Some(with_missing))
+}
+
+/// Score of a split (see `SplitCriterion::split_score`).
+///
+/// If there are samples with missing features, `with_missing` contains the left and right
+/// criteria including these samples. Both assignments are tried, and the better one is returned
+/// together with `true` if the missing samples should go to the left.
+fn combine_criteria<Sample, C>(left: &C, right: &C, with_missing: &Option<(C, C)>) -> (f64, bool)
+    where Sample: SampleDescription,
+          C: SplitCriterion<Sample::Target>
+{
+    match *with_missing {
+        None => (C::split_score(left, right), false),
+        Some((ref left_missing, ref right_missing)) => {
+            let missing_left = C::split_score(left_missing, right);
+            let missing_right = C::split_score(left, right_missing);
+            if missing_left < missing_right {
+                (missing_left, true)
+            } else {
+                (missing_right, false)
+            }
+        }
+    }
+}
+
 /// find optimal split for given feature
 fn find_best_split_for_feature<Sample, Training>(data: &mut Training, theta: &Sample::ThetaSplit)
                                                  -> (f64, Option<Split<Sample::ThetaSplit, Sample::Feature>>)
     where Sample: SampleDescription,
           Training: ?Sized + TrainingData<Sample>
 {
+    if is_categorical(data, theta) {
+        return find_best_subset_split(data, theta)
+    }
+
+    // samples with missing features are sorted to the end
     data.sort_data(theta);
 
+    let n = data.total_weight();
+
     let mut best_criterion = f64::INFINITY;
     let mut best_split = None;
 
-    let mut left_crit = Training::Criterion::from_dataset(data);
-    let mut right_crit = Training::Criterion::new();
+    let mut any_missing = false;
+    data.visit_samples(|sample| any_missing |= is_missing(&sample.sample_as_split_feature(theta)));
+
+    // criteria of the samples below and above the current threshold, and of the same samples
+    // plus all missing ones
+    let mut lower = Training::Criterion::new();
+    let mut upper = Training::Criterion::new();
+    let mut with_missing = if any_missing {
+        Some((Training::Criterion::new(), Training::Criterion::new()))
+    } else {
+        None
+    };
+
+    data.visit_samples(|sample| {
+        let missing = is_missing(&sample.sample_as_split_feature(theta));
+        if !missing {
+            upper.add_sample(sample);
+        }
+        if let Some((ref mut lower_missing, ref mut upper_missing)) = with_missing {
+            upper_missing.add_sample(sample);
+            if missing {
+                lower_missing.add_sample(sample);
+            }
+        }
+    });
 
     let mut prev_sf: Option<Sample::Feature> = None;
     data.visit_samples(|sample| {
         let sf = sample.sample_as_split_feature(theta);
 
-        // don't try to split between two samples of same feature value
-        if let Some(ref psf) = prev_sf {
-            if psf == &sf {
-                left_crit.remove_sample(sample);
-                right_crit.add_sample(sample);
-                return
-            }
+        if is_missing(&sf) {
+            return
         }
 
-        let criterion = (left_crit.get_weighted() + right_crit.get_weighted()) / data.n_samples() as f64;
+        // don't try to split between two samples of same feature value
+        let same_value = match prev_sf {
+            Some(ref psf) => psf == &sf,
+            None => true,
+        };
+
+        if !same_value {
+            let (criterion, missing_left) = combine_criteria::<Sample, _>(&lower, &upper, &with_missing);
+            let criterion = criterion / n;
 
-        if criterion <= best_criterion {
-            if let Some(ref psf) = prev_sf {
-                best_criterion = criterion;
-                best_split = Some(Split {
-                    theta: theta.clone(),
-                    threshold: psf.split_between(&sf)
-                });
+            if criterion <= best_criterion {
+                if let Some(ref psf) = prev_sf {
+                    best_criterion = criterion;
+                    best_split = Some(Split {
+                        theta: theta.clone(),
+                        rule: SplitRule::Threshold(psf.split_between(&sf)),
+                        missing_left,
+                    });
+                }
             }
         }
 
-        left_crit.remove_sample(sample);
-        right_crit.add_sample(sample);
+        upper.remove_sample(sample);
+        lower.add_sample(sample);
+        if let Some((ref mut lower_missing, ref mut upper_missing)) = with_missing {
+            upper_missing.remove_sample(sample);
+            lower_missing.add_sample(sample);
+        }
 
         prev_sf = Some(sf);
     });
@@ -249,9 +514,190 @@ fn find_best_split_for_feature<Sample, Training>(data: &mut Training, theta: &Sa
 }
 
 
+/// Criterion of the best split found, and the split
+type ScoredSplit<Sample> = (f64, Option<Split<<Sample as SampleDescription>::ThetaSplit,
+                                             <Sample as SampleDescription>::Feature>>);
+
+/// Return true if the feature `theta` is categorical (judging from the first sample).
+fn is_categorical<Sample, Training>(data: &Training, theta: &Sample::ThetaSplit) -> bool
+    where Sample: SampleDescription,
+          Training: ?Sized + TrainingData<Sample>
+{
+    match data.first_sample() {
+        Some(sample) => sample.sample_as_category(theta).is_some(),
+        None => false,
+    }
+}
+
+/// Category id of each sample in the order they are visited (`None` if missing), and the sorted
+/// distinct categories that are present
+fn sample_categories<Sample, Training>(data: &Training, theta: &Sample::ThetaSplit) -> (Vec<Option<usize>>, Vec<usize>)
+    where Sample: SampleDescription,
+          Training: ?Sized + TrainingData<Sample>
+{
+    let mut categories = Vec::with_capacity(data.n_samples());
+    data.visit_samples(|sample| categories.push(category_id(sample.sample_as_category(theta))));
+
+    let mut present: Vec<usize> = categories.iter().filter_map(|&c| c).collect();
+    present.sort_unstable();
+    present.dedup();
+
+    (categories, present)
+}
+
+/// Draw a random non-empty proper subset of the categories present in the data.
+fn random_subset<Sample, Training, R>(data: &Training, theta: &Sample::ThetaSplit, rng: &mut R) -> Option<Vec<usize>>
+    where Sample: SampleDescription,
+          Training: ?Sized + TrainingData<Sample>,
+          R: Rng
+{
+    let (_, mut present) = sample_categories(data, theta);
+    if present.len() < 2 {
+        return None
+    }
+
+    rng.shuffle(&mut present);
+    let k = rng.gen_range(1, present.len());
+    let mut subset = present[..k].to_vec();
+    subset.sort_unstable();
+    Some(subset)
+}
+
+/// Reorder `data` so that the samples of each category in the sorted list `categories` are
+/// contiguous, and append the samples of each category, in order, to `buckets`.
+///
+/// Samples of other categories and with a missing category are moved behind the buckets.
+fn bucket_categories<'a, Sample, Training>(data: &'a mut Training,
+                                           theta: &Sample::ThetaSplit,
+                                           categories: &[usize],
+                                           buckets: &mut Vec<&'a Training>)
+    where Sample: SampleDescription,
+          Training: ?Sized + TrainingData<Sample>
+{
+    match categories.len() {
+        0 => {}
+        1 => {
+            let (bucket, _) = data.partition_data(&Split::into_subset(theta.clone(), categories.to_vec()));
+            buckets.push(bucket);
+        }
+        k => {
+            let (first, second) = categories.split_at(k / 2);
+            let (left, right) = data.partition_data(&Split::into_subset(theta.clone(), first.to_vec()));
+            bucket_categories(left, theta, first, buckets);
+            bucket_categories(right, theta, second, buckets);
+        }
+    }
+}
+
+/// Move all samples of `bucket` from criterion `from` to criterion `to`.
+fn move_bucket<Sample, Training>(bucket: &Training, from: &mut Training::Criterion, to: &mut Training::Criterion)
+    where Sample: SampleDescription,
+          Training: ?Sized + TrainingData<Sample>
+{
+    bucket.visit_samples(|sample| {
+        from.remove_sample(sample);
+        to.add_sample(sample);
+    });
+}
+
+/// Find the best subset split for a categorical feature.
+///
+/// If the criterion provides a `category_order_key`, the categories are sorted by that key and
+/// only splits of the sorted sequence are considered. This is exact for the variance and deviance
+/// criteria of regression and for binary classification. Otherwise (e.g. for absolute or Huber
+/// errors, whose best subsets need not be contiguous in any order), categories are greedily moved
+/// to the left one at a time, always picking the one that results in the best split.
+///
+/// The samples are grouped by category once, so moving a category only visits its own samples.
+/// The subset is chosen among the samples whose category is known. Samples with a missing
+/// category are then assigned to the side that gives the better split.
+fn find_best_subset_split<Sample, Training>(data: &mut Training, theta: &Sample::ThetaSplit) -> ScoredSplit<Sample>
+    where Sample: SampleDescription,
+          Training: ?Sized + TrainingData<Sample>
+{
+    let (categories, present) = sample_categories(data, theta);
+    if present.len() < 2 {
+        return (f64::INFINITY, None)
+    }
+
+    let n = data.total_weight();
+    let any_missing = categories.iter().any(Option::is_none);
+
+    let mut best_criterion = f64::INFINITY;
+    let mut best_subset = Vec::new();
+    {
+        let mut buckets = Vec::with_capacity(present.len());
+        bucket_categories(data, theta, &present, &mut buckets);
+
+        let mut left = Training::Criterion::new();
+        let mut right = Training::Criterion::new();
+        for bucket in &buckets {
+            bucket.visit_samples(|sample| right.add_sample(sample));
+        }
+
+        if right.category_order_key().is_some() {
+            let mut order: Vec<(f64, usize)> = buckets.iter()
+                .map(|&bucket| Training::Criterion::from_dataset(bucket).category_order_key().unwrap_or(0.0))
+                .zip(0..)
+                .collect();
+            order.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+
+            for k in 1..order.len() {
+                move_bucket(buckets[order[k - 1].1], &mut right, &mut left);
+                let criterion = Training::Criterion::split_score(&left, &right) / n;
+                if criterion <= best_criterion {
+                    best_criterion = criterion;
+                    best_subset = order[..k].iter().map(|&(_, j)| present[j]).collect();
+                }
+            }
+        } else {
+            let mut remaining: Vec<usize> = (0..buckets.len()).collect();
+            let mut subset = Vec::new();
+
+            while remaining.len() > 1 {
+                let mut best_move = (f64::INFINITY, 0);
+                for (i, &j) in remaining.iter().enumerate() {
+                    move_bucket(buckets[j], &mut right, &mut left);
+                    let criterion = Training::Criterion::split_score(&left, &right) / n;
+                    move_bucket(buckets[j], &mut left, &mut right);
+                    if criterion < best_move.0 {
+                        best_move = (criterion, i);
+                    }
+                }
+
+                let j = remaining.remove(best_move.1);
+                move_bucket(buckets[j], &mut right, &mut left);
+                subset.push(present[j]);
+
+                if best_move.0 <= best_criterion {
+                    best_criterion = best_move.0;
+                    best_subset = subset.clone();
+                }
+            }
+        }
+    }
+
+    if best_subset.is_empty() {
+        return (f64::INFINITY, None)
+    }
+
+    best_subset.sort_unstable();
+
+    let mut split = Split::into_subset(theta.clone(), best_subset);
+    if any_missing {
+        let (criterion, missing_left) = evaluate_split(data, &split);
+        split.missing_left = missing_left;
+        best_criterion = criterion / n;
+    }
+
+    (best_criterion, Some(split))
+}
+
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::thread_rng;
     use split::BestRandomSplit;
     use testdata::Sample;
 
@@ -262,10 +708,10 @@ mod tests {
             Sample::new(&[1.0], 2.0),
         ];
         let spl = BestRandomSplit::new(1);
-        let split = spl.find_split(data).unwrap();
+        let split = spl.find_split(data, &mut thread_rng()).unwrap();
         assert_eq!(split.theta, 0);
-        assert!(split.threshold >= 0.0);
-        assert!(split.threshold <= 1.0);
+        assert!(*split.threshold().unwrap() >= 0.0);
+        assert!(*split.threshold().unwrap() <= 1.0);
 
         let data: &mut [_] = &mut [
             Sample::new(&[41.0, 0.0], 1.0),
@@ -278,10 +724,10 @@ mod tests {
             Sample::new(&[43.0, 7.0], 12.0),
         ];
         let spl = BestRandomSplit::new(100);
-        let split = spl.find_split(data).unwrap();
+        let split = spl.find_split(data, &mut thread_rng()).unwrap();
         assert_eq!(split.theta, 1);
-        assert!(split.threshold >= 3.0);
-        assert!(split.threshold <= 4.0);
+        assert!(*split.threshold().unwrap() >= 3.0);
+        assert!(*split.threshold().unwrap() <= 4.0);
     }
 
     #[test]
@@ -299,10 +745,10 @@ mod tests {
             Sample::new(&[0.0, 42.0, 9.0], 12.0),
         ];
         let spl = BestSplit::new();
-        let split = spl.find_split(data).unwrap();
+        let split = spl.find_split(data, &mut thread_rng()).unwrap();
         assert_eq!(split.theta, 2);
-        assert!(split.threshold >= 3.0);
-        assert!(split.threshold <= 4.0);
+        assert!(*split.threshold().unwrap() >= 3.0);
+        assert!(*split.threshold().unwrap() <= 4.0);
     }
 
     #[test]
@@ -320,9 +766,108 @@ mod tests {
             Sample::new(&[0.0, 42.0, 9.0], 12.0),
         ];
         let spl = BestSplitRandomFeature::new(3);
-        let split = spl.find_split(data).unwrap();
+        let split = spl.find_split(data, &mut thread_rng()).unwrap();
         assert_eq!(split.theta, 2);
-        assert!(split.threshold >= 3.0);
-        assert!(split.threshold <= 4.0);
+        assert!(*split.threshold().unwrap() >= 3.0);
+        assert!(*split.threshold().unwrap() <= 4.0);
+    }
+
+    #[test]
+    fn missing_values() {
+        let data: &mut [_] = &mut [
+            Sample::new(&[f64::NAN], 1.0),
+            Sample::new(&[0.0], 1.0),
+            Sample::new(&[1.0], 2.0),
+            Sample::new(&[f64::NAN], 2.0),
+            Sample::new(&[2.0], 11.0),
+            Sample::new(&[3.0], 12.0),
+        ];
+
+        let split = BestSplit::new().find_split(data, &mut thread_rng()).unwrap();
+        assert!(*split.threshold().unwrap() >= 1.0);
+        assert!(*split.threshold().unwrap() <= 2.0);
+        assert!(split.missing_left);
+
+        let split = BestRandomSplit::new(100).find_split(data, &mut thread_rng()).unwrap();
+        assert!(*split.threshold().unwrap() >= 1.0);
+        assert!(*split.threshold().unwrap() <= 2.0);
+        assert!(split.missing_left);
+
+        // now the missing samples belong to the right side
+        for sample in data.iter_mut() {
+            if sample.x[0].is_nan() {
+                sample.y = 11.5;
+            }
+        }
+
+        let split = BestSplit::new().find_split(data, &mut thread_rng()).unwrap();
+        assert!(!split.missing_left);
+        assert_eq!(split.goes_left(&0.0), Some(true));
+        assert_eq!(split.goes_left(&f64::NAN), Some(false));
+    }
+
+    #[test]
+    fn subset_split() {
+        use dtree::DeterministicTreeBuilder;
+        use testdata::ColorSample;
+
+        // the ordered feature is constant, so only the colors can be split
+        let data: &mut [_] = &mut (0..50)
+            .map(|i| i % 5)
+            .map(|c| ColorSample::new(c, 0.0, if c % 2 == 0 { 1.0 } else { 10.0 } + c as f64 / 10.0))
+            .collect::<Vec<_>>();
+
+        let split = BestSplit::new().find_split(data, &mut thread_rng()).unwrap();
+        assert_eq!(split.theta, 0);
+        let mut categories = split.categories().unwrap().to_vec();
+        categories.sort();
+        assert!(categories == vec![0, 2, 4] || categories == vec![1, 3]);
+        assert_eq!(split.goes_left(&0.0), None);
+        for sample in data.iter() {
+            assert_eq!(split.sample_goes_left(sample), categories.contains(&sample.color.unwrap()));
+        }
+
+        let split = BestRandomSplit::new(100).find_split(data, &mut thread_rng()).unwrap();
+        assert_eq!(split.theta, 0);
+        assert!(split.categories().is_some());
+
+        // multiple classes are split greedily
+        let data: &mut [_] = &mut (0..60)
+            .map(|i| ColorSample::new(i % 6, 0.0, [0u8, 1, 2, 0, 2, 1][i % 6]))
+            .collect::<Vec<_>>();
+
+        let split = BestSplit::new().find_split(data, &mut thread_rng()).unwrap();
+        assert_eq!(split.theta, 0);
+        assert!(split.categories().is_some());
+
+        let tree = DeterministicTreeBuilder::new(2, BestSplit::new()).fit(data);
+        for sample in data.iter() {
+            assert_eq!(tree.predict(sample), sample.y);
+        }
+    }
+
+    #[test]
+    fn missing_category() {
+        use dtree::DeterministicTreeBuilder;
+        use testdata::ColorSample;
+
+        // samples without a color have the same targets as colors 1 and 3
+        let data: &mut [_] = &mut (0..60)
+            .map(|i| match i % 6 {
+                5 => ColorSample::without_color(0.0, 10.0),
+                c => ColorSample::new(c, 0.0, if c % 2 == 0 { 1.0 } else { 10.0 }),
+            })
+            .collect::<Vec<_>>();
+
+        let split = BestSplit::new().find_split(data, &mut thread_rng()).unwrap();
+        assert_eq!(split.theta, 0);
+        let goes_left = split.sample_goes_left(&ColorSample::new(1, 0.0, 0.0));
+        assert_eq!(split.sample_goes_left(&ColorSample::without_color(0.0, 0.0)), goes_left);
+
+        let split = BestRandomSplit::new(100).find_split(data, &mut thread_rng()).unwrap();
+        assert!(split.categories().is_some());
+
+        let tree = DeterministicTreeBuilder::new(2, BestSplit::new()).fit(data);
+        assert_eq!(tree.predict(&ColorSample::without_color(0.0, 0.0)), 10.0);
     }
 }
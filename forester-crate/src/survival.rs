@@ -0,0 +1,451 @@
+//! Random survival forests for right-censored time-to-event data (Ishwaran et al., 2008).
+//!
+//! Samples use a `Survival` target (an observed time and whether the event happened at that time
+//! or the observation was censored) and split with the `LogRankCriterion`. Leaves estimate the
+//! survival function (Kaplan-Meier) and cumulative hazard (Nelson-Aalen) as a `SurvivalCurve`,
+//! and forests average the curves of the leaves a sample falls into.
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::f64;
+
+use criterion::SplitCriterion;
+use data::{SampleDescription, TrainingData};
+use iter_mean::IterMean;
+
+/// Time-to-event target
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Survival {
+    /// Time of the event or of censoring
+    pub time: f64,
+    /// `true` if the event was observed, `false` if the observation was censored
+    pub event: bool,
+}
+
+impl Survival {
+    pub fn event(time: f64) -> Self {
+        Survival { time, event: true }
+    }
+
+    pub fn censored(time: f64) -> Self {
+        Survival { time, event: false }
+    }
+}
+
+/// Weighted number of events and of all observations at each distinct time, keyed by `time_key`.
+type TimeCounts = BTreeMap<u64, (f64, f64)>;
+
+/// Map a time to a key that sorts like the times themselves, including negative times.
+fn time_key(time: f64) -> u64 {
+    // adding zero turns -0.0 into 0.0
+    let bits = (time + 0.0).to_bits();
+    if bits >> 63 == 0 {
+        // non-negative times sort after all negative ones
+        bits | 1 << 63
+    } else {
+        // negative times sort in reverse order of their magnitude
+        !bits
+    }
+}
+
+/// Inverse of `time_key`
+fn key_time(key: u64) -> f64 {
+    if key >> 63 == 1 {
+        f64::from_bits(key & !(1 << 63))
+    } else {
+        f64::from_bits(!key)
+    }
+}
+
+fn add_observation(counts: &mut TimeCounts, target: &Survival, w: f64) {
+    let key = time_key(target.time);
+    let remove = {
+        let entry = counts.entry(key).or_insert((0.0, 0.0));
+        if target.event {
+            entry.0 += w;
+        }
+        entry.1 += w;
+        entry.1 <= 0.0
+    };
+    if remove {
+        counts.remove(&key);
+    }
+}
+
+/// Log-rank criterion for evaluating splits of survival targets
+///
+/// A split is scored by the log-rank test statistic comparing the survival of both sides; larger
+/// statistics give smaller (better) scores, which become negative once the statistic exceeds the
+/// node's total weight. The impurity of a node is its total weight if it contains any events and
+/// observations (events or censorings) at more than one distinct time, and zero otherwise, so the
+/// impurity decrease of a split equals its log-rank statistic. Samples are counted according to their `weight()`.
+///
+/// Scoring a split takes time linear in the number of distinct times, which makes exhaustive
+/// split searches expensive; `BestRandomSplit` is usually a better fit.
+pub struct LogRankCriterion {
+    counts: TimeCounts,
+    w: f64,
+    events: f64,
+}
+
+impl LogRankCriterion {
+    /// Log-rank chi-square statistic of the samples in `left` versus those in `right`
+    pub fn statistic(left: &Self, right: &Self) -> f64 {
+        // visit the distinct times in decreasing order to accumulate the risk sets
+        let mut l = left.counts.iter().rev().peekable();
+        let mut r = right.counts.iter().rev().peekable();
+
+        let mut at_risk_left = 0.0;
+        let mut at_risk_right = 0.0;
+        let mut u = 0.0;
+        let mut v = 0.0;
+        loop {
+            let key = match (l.peek(), r.peek()) {
+                (None, None) => break,
+                (Some(&(&a, _)), None) => a,
+                (None, Some(&(&b, _))) => b,
+                (Some(&(&a, _)), Some(&(&b, _))) => a.max(b),
+            };
+
+            let mut d_left = 0.0;
+            let mut d_right = 0.0;
+            if l.peek().map(|&(&k, _)| k) == Some(key) {
+                let &(d, n) = l.next().unwrap().1;
+                d_left = d;
+                at_risk_left += n;
+            }
+            if r.peek().map(|&(&k, _)| k) == Some(key) {
+                let &(d, n) = r.next().unwrap().1;
+                d_right = d;
+                at_risk_right += n;
+            }
+
+            let d = d_left + d_right;
+            let y = at_risk_left + at_risk_right;
+            if d <= 0.0 || y <= 0.0 {
+                continue
+            }
+            u += d_left - at_risk_left * d / y;
+            if y > 1.0 {
+                v += at_risk_left * at_risk_right * d * (y - d) / (y * y * (y - 1.0));
+            }
+        }
+
+        if v <= 0.0 {
+            0.0
+        } else {
+            u * u / v
+        }
+    }
+}
+
+impl SplitCriterion<Survival> for LogRankCriterion {
+    fn new() -> Self {
+        LogRankCriterion {
+            counts: TimeCounts::new(),
+            w: 0.0,
+            events: 0.0,
+        }
+    }
+
+    fn add_sample<S: SampleDescription<Target=Survival>>(&mut self, sample: &S) {
+        let target = sample.target();
+        let w = sample.weight();
+        add_observation(&mut self.counts, &target, w);
+        self.w += w;
+        if target.event {
+            self.events += w;
+        }
+    }
+
+    fn remove_sample<S: SampleDescription<Target=Survival>>(&mut self, sample: &S) {
+        let target = sample.target();
+        let w = sample.weight();
+        add_observation(&mut self.counts, &target, -w);
+        self.w -= w;
+        if target.event {
+            self.events -= w;
+        }
+        if self.counts.is_empty() {
+            *self = Self::new();
+        }
+    }
+
+    fn get(&self) -> f64 {
+        if self.events > 0.0 && self.counts.len() > 1 { 1.0 } else { 0.0 }
+    }
+
+    fn get_weighted(&self) -> f64 {
+        self.get() * self.w
+    }
+
+    fn split_score(left: &Self, right: &Self) -> f64 {
+        left.w + right.w - Self::statistic(left, right)
+    }
+}
+
+/// Survival function and cumulative hazard, as step functions of time
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SurvivalCurve {
+    times: Vec<f64>,
+    survival: Vec<f64>,
+    cumulative_hazard: Vec<f64>,
+}
+
+impl SurvivalCurve {
+    /// Kaplan-Meier and Nelson-Aalen estimates from the (weighted) samples of `data`
+    pub fn from_dataset<S, D>(data: &D) -> Self
+        where S: SampleDescription<Target=Survival>,
+              D: ?Sized + TrainingData<S>,
+    {
+        let mut counts = TimeCounts::new();
+        let mut at_risk = 0.0;
+        data.visit_samples(|sample| {
+            add_observation(&mut counts, &sample.target(), sample.weight());
+            at_risk += sample.weight();
+        });
+
+        let mut curve = SurvivalCurve::default();
+        let mut s = 1.0;
+        let mut h = 0.0;
+        for (&key, &(d, n)) in &counts {
+            if d > 0.0 && at_risk > 0.0 {
+                s *= 1.0 - d / at_risk;
+                h += d / at_risk;
+                curve.times.push(key_time(key));
+                curve.survival.push(s);
+                curve.cumulative_hazard.push(h);
+            }
+            at_risk -= n;
+        }
+        curve
+    }
+
+    /// Times at which the curve steps, in increasing order
+    pub fn times(&self) -> &[f64] {
+        &self.times
+    }
+
+    /// Estimated probability that the event has not happened by time `t`
+    pub fn survival(&self, t: f64) -> f64 {
+        match self.step(t) {
+            Some(i) => self.survival[i],
+            None => 1.0,
+        }
+    }
+
+    /// Estimated cumulative hazard at time `t`
+    pub fn cumulative_hazard(&self, t: f64) -> f64 {
+        match self.step(t) {
+            Some(i) => self.cumulative_hazard[i],
+            None => 0.0,
+        }
+    }
+
+    /// Index of the last step at or before `t`
+    fn step(&self, t: f64) -> Option<usize> {
+        // never finds an exact match, so the error is the number of steps at or before `t`
+        let n = match self.times.binary_search_by(|&ti| if ti > t { Ordering::Greater } else { Ordering::Less }) {
+            Ok(i) | Err(i) => i,
+        };
+        n.checked_sub(1)
+    }
+
+    fn mean_of<'a, I: Iterator<Item=&'a SurvivalCurve>>(iter: I) -> Self {
+        let curves: Vec<_> = iter.collect();
+        let mut times: Vec<f64> = curves.iter().flat_map(|c| c.times.iter().cloned()).collect();
+        times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        times.dedup();
+
+        let n = curves.len() as f64;
+        let survival = times.iter()
+            .map(|&t| curves.iter().map(|c| c.survival(t)).sum::<f64>() / n)
+            .collect();
+        let cumulative_hazard = times.iter()
+            .map(|&t| curves.iter().map(|c| c.cumulative_hazard(t)).sum::<f64>() / n)
+            .collect();
+
+        SurvivalCurve {
+            times,
+            survival,
+            cumulative_hazard,
+        }
+    }
+}
+
+/// Point-wise mean of the curves
+impl IterMean for SurvivalCurve {
+    fn mean<I: ExactSizeIterator<Item=SurvivalCurve>>(iter: I) -> Self {
+        let curves: Vec<_> = iter.collect();
+        Self::mean_of(curves.iter())
+    }
+}
+
+impl<'a> IterMean<&'a SurvivalCurve> for SurvivalCurve {
+    fn mean<I: ExactSizeIterator<Item=&'a SurvivalCurve>>(iter: I) -> Self {
+        Self::mean_of(iter)
+    }
+}
+
+/// Harrell's concordance index of predicted risks (e.g. the cumulative hazard at a fixed time).
+///
+/// Of all pairs of samples whose order of events is known (the earlier time is an event), this
+/// is the fraction where the sample with the earlier event has the higher risk. Ties in risk
+/// count as one half. Returns NaN if there are no such pairs.
+pub fn concordance_index(risk: &[f64], targets: &[Survival]) -> f64 {
+    assert_eq!(risk.len(), targets.len());
+    let mut concordant = 0.0;
+    let mut comparable = 0.0;
+    for (i, ti) in targets.iter().enumerate() {
+        if !ti.event {
+            continue
+        }
+        for (j, tj) in targets.iter().enumerate() {
+            if ti.time < tj.time {
+                comparable += 1.0;
+                if risk[i] > risk[j] {
+                    concordant += 1.0;
+                } else if risk[i] == risk[j] {
+                    concordant += 0.5;
+                }
+            }
+        }
+    }
+    concordant / comparable
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use data::feature_bounds_of;
+    use dforest::DeterministicForestBuilder;
+    use dtree::DeterministicTreeBuilder;
+    use split::BestRandomSplit;
+
+    #[derive(Debug, Clone)]
+    struct Patient {
+        x: f64,
+        y: Survival,
+    }
+
+    impl SampleDescription for Patient {
+        type ThetaSplit = ();
+        type ThetaLeaf = SurvivalCurve;
+        type Feature = f64;
+        type Target = Survival;
+        type Prediction = SurvivalCurve;
+
+        fn target(&self) -> Survival { self.y }
+        fn sample_as_split_feature(&self, _: &()) -> f64 { self.x }
+        fn sample_predict(&self, w: &SurvivalCurve) -> SurvivalCurve { w.clone() }
+    }
+
+    impl TrainingData<Patient> for [Patient] {
+        type Criterion = LogRankCriterion;
+
+        fn n_samples(&self) -> usize { self.len() }
+        fn gen_split_feature<R: Rng>(&self, _: &mut R) {}
+        fn train_leaf_predictor(&self) -> SurvivalCurve { SurvivalCurve::from_dataset(self) }
+
+        fn feature_bounds(&self, theta: &()) -> (f64, f64) {
+            feature_bounds_of(self, theta)
+        }
+    }
+
+    fn patient(time: f64, event: bool) -> Patient {
+        Patient { x: 0.0, y: Survival { time, event } }
+    }
+
+    #[test]
+    fn log_rank() {
+        let mut left = LogRankCriterion::new();
+        let mut right = LogRankCriterion::new();
+        left.add_sample(&patient(1.0, true));
+        left.add_sample(&patient(2.0, true));
+        right.add_sample(&patient(3.0, true));
+        right.add_sample(&patient(4.0, true));
+        assert!((LogRankCriterion::statistic(&left, &right) - 2.882353).abs() < 1e-6);
+        assert!((LogRankCriterion::statistic(&right, &left) - 2.882353).abs() < 1e-6);
+
+        // censoring only shrinks the risk sets
+        right.add_sample(&patient(5.0, false));
+        right.remove_sample(&patient(5.0, false));
+        assert!((LogRankCriterion::statistic(&left, &right) - 2.882353).abs() < 1e-6);
+
+        left.remove_sample(&patient(2.0, true));
+        assert_eq!(left.get(), 0.0);
+        assert_eq!(right.get(), 1.0);
+    }
+
+    #[test]
+    fn survival_curve() {
+        let data = [
+            patient(1.0, true),
+            patient(2.0, false),
+            patient(3.0, true),
+            patient(3.0, true),
+            patient(5.0, false),
+        ];
+        let curve = SurvivalCurve::from_dataset(&data[..]);
+        assert_eq!(curve.times(), &[1.0, 3.0]);
+        assert_eq!(curve.survival(0.5), 1.0);
+        assert_eq!(curve.survival(1.0), 0.8);
+        assert!((curve.survival(4.0) - 0.8 / 3.0).abs() < 1e-12);
+        assert_eq!(curve.cumulative_hazard(2.0), 0.2);
+        assert!((curve.cumulative_hazard(10.0) - (0.2 + 2.0 / 3.0)).abs() < 1e-12);
+
+        let mean = SurvivalCurve::mean(vec![curve.clone(), SurvivalCurve::from_dataset(&data[..1])].into_iter());
+        assert_eq!(mean.times(), &[1.0, 3.0]);
+        assert_eq!(mean.survival(1.0), 0.4);
+        assert_eq!(mean.cumulative_hazard(1.0), 0.6);
+
+        // -0.0 is the same time as 0.0, and negative times come first
+        let curve = SurvivalCurve::from_dataset(&[
+            patient(1.0, true), patient(-0.0, true), patient(0.0, true), patient(-2.0, true), patient(-0.5, true),
+        ][..]);
+        assert_eq!(curve.times(), &[-2.0, -0.5, 0.0, 1.0]);
+        assert_eq!(curve.survival(-3.0), 1.0);
+        assert_eq!(curve.survival(-1.0), 0.8);
+        assert!((curve.survival(0.0) - 0.2).abs() < 1e-12);
+        assert_eq!(curve.survival(1.0), 0.0);
+    }
+
+    #[test]
+    fn survival_forest() {
+        // high x dies early, censoring at time 30
+        let data: &mut Vec<_> = &mut (0..60)
+            .map(|i| {
+                let x = i as f64;
+                let time = if i < 30 { 40.0 - (i % 10) as f64 } else { 10.0 - (i % 10) as f64 / 2.0 };
+                Patient { x, y: Survival { time: time.min(30.0), event: time < 30.0 } }
+            })
+            .collect();
+
+        let forest = DeterministicForestBuilder::new(
+            20,
+            DeterministicTreeBuilder::new(10, BestRandomSplit::new(5)).with_bootstrap(60)
+        ).with_seed(7).fit(&mut data[..]);
+
+        let low = forest.predict(&Patient { x: 5.0, y: Survival::censored(0.0) });
+        let high = forest.predict(&Patient { x: 50.0, y: Survival::censored(0.0) });
+        assert!(high.survival(10.0) < low.survival(10.0));
+        assert!(high.cumulative_hazard(10.0) > low.cumulative_hazard(10.0));
+
+        let risk: Vec<f64> = data.iter().map(|p| forest.predict(p).cumulative_hazard(10.0)).collect();
+        let targets: Vec<Survival> = data.iter().map(|p| p.y).collect();
+        assert!(concordance_index(&risk, &targets) > 0.8);
+    }
+
+    #[test]
+    fn c_index() {
+        let targets = [Survival::event(1.0), Survival::censored(2.0), Survival::event(3.0)];
+        assert_eq!(concordance_index(&[3.0, 2.0, 1.0], &targets), 1.0);
+        assert_eq!(concordance_index(&[1.0, 2.0, 3.0], &targets), 0.0);
+        assert_eq!(concordance_index(&[1.0, 1.0, 1.0], &targets), 0.5);
+        assert!(concordance_index(&[1.0], &[Survival::censored(1.0)]).is_nan());
+    }
+}
@@ -1,11 +1,13 @@
 
 use num_traits::Bounded;
 use rand::distributions::range::SampleRange;
-use rand::{thread_rng, Rng};
+use rand::Rng;
 
-use criterion::VarianceCriterion;
-use data::{SampleDescription, TrainingData};
+use categorical::{CatCount, Category, GenericCatCounter};
+use criterion::{GiniCriterion, MultiLabelGiniCriterion, MultiOutputVarianceCriterion, VarianceCriterion};
+use data::{feature_bounds_of, SampleDescription, TrainingData};
 use iter_mean::IterMean;
+use multilabel::{LabelProbabilities, LabelSet};
 use split_between::SplitBetween;
 
 #[derive(Debug, Clone)]
@@ -54,8 +56,8 @@ impl<'a, X> TrainingData<Sample<'a, X, f64>> for [Sample<'a, X, f64>]
         self.len()
     }
 
-    fn gen_split_feature(&self) -> usize {
-        thread_rng().gen_range(0, self[0].x.len())
+    fn gen_split_feature<R: Rng>(&self, rng: &mut R) -> usize {
+        rng.gen_range(0, self[0].x.len())
     }
 
     fn all_split_features(&self) -> Option<Box<Iterator<Item=usize>>> {
@@ -67,12 +69,337 @@ impl<'a, X> TrainingData<Sample<'a, X, f64>> for [Sample<'a, X, f64>]
     }
 
     fn feature_bounds(&self, theta: &usize) -> (X, X) {
-        self.iter()
-            .map(|sample| sample.sample_as_split_feature(theta))
-            .fold((X::max_value(), X::min_value()),
-                  |(min, max), x| {
-                      (if x < min {x.clone()} else {min},
-                       if x > max {x} else {max})
-                  })
-    }
-}
\ No newline at end of file
+        feature_bounds_of(self, theta)
+    }
+}
+
+/// Sample with a vector of targets
+#[derive(Debug, Clone)]
+pub struct VecSample<'a, X: 'a> {
+    pub x: &'a[X],
+    pub y: Vec<f64>,
+}
+
+impl<'a, X: 'a> VecSample<'a, X> {
+    pub fn new(x: &'a[X], y: Vec<f64>) -> Self {
+        VecSample {
+            x,
+            y,
+        }
+    }
+}
+
+impl<'a, X> SampleDescription for VecSample<'a, X>
+    where X: Clone + PartialOrd + SampleRange + Bounded + SplitBetween
+{
+    type ThetaSplit = usize;
+    type ThetaLeaf = Vec<f64>;
+    type Feature = X;
+    type Target = Vec<f64>;
+    type Prediction = Vec<f64>;
+
+    fn target(&self) -> Self::Target {
+        self.y.clone()
+    }
+
+    fn sample_as_split_feature(&self, theta: &Self::ThetaSplit) -> Self::Feature {
+        self.x[*theta].clone()
+    }
+
+    fn sample_predict(&self, w: &Self::ThetaLeaf) -> Self::Prediction {
+        w.clone()
+    }
+}
+
+impl<'a, X> TrainingData<VecSample<'a, X>> for [VecSample<'a, X>]
+    where X: Clone + PartialOrd + SampleRange + Bounded + SplitBetween
+{
+    type Criterion = MultiOutputVarianceCriterion;
+
+    fn n_samples(&self) -> usize {
+        self.len()
+    }
+
+    fn gen_split_feature<R: Rng>(&self, rng: &mut R) -> usize {
+        rng.gen_range(0, self[0].x.len())
+    }
+
+    fn all_split_features(&self) -> Option<Box<Iterator<Item=usize>>> {
+        Some(Box::new(0..self[0].x.len()))
+    }
+
+    fn train_leaf_predictor(&self) -> Vec<f64> {
+        Vec::<f64>::mean(self.iter().map(|sample| &sample.y))
+    }
+
+    fn feature_bounds(&self, theta: &usize) -> (X, X) {
+        feature_bounds_of(self, theta)
+    }
+}
+
+/// Sample with a set of labels as target
+#[derive(Debug, Clone)]
+pub struct TagSample<'a, X: 'a> {
+    pub x: &'a[X],
+    pub y: LabelSet,
+}
+
+impl<'a, X: 'a> TagSample<'a, X> {
+    pub fn new(x: &'a[X], y: LabelSet) -> Self {
+        TagSample {
+            x,
+            y,
+        }
+    }
+}
+
+impl<'a, X> SampleDescription for TagSample<'a, X>
+    where X: Clone + PartialOrd + SampleRange + Bounded + SplitBetween
+{
+    type ThetaSplit = usize;
+    type ThetaLeaf = LabelProbabilities;
+    type Feature = X;
+    type Target = LabelSet;
+    type Prediction = LabelProbabilities;
+
+    fn target(&self) -> Self::Target {
+        self.y.clone()
+    }
+
+    fn sample_as_split_feature(&self, theta: &Self::ThetaSplit) -> Self::Feature {
+        self.x[*theta].clone()
+    }
+
+    fn sample_predict(&self, w: &Self::ThetaLeaf) -> Self::Prediction {
+        w.clone()
+    }
+}
+
+impl<'a, X> TrainingData<TagSample<'a, X>> for [TagSample<'a, X>]
+    where X: Clone + PartialOrd + SampleRange + Bounded + SplitBetween
+{
+    type Criterion = MultiLabelGiniCriterion;
+
+    fn n_samples(&self) -> usize {
+        self.len()
+    }
+
+    fn gen_split_feature<R: Rng>(&self, rng: &mut R) -> usize {
+        rng.gen_range(0, self[0].x.len())
+    }
+
+    fn all_split_features(&self) -> Option<Box<Iterator<Item=usize>>> {
+        Some(Box::new(0..self[0].x.len()))
+    }
+
+    fn train_leaf_predictor(&self) -> LabelProbabilities {
+        LabelProbabilities::from_dataset(self)
+    }
+
+    fn feature_bounds(&self, theta: &usize) -> (X, X) {
+        feature_bounds_of(self, theta)
+    }
+}
+
+/// Sample with a class label as target
+#[derive(Debug, Clone)]
+pub struct ClassSample<'a, X: 'a> {
+    pub x: &'a[X],
+    pub y: u8,
+}
+
+impl<'a, X: 'a> ClassSample<'a, X> {
+    pub fn new(x: &'a[X], y: u8) -> Self {
+        ClassSample {
+            x,
+            y,
+        }
+    }
+}
+
+impl<'a, X> SampleDescription for ClassSample<'a, X>
+    where X: Clone + PartialOrd + SampleRange + Bounded + SplitBetween
+{
+    type ThetaSplit = usize;
+    type ThetaLeaf = u8;
+    type Feature = X;
+    type Target = u8;
+    type Prediction = u8;
+
+    fn target(&self) -> Self::Target {
+        self.y
+    }
+
+    fn sample_as_split_feature(&self, theta: &Self::ThetaSplit) -> Self::Feature {
+        self.x[*theta].clone()
+    }
+
+    fn sample_predict(&self, w: &Self::ThetaLeaf) -> Self::Prediction {
+        *w
+    }
+}
+
+impl<'a, X> TrainingData<ClassSample<'a, X>> for [ClassSample<'a, X>]
+    where X: Clone + PartialOrd + SampleRange + Bounded + SplitBetween
+{
+    type Criterion = GiniCriterion;
+
+    fn n_samples(&self) -> usize {
+        self.len()
+    }
+
+    fn gen_split_feature<R: Rng>(&self, rng: &mut R) -> usize {
+        rng.gen_range(0, self[0].x.len())
+    }
+
+    fn all_split_features(&self) -> Option<Box<Iterator<Item=usize>>> {
+        Some(Box::new(0..self[0].x.len()))
+    }
+
+    fn train_leaf_predictor(&self) -> u8 {
+        let mut counts = GenericCatCounter::new();
+        for sample in self {
+            counts.add(sample.y);
+        }
+        counts.most_frequent()
+    }
+
+    fn feature_bounds(&self, theta: &usize) -> (X, X) {
+        feature_bounds_of(self, theta)
+    }
+}
+
+/// Sample with a categorical feature (`theta = 0`) and an ordered feature (`theta = 1`)
+#[derive(Debug, Clone)]
+pub struct ColorSample<Y> {
+    pub color: Option<usize>,
+    pub x: f64,
+    pub y: Y,
+}
+
+impl<Y> ColorSample<Y> {
+    pub fn new(color: usize, x: f64, y: Y) -> Self {
+        ColorSample {
+            color: Some(color),
+            x,
+            y,
+        }
+    }
+
+    /// Sample whose color is missing
+    pub fn without_color(x: f64, y: Y) -> Self {
+        ColorSample {
+            color: None,
+            x,
+            y,
+        }
+    }
+
+    fn feature(&self, theta: usize) -> f64 {
+        if theta == 0 { self.color.map_or(f64::NAN, |c| c as f64) } else { self.x }
+    }
+
+    fn category(&self, theta: usize) -> Option<Category> {
+        if theta == 0 { Some(self.color.map_or(Category::missing(), Category::of)) } else { None }
+    }
+}
+
+impl SampleDescription for ColorSample<f64> {
+    type ThetaSplit = usize;
+    type ThetaLeaf = f64;
+    type Feature = f64;
+    type Target = f64;
+    type Prediction = f64;
+
+    fn target(&self) -> f64 {
+        self.y
+    }
+
+    fn sample_as_split_feature(&self, theta: &usize) -> f64 {
+        self.feature(*theta)
+    }
+
+    fn sample_as_category(&self, theta: &usize) -> Option<Category> {
+        self.category(*theta)
+    }
+
+    fn sample_predict(&self, w: &f64) -> f64 {
+        *w
+    }
+}
+
+impl TrainingData<ColorSample<f64>> for [ColorSample<f64>] {
+    type Criterion = VarianceCriterion;
+
+    fn n_samples(&self) -> usize {
+        self.len()
+    }
+
+    fn gen_split_feature<R: Rng>(&self, rng: &mut R) -> usize {
+        rng.gen_range(0, 2)
+    }
+
+    fn all_split_features(&self) -> Option<Box<Iterator<Item=usize>>> {
+        Some(Box::new(0..2))
+    }
+
+    fn train_leaf_predictor(&self) -> f64 {
+        f64::mean(self.iter().map(|sample| &sample.y))
+    }
+
+    fn feature_bounds(&self, theta: &usize) -> (f64, f64) {
+        feature_bounds_of(self, theta)
+    }
+}
+
+impl SampleDescription for ColorSample<u8> {
+    type ThetaSplit = usize;
+    type ThetaLeaf = u8;
+    type Feature = f64;
+    type Target = u8;
+    type Prediction = u8;
+
+    fn target(&self) -> u8 {
+        self.y
+    }
+
+    fn sample_as_split_feature(&self, theta: &usize) -> f64 {
+        self.feature(*theta)
+    }
+
+    fn sample_as_category(&self, theta: &usize) -> Option<Category> {
+        self.category(*theta)
+    }
+
+    fn sample_predict(&self, w: &u8) -> u8 {
+        *w
+    }
+}
+
+impl TrainingData<ColorSample<u8>> for [ColorSample<u8>] {
+    type Criterion = GiniCriterion;
+
+    fn n_samples(&self) -> usize {
+        self.len()
+    }
+
+    fn gen_split_feature<R: Rng>(&self, rng: &mut R) -> usize {
+        rng.gen_range(0, 2)
+    }
+
+    fn all_split_features(&self) -> Option<Box<Iterator<Item=usize>>> {
+        Some(Box::new(0..2))
+    }
+
+    fn train_leaf_predictor(&self) -> u8 {
+        let mut counts = GenericCatCounter::new();
+        for sample in self {
+            counts.add(sample.y);
+        }
+        counts.most_frequent()
+    }
+
+    fn feature_bounds(&self, theta: &usize) -> (f64, f64) {
+        feature_bounds_of(self, theta)
+    }
+}
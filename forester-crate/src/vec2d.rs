@@ -32,7 +32,7 @@ use std::ops;
 use std::slice;
 
 /// A row-major contiguous two-dimensional array.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Vec2D<T> {
     data: Vec<T>,
     n_columns: usize,
@@ -117,6 +117,12 @@ impl<T> ops::Index<(usize, usize)> for Vec2D<T> {
     }
 }
 
+impl<T> ops::IndexMut<(usize, usize)> for Vec2D<T> {
+    fn index_mut(&mut self, (r, c): (usize, usize)) -> &mut Self::Output {
+        &mut self.data[r * self.n_columns + c]
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -181,5 +187,10 @@ mod tests {
         assert_eq!(x[(2, 2)], 9);
         assert_eq!(x[(0, 2)], 3);
         assert_eq!(x[(3, 0)], 10);
+
+        let mut y = x.clone();
+        y[(1, 2)] = 42;
+        assert_eq!(y[1], [4, 5, 42]);
+        assert_eq!(x[1], [4, 5, 6]);
     }
 }
@@ -7,7 +7,7 @@ extern crate rand;
 use std::f32;
 use std::fmt;
 
-use rand::{thread_rng, Rng};
+use rand::Rng;
 use openml::MeasureAccumulator;
 
 use forester::criterion::VarianceCriterion;
@@ -44,7 +44,7 @@ impl<'a> SampleDescription for Sample<'a> {
         // We use the data columns directly as features
         match self.x[*theta] {
             Some(x) => x,
-            None => -1.0,  // encode missing values as -1.0... this is pretty naive :)
+            None => std::f32::NAN,  // missing values are sent down the side learned by each split
         }
     }
 
@@ -60,9 +60,9 @@ impl<'a> TrainingData<Sample<'a>> for [Sample<'a>] {
         self.len()
     }
 
-    fn gen_split_feature(&self) -> usize {
+    fn gen_split_feature<R: Rng>(&self, rng: &mut R) -> usize {
         // The data set has four feature columns
-        thread_rng().gen_range(0, self[0].x.len())
+        rng.gen_range(0, self[0].x.len())
     }
 
     fn all_split_features(&self) -> Option<Box<Iterator<Item=usize>>> {
@@ -118,7 +118,12 @@ fn main() {
                 2,
                 BestSplit::new()
             ).with_bootstrap(100)
-        ).fit(&mut train as &mut [_]);
+        ).with_oob_score().fit(&mut train as &mut [_]);
+
+        // The out-of-bag error estimates the generalization error without a validation set
+        if let Some(mse) = forest.oob_score(|&p, &y| ((p - y) * (p - y)) as f64) {
+            println!("OOB RMSE: {}", mse.sqrt());
+        }
 
         println!("Predicting...");
         let result: Vec<_> = test.map(|x| {
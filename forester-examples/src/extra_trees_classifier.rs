@@ -50,8 +50,8 @@ impl TrainingData<Sample<Classes>> for [Sample<Classes>] {
         self.len()
     }
 
-    fn gen_split_feature(&self) -> usize {
-        thread_rng().gen_range(0, 2)
+    fn gen_split_feature<R: Rng>(&self, rng: &mut R) -> usize {
+        rng.gen_range(0, 2)
     }
 
     fn train_leaf_predictor(&self) -> ClassCounts {
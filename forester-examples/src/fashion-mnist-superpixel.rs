@@ -21,7 +21,7 @@ extern crate rand;
 #[macro_use]
 extern crate serde_derive;
 
-use rand::{thread_rng, Rng};
+use rand::Rng;
 use openml::MeasureAccumulator;
 
 use forester::categorical::CatCount;
@@ -150,9 +150,7 @@ impl<'a> TrainingData<Sample<'a>> for [Sample<'a>] {
     /// The current implementation simply generates two random points and defines the rectangle they
     /// span as the super-pixel feature. This favors the central regions of the image. Since the
     /// central region is probably more interesting than the borders anyway I'll leave it at that.
-    fn gen_split_feature(&self) -> SuperPixel {
-        let mut rng = thread_rng();
-
+    fn gen_split_feature<R: Rng>(&self, rng: &mut R) -> SuperPixel {
         let a = rng.gen_range(0, 28);
         let b = rng.gen_range(0, 28);
         let c = rng.gen_range(0, 28);
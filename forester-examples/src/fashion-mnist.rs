@@ -5,7 +5,7 @@ extern crate rand;
 #[macro_use]
 extern crate serde_derive;
 
-use rand::{thread_rng, Rng};
+use rand::Rng;
 use openml::MeasureAccumulator;
 
 use forester::categorical::CatCount;
@@ -63,9 +63,9 @@ impl<'a> TrainingData<Sample<'a>> for [Sample<'a>] {
         self.len()
     }
 
-    fn gen_split_feature(&self) -> usize {
+    fn gen_split_feature<R: Rng>(&self, rng: &mut R) -> usize {
         // The data set has four feature columns
-        thread_rng().gen_range(0, 784)
+        rng.gen_range(0, 784)
     }
 
     fn all_split_features(&self) -> Option<Box<Iterator<Item=usize>>> {
@@ -9,7 +9,7 @@ extern crate serde_derive;
 use std::fmt;
 
 use num_traits::ToPrimitive;
-use rand::{thread_rng, Rng};
+use rand::Rng;
 use openml::MeasureAccumulator;
 
 use forester::categorical::{Categorical, CatCount};
@@ -129,9 +129,9 @@ impl<'a> TrainingData<Sample<'a>> for [Sample<'a>] {
         self.len()
     }
 
-    fn gen_split_feature(&self) -> usize {
+    fn gen_split_feature<R: Rng>(&self, rng: &mut R) -> usize {
         // The data set has four feature columns
-        thread_rng().gen_range(0, 4)
+        rng.gen_range(0, 4)
     }
 
     fn all_split_features(&self) -> Option<Box<Iterator<Item=usize>>> {
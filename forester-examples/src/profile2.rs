@@ -5,7 +5,7 @@ extern crate rand;
 
 use std::fmt;
 
-use rand::{thread_rng, Rng};
+use rand::Rng;
 
 use forester::criterion::GiniCriterion;
 use forester::data::{SampleDescription, TrainingData};
@@ -61,9 +61,9 @@ impl<'a> TrainingData<Sample<'a>> for [Sample<'a>] {
         self.len()
     }
 
-    fn gen_split_feature(&self) -> usize {
+    fn gen_split_feature<R: Rng>(&self, rng: &mut R) -> usize {
         // The data set has four feature columns
-        thread_rng().gen_range(0, 28*28)
+        rng.gen_range(0, 28*28)
     }
 
     fn train_leaf_predictor(&self) -> ClassCounts {
@@ -51,8 +51,8 @@ impl TrainingData<Sample<Classes>> for [Sample<Classes>]
         self.len()
     }
 
-    fn gen_split_feature(&self) -> (f64, f64) {
-        let a: f64 = thread_rng().gen::<f64>() * PI;
+    fn gen_split_feature<R: Rng>(&self, rng: &mut R) -> (f64, f64) {
+        let a: f64 = rng.gen::<f64>() * PI;
         (a.sin(), a.cos())
     }
 